@@ -1,6 +1,8 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 
-use pmd_mask::{Masks, apply_pmd_mask};
+use pmd_mask::{Masks, MaskOptions, apply_pmd_mask};
+use pmd_mask::genome::LibraryType;
+use pmd_mask::reference::ReferenceCache;
 
 
 use rust_htslib::bam::{Read, Header, Format};
@@ -23,19 +25,20 @@ fn bench_apply_pmd_mask(c: &mut Criterion) {
     let mut fixture = black_box(NamedTempFile::new("output.bam").expect("Failed to create fixture for output bam"));    
     let mut writer  = black_box(BamWriter::from_path(&mut fixture, &Header::from_template(bam.header()), Format::Sam).expect("Can't open Sam Writer"));
 
-    let reference   = black_box(FaReader::from_path(test_dir!(fa "/hs37d5-MTonly/hs37d5-MTonly.fa.gz")).expect("Can't open reference file"));
+    let fasta         = black_box(FaReader::from_path(test_dir!(fa "/hs37d5-MTonly/hs37d5-MTonly.fa.gz")).expect("Can't open reference file"));
+    let mut reference = black_box(ReferenceCache::new(fasta, 1).expect("Can't cache reference file"));
 
 
     let mut bench_masks = Vec::new();
     for threshold in [0.0, 0.01, 0.05, 0.5, 1.0] {
-        let masks       = black_box(Masks::from_path(test_dir!(bam "misincorporation.txt"), black_box(threshold)).expect("Failed to open misincorporation file"));
+        let masks       = black_box(Masks::from_path(test_dir!(bam "misincorporation.txt"), black_box(threshold), LibraryType::DoubleStranded, Default::default()).expect("Failed to open misincorporation file"));
         bench_masks.push((threshold, masks));
     }
 
     for (threshold, masks) in bench_masks {
         let bench_name = format!("apply_pmd_mask-{threshold}");
         c.bench_function(&bench_name, |bench| bench.iter(|| {        
-            apply_pmd_mask(&mut bam, &reference, &masks, &mut writer).unwrap();
+            apply_pmd_mask(&mut bam, &mut reference, &masks, &MaskOptions::default(), 1, &mut writer).unwrap();
         }));
     }
 
@@ -0,0 +1,67 @@
+use rust_htslib::bam::{self, record::{Aux, AuxArray}};
+
+/// Aux tag listing the read offsets which would have been masked (`B:I` array, 0-based, in the orientation of the 
+/// stored sequence, sorted in ascending order). See [`annotate()`].
+pub const OFFSETS_TAG: &[u8; 2] = b"ZO";
+
+/// Aux tag counting the number of read offsets which would have been masked (`i`). See [`annotate()`].
+pub const COUNT_TAG: &[u8; 2] = b"ZC";
+
+/// Annotate a record with the read offsets which would have been masked, without altering its sequence or base 
+/// qualities. Offsets are sorted, deduplicated, and stored within the [`OFFSETS_TAG`] aux field, while their number is
+/// stored within [`COUNT_TAG`]. Pre-existing values of these tags are overwritten.
+/// 
+/// Note that [`OFFSETS_TAG`] is omitted when no offset would have been masked, while [`COUNT_TAG`] is always set.
+/// 
+/// # Errors
+/// Returns a [`rust_htslib::errors::Error`] if htslib fails to append either aux field to the record.
+/// 
+/// # Usage
+/// ```
+/// use rust_htslib::bam::{self, record::Aux};
+/// use pmd_mask::annotate::{annotate, OFFSETS_TAG, COUNT_TAG};
+/// 
+/// let mut record = bam::Record::new();
+/// record.set(b"read", None, b"TCGATA", &[37; 6]);
+/// annotate(&mut record, &[4, 0, 4]).expect("Failed to annotate record");
+/// 
+/// assert_eq!(record.aux(COUNT_TAG).ok(), Some(Aux::I32(2)));
+/// match record.aux(OFFSETS_TAG) {
+///     Ok(Aux::ArrayU32(offsets)) => assert_eq!(offsets.iter().collect::<Vec<_>>(), vec![0, 4]),
+///     other                      => panic!("Unexpected aux field: {other:?}"),
+/// }
+/// assert_eq!(record.seq().as_bytes(), b"TCGATA");
+/// ```
+pub fn annotate(record: &mut bam::Record, offsets: &[usize]) -> Result<(), rust_htslib::errors::Error> {
+    let mut offsets = offsets.iter().map(|offset| *offset as u32).collect::<Vec<u32>>();
+    offsets.sort_unstable();
+    offsets.dedup();
+
+    for tag in [OFFSETS_TAG, COUNT_TAG] {
+        if record.aux(tag).is_ok() {
+            record.remove_aux(tag)?;
+        }
+    }
+
+    if !offsets.is_empty() {
+        record.push_aux(OFFSETS_TAG, Aux::ArrayU32(AuxArray::from(&offsets)))?;
+    }
+    record.push_aux(COUNT_TAG, Aux::I32(offsets.len() as i32))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn annotate_overwrites_previous_tags() {
+        let mut record = bam::Record::new();
+        record.set(b"read", None, b"TCGATA", &[37; 6]);
+        annotate(&mut record, &[1, 2, 3]).expect("Failed to annotate record");
+        annotate(&mut record, &[]).expect("Failed to re-annotate record");
+
+        assert_eq!(record.aux(COUNT_TAG).ok(), Some(Aux::I32(0)));
+        assert!(record.aux(OFFSETS_TAG).is_err());
+        assert_eq!(record.aux_iter().count(), 1);
+    }
+}
@@ -0,0 +1,165 @@
+use rust_htslib::bam::record::{Cigar, CigarString};
+
+/// Soft-clip the first `clip_5p` and last `clip_3p` read nucleotides of an alignment, and return the rewritten
+/// [`CigarString`], along with the number of reference positions the alignment start should be shifted by.
+/// 
+/// Note that `clip_5p` and `clip_3p` are defined in read nucleotides, starting from either end of the stored sequence,
+/// and thus include any pre-existing soft-clip. Hard-clips are preserved, while deletions, reference skips and
+/// insertions found at the new boundaries of the alignment are respectively dropped and absorbed within the soft-clip.
+/// 
+/// Returns [`None`] if soft-clipping would leave no aligned nucleotide within the read.
+/// 
+/// # Usage
+/// ```
+/// use rust_htslib::bam::record::{Cigar::*, CigarString};
+/// use pmd_mask::clip::soft_clip;
+/// 
+/// let cigar = CigarString(vec![Match(10), Del(2), Match(10)]);
+/// let (clipped, shift) = soft_clip(&cigar, 10, 3).expect("Nothing left to align");
+/// assert_eq!(clipped, CigarString(vec![SoftClip(10), Match(7), SoftClip(3)]));
+/// assert_eq!(shift, 12);
+/// ```
+pub fn soft_clip(cigar: &CigarString, clip_5p: usize, clip_3p: usize) -> Option<(CigarString, usize)> {
+    let (clipped, ref_shift) = clip_leading(cigar.0.iter().copied(), clip_5p);
+    let (mut clipped, _)     = clip_leading(clipped.into_iter().rev(), clip_3p);
+    clipped.reverse();
+
+    if !clipped.iter().any(|op| matches!(op, Cigar::Match(_) | Cigar::Equal(_) | Cigar::Diff(_))) {
+        return None
+    }
+    Some((CigarString(clipped), ref_shift))
+}
+
+/// Soft-clip the first `n` read nucleotides of a sequence of [`Cigar`] operations, and return the rewritten 
+/// operations, along with the number of reference positions consumed by the clipped region. See [`soft_clip`].
+fn clip_leading(ops: impl Iterator<Item = Cigar>, n: usize) -> (Vec<Cigar>, usize) {
+    let mut clipped    = Vec::new();
+    let mut hard_clips = Vec::new();
+    let mut remaining  = n;
+    let mut soft_clip  = 0;
+    let mut ref_shift  = 0;
+    let mut ops        = ops.peekable();
+
+    // ---- Consume operations until the requested number of read nucleotides has been clipped.
+    while let Some(op) = ops.next_if(|_| remaining > 0) {
+        match op {
+            Cigar::HardClip(len) => hard_clips.push(Cigar::HardClip(len)),
+            Cigar::SoftClip(len) | Cigar::Ins(len) => {
+                let len = len as usize;
+                soft_clip += len;
+                remaining = remaining.saturating_sub(len);
+            },
+            Cigar::Match(len) | Cigar::Equal(len) | Cigar::Diff(len) => {
+                let consumed = remaining.min(len as usize);
+                soft_clip += consumed;
+                ref_shift += consumed;
+                remaining -= consumed;
+                if consumed < len as usize {
+                    let leftover = len - consumed as u32;
+                    clipped.push(match op {
+                        Cigar::Equal(_) => Cigar::Equal(leftover),
+                        Cigar::Diff(_)  => Cigar::Diff(leftover),
+                        _               => Cigar::Match(leftover),
+                    });
+                }
+            },
+            Cigar::Del(len) | Cigar::RefSkip(len) => ref_shift += len as usize,
+            Cigar::Pad(_) => {},
+        }
+    }
+
+    // ---- Ensure the alignment does not start with an indel, once clipped.
+    if n > 0 && clipped.is_empty() {
+        while let Some(op) = ops.next_if(|op| matches!(op, Cigar::Del(_) | Cigar::RefSkip(_) | Cigar::Ins(_) | Cigar::Pad(_))) {
+            match op {
+                Cigar::Ins(len)                         => soft_clip += len as usize,
+                Cigar::Del(len) | Cigar::RefSkip(len)   => ref_shift += len as usize,
+                _                                       => {},
+            }
+        }
+    }
+
+    let mut out = hard_clips;
+    if soft_clip > 0 {
+        out.push(Cigar::SoftClip(soft_clip as u32));
+    }
+    out.extend(clipped);
+    out.extend(ops);
+    (out, ref_shift)
+}
+
+/// Compute the BAI bin of an alignment spanning the zero-based, half-open reference interval `[beg, end)`.
+/// This mirrors htslib's `hts_reg2bin(beg, end, 14, 5)`.
+/// 
+/// # Usage
+/// ```
+/// use pmd_mask::clip::reg2bin;
+/// assert_eq!(reg2bin(0, 100), 4681);
+/// ```
+pub fn reg2bin(beg: i64, end: i64) -> u16 {
+    let end = end - 1;
+    let (mut level_start, mut shift) = (((1 << 15) - 1) / 7, 14);
+    while shift < 29 {
+        if beg >> shift == end >> shift {
+            return (level_start + (beg >> shift)) as u16
+        }
+        shift += 3;
+        level_start = ((1 << (29 - shift)) - 1) / 7;
+    }
+    0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use Cigar::*;
+
+    #[test]
+    fn clip_nothing() {
+        let cigar = CigarString(vec![SoftClip(2), Match(20), Ins(1), Match(5)]);
+        assert_eq!(soft_clip(&cigar, 0, 0), Some((cigar.clone(), 0)));
+    }
+
+    #[test]
+    fn clip_both_ends() {
+        let cigar = CigarString(vec![Match(30)]);
+        assert_eq!(soft_clip(&cigar, 4, 6), Some((CigarString(vec![SoftClip(4), Match(20), SoftClip(6)]), 4)));
+    }
+
+    #[test]
+    fn clip_with_existing_clips() {
+        // Pre-existing soft-clips are part of the read nucleotides to clip. Hard-clips are preserved.
+        let cigar = CigarString(vec![HardClip(5), SoftClip(2), Match(30), SoftClip(3), HardClip(1)]);
+        let want  = CigarString(vec![HardClip(5), SoftClip(4), Match(27), SoftClip(4), HardClip(1)]);
+        assert_eq!(soft_clip(&cigar, 4, 4), Some((want, 2)));
+    }
+
+    #[test]
+    fn clip_through_indels() {
+        // Insertion within the clipped region: consumes read nucleotides, but not reference positions.
+        let cigar = CigarString(vec![Match(2), Ins(2), Match(20)]);
+        assert_eq!(soft_clip(&cigar, 5, 0), Some((CigarString(vec![SoftClip(5), Match(19)]), 3)));
+
+        // Deletion at the new alignment boundary should be dropped, and the start shifted accordingly.
+        let cigar = CigarString(vec![Match(3), Del(4), Match(20)]);
+        assert_eq!(soft_clip(&cigar, 3, 0), Some((CigarString(vec![SoftClip(3), Match(20)]), 7)));
+
+        // Insertion at the new alignment boundary should be absorbed within the soft-clip.
+        let cigar = CigarString(vec![Match(20), Ins(2), Match(3)]);
+        assert_eq!(soft_clip(&cigar, 0, 3), Some((CigarString(vec![Match(20), SoftClip(5)]), 0)));
+    }
+
+    #[test]
+    fn clip_everything() {
+        let cigar = CigarString(vec![Match(10)]);
+        assert_eq!(soft_clip(&cigar, 6, 4), None);
+        assert_eq!(soft_clip(&cigar, 11, 0), None);
+    }
+
+    #[test]
+    fn bins() {
+        assert_eq!(reg2bin(0, 1), 4681);
+        assert_eq!(reg2bin(16_383, 16_385), 585);
+        assert_eq!(reg2bin(0, 1 << 29), 0);
+    }
+}
@@ -12,6 +12,21 @@ pub enum RuntimeError {
     #[error(transparent)]
     ParseMisincorporation(#[from] crate::mask::MasksError),
 
+    #[error(transparent)]
+    Reference(#[from] crate::reference::ReferenceError),
+
+    #[error(transparent)]
+    Region(#[from] crate::region::RegionError),
+
+    #[error(transparent)]
+    Panel(#[from] crate::panel::PanelError),
+
+    #[error(
+    "Failed to load the index of '{path}'. Region-restricted processing (--region, --regions-file) requires a \
+    coordinate-sorted and indexed input alignment file (see 'samtools index'). [{source}]"
+    )]
+    MissingIndex{path: std::path::PathBuf, #[source] source: rust_htslib::errors::Error},
+
     #[error("Both the output and input alignment files appears to be the same file! Exiting.")]
     InputIsOutput,
 
@@ -21,6 +36,33 @@ pub enum RuntimeError {
     #[error("Failed to write masking thresholds within the provided metrics file path. [{0}]")]
     WriteMasksMetrics(#[source] std::io::Error),
 
+    #[error("Failed to write masking statistics within the provided metrics file path. [{0}]")]
+    WriteStatsMetrics(#[source] std::io::Error),
+
+    #[error("Failed to write the JSON metrics report within the provided path. [{0}]")]
+    WriteJsonMetrics(#[source] std::io::Error),
+
+    #[error("Failed to open the requested misincorporation output file. [{0}]")]
+    OpenProfile(#[source] std::io::Error),
+
+    #[error("Failed to write the misincorporation profile. [{0}]")]
+    WriteProfile(#[source] std::io::Error),
+
+    #[error("{0} is absent from the misincorporation profile, and the 'fail' missing contig policy was requested.")]
+    MissingMaskEntry(crate::mask::MaskEntry),
+
+    #[error(
+    "The 'pooled' missing contig policy requires a misincorporation profile (either provided with --misincorporation, \
+    or estimated from the input alignment file), and cannot be used along --thresholds-file or --mask-5p/--mask-3p."
+    )]
+    MissingPooledProfile,
+
+    #[error(
+    "'--mask-mode rescale' requires a misincorporation profile (either provided with --misincorporation, or estimated \
+    from the input alignment file), and cannot be used along --thresholds-file or --mask-5p/--mask-3p."
+    )]
+    RescaleWithoutProfile,
+
     #[error("Length of the retrieved reference sequence does not match the length of the read")]
     ReferenceOutOfIndexError,
 
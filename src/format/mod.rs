@@ -0,0 +1,94 @@
+use std::path::Path;
+
+use rust_htslib::{bam, htslib};
+
+/// Detect the format of an opened alignment file, using the `htsFormat` of its underlying htslib file handle.
+/// 
+/// Returns [`None`] if the input is neither in SAM, BAM nor CRAM format.
+/// 
+/// # Usage
+/// ```
+/// use rust_htslib::bam;
+/// use pmd_mask::format::input_format;
+/// 
+/// let reader = bam::Reader::from_path("tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam").unwrap();
+/// assert!(matches!(input_format(&reader), Some(bam::Format::Bam)));
+/// ```
+pub fn input_format<B: bam::Read>(bam: &B) -> Option<bam::Format> {
+    // SAFETY: htsfile() points to the file handle of an opened reader, which lives as long as `bam`.
+    let format = unsafe { (*bam.htsfile()).format.format };
+    match format {
+        htslib::htsExactFormat_sam  => Some(bam::Format::Sam),
+        htslib::htsExactFormat_bam  => Some(bam::Format::Bam),
+        htslib::htsExactFormat_cram => Some(bam::Format::Cram),
+        _                           => None,
+    }
+}
+
+/// Infer an alignment format from the extension of a file path (`.sam`, `.bam` or `.cram`, case-insensitive). 
+/// 
+/// Returns [`None`] if the extension is missing or unknown.
+/// 
+/// # Usage
+/// ```
+/// use rust_htslib::bam;
+/// use pmd_mask::format::format_from_extension;
+/// 
+/// assert!(matches!(format_from_extension("masked.CRAM"), Some(bam::Format::Cram)));
+/// assert!(format_from_extension("masked.txt").is_none());
+/// ```
+pub fn format_from_extension(path: impl AsRef<Path>) -> Option<bam::Format> {
+    let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "sam"  => Some(bam::Format::Sam),
+        "bam"  => Some(bam::Format::Bam),
+        "cram" => Some(bam::Format::Cram),
+        _      => None,
+    }
+}
+
+/// Choose the output format of an alignment file. In order of precedence:
+/// 1. the user-`requested` format, if any.
+/// 2. the format inferred from the extension of the `output` path, if any (see [`format_from_extension`]). The 
+///    standard output has no extension, and thus skips this step.
+/// 3. the format of the `input` file (see [`input_format`]).
+/// 4. [`bam::Format::Sam`].
+/// 
+/// # Usage
+/// ```
+/// use std::path::Path;
+/// use rust_htslib::bam::Format;
+/// use pmd_mask::format::output_format;
+/// 
+/// assert!(matches!(output_format(None, Some(Path::new("out.cram")), Some(Format::Bam)), Format::Cram));
+/// assert!(matches!(output_format(None, Some(Path::new("out")), Some(Format::Bam)), Format::Bam));
+/// assert!(matches!(output_format(None, None, Some(Format::Bam)), Format::Bam));
+/// assert!(matches!(output_format(None, None, None), Format::Sam));
+/// assert!(matches!(output_format(Some(Format::Sam), Some(Path::new("out.bam")), Some(Format::Bam)), Format::Sam));
+/// ```
+pub fn output_format(requested: Option<bam::Format>, output: Option<&Path>, input: Option<bam::Format>) -> bam::Format {
+    requested
+        .or_else(|| output.and_then(format_from_extension))
+        .or(input)
+        .unwrap_or(bam::Format::Sam)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn extensions() {
+        assert!(matches!(format_from_extension("a.sam"), Some(bam::Format::Sam)));
+        assert!(matches!(format_from_extension("dir.bam/a.Bam"), Some(bam::Format::Bam)));
+        assert!(matches!(format_from_extension("a.cram"), Some(bam::Format::Cram)));
+        for invalid in ["a", "a.bam.gz", "dir.bam/a"] {
+            assert!(format_from_extension(invalid).is_none(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn fallback_to_sam() {
+        assert!(matches!(output_format(None, Some(Path::new("out.txt")), None), bam::Format::Sam));
+    }
+}
@@ -1,6 +1,6 @@
 use std::{fmt::{self, Display, Formatter}, str};
 
-use serde::Deserialize;
+use serde::{Serialize, Deserialize};
 use rust_htslib::bam::{HeaderView, Record};
 
 mod error;
@@ -15,7 +15,7 @@ pub use error::ChrNameError;
 /// let chr = ChrName::new("chrMT");
 /// assert_eq!(chr.inner(), "chrMT");
 /// ```
-#[derive(Debug, Clone, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct ChrName(String);
 
 impl ChrName {
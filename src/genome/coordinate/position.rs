@@ -1,10 +1,10 @@
 use std::fmt::{self, Display, Formatter};
-use serde::Deserialize;
+use serde::{Serialize, Deserialize};
 
 /// Absolute or relative position within a chromosome or read (in base pairs).
 /// 
 /// This is just a struct containing a [`usize`].
-#[derive(Debug, Clone, Copy, Deserialize, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Hash, PartialEq, Eq)]
 pub struct Position(usize);
 
 impl Display for Position {
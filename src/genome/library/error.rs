@@ -0,0 +1,8 @@
+use thiserror::Error;
+
+/// Error type associated with [`crate::genome::LibraryType`]
+#[derive(Debug, Error, PartialEq)]
+pub enum LibraryTypeError {
+    #[error("Failed to parse string value '{0}' into a valid library type. Accepted values: 'double|ds|single|ss'")]
+    ParseLibraryType(String),
+}
@@ -0,0 +1,141 @@
+use std::{fmt::{self, Display, Formatter}, str::FromStr};
+
+use super::{Orientation, Strand};
+
+mod error;
+pub use error::LibraryTypeError;
+
+/// Library preparation protocol of the sequenced material. Two possible variants:
+/// - [`LibraryType::DoubleStranded`]|`'double'`: `C>T` transitions are expected at the 5p end, and `G>A` at the 3p end.
+/// - [`LibraryType::SingleStranded`]|`'single'`: `C>T` transitions are expected at both the 5p and 3p ends.
+/// 
+/// This dictates both which misincorporation frequency is used to compute masking thresholds
+/// (see [`crate::misincorporation::MisincorporationRecord::target_freq()`]), and which reference
+/// nucleotide is targeted when masking reads (see [`LibraryType::target_nucleotide()`]).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum LibraryType {
+    #[default]
+    DoubleStranded,
+    SingleStranded,
+}
+
+impl LibraryType {
+    /// Return the reference nucleotide which should be considered as a masking candidate, at a given read
+    /// [`Orientation`], and for a read aligned on a given [`Strand`].
+    /// 
+    /// Note that read sequences are stored in the reference's orientation within sam/bam/cram files. Thus, `C>T`
+    /// transitions of reverse-strand reads appear as `G>A` transitions.
+    /// 
+    /// | library            | orientation                 | `Forward` | `Reverse` |
+    /// | ------------------ | --------------------------- | --------- | --------- |
+    /// | `DoubleStranded`   | [`Orientation::FivePrime`]  | `C`       | `C`       |
+    /// | `DoubleStranded`   | [`Orientation::ThreePrime`] | `G`       | `G`       |
+    /// | `SingleStranded`   | any                         | `C`       | `G`       |
+    /// 
+    /// # Usage
+    /// ```
+    /// use pmd_mask::genome::{LibraryType, Orientation, Strand};
+    /// 
+    /// assert_eq!(LibraryType::DoubleStranded.target_nucleotide(&Orientation::ThreePrime, &Strand::Forward), b'G');
+    /// assert_eq!(LibraryType::SingleStranded.target_nucleotide(&Orientation::ThreePrime, &Strand::Forward), b'C');
+    /// assert_eq!(LibraryType::SingleStranded.target_nucleotide(&Orientation::FivePrime,  &Strand::Reverse), b'G');
+    /// ```
+    pub fn target_nucleotide(&self, orientation: &Orientation, strand: &Strand) -> u8 {
+        match (self, orientation, strand) {
+            (Self::DoubleStranded, Orientation::FivePrime,  _              ) => b'C',
+            (Self::DoubleStranded, Orientation::ThreePrime, _              ) => b'G',
+            (Self::SingleStranded, _,                       Strand::Forward) => b'C',
+            (Self::SingleStranded, _,                       Strand::Reverse) => b'G',
+        }
+    }
+}
+
+impl AsRef<str> for LibraryType {
+    /// Obtain the [`str`] representation of a [`LibraryType`]
+    /// ```
+    /// use pmd_mask::genome::LibraryType;
+    /// 
+    /// assert_eq!(LibraryType::DoubleStranded.as_ref(), "double");
+    /// assert_eq!(LibraryType::SingleStranded.as_ref(), "single");
+    /// ```
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::DoubleStranded => "double",
+            Self::SingleStranded => "single",
+        }
+    }
+}
+
+impl Display for LibraryType {
+    /// Obtain a formatted [`String`] representation of a [`LibraryType`].
+    /// 
+    /// ```
+    /// # use pmd_mask::genome::LibraryType;
+    /// assert_eq!(&format!("{: <8}", LibraryType::SingleStranded), "single  ");
+    /// ```
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.as_ref().fmt(f)
+    }
+}
+
+impl FromStr for LibraryType {
+    type Err = LibraryTypeError;
+
+    /// Attempt to convert a string sequence into a [`LibraryType`]. Matching is case-insensitive.
+    /// - `ds`, `double`, `double-stranded` -> [`LibraryType::DoubleStranded`]
+    /// - `ss`, `single`, `single-stranded` -> [`LibraryType::SingleStranded`]
+    /// 
+    /// # Errors
+    /// 
+    /// Returns a [`LibraryTypeError::ParseLibraryType`] upon encountering any other value.
+    /// ```
+    /// use pmd_mask::genome::LibraryType;
+    /// 
+    /// assert_eq!("ss".parse::<LibraryType>(), Ok(LibraryType::SingleStranded));
+    /// assert_eq!("Double".parse::<LibraryType>(), Ok(LibraryType::DoubleStranded));
+    /// assert!("triple".parse::<LibraryType>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ds" | "double" | "double-stranded" => Ok(Self::DoubleStranded),
+            "ss" | "single" | "single-stranded" => Ok(Self::SingleStranded),
+            _ => Err(Self::Err::ParseLibraryType(s.to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn display() {
+        assert_eq!("double|single", format!("{}|{}", LibraryType::DoubleStranded, LibraryType::SingleStranded));
+        assert_eq!("single----", format!("{:-<10}", LibraryType::SingleStranded));
+    }
+
+    #[test]
+    fn from_str() {
+        for ds in ["ds", "DS", "double", "Double-Stranded"] {
+            assert_eq!(LibraryType::from_str(ds), Ok(LibraryType::DoubleStranded));
+        }
+        for ss in ["ss", "SS", "single", "single-stranded"] {
+            assert_eq!(LibraryType::from_str(ss), Ok(LibraryType::SingleStranded));
+        }
+        assert_eq!(LibraryType::from_str("s"), Err(LibraryTypeError::ParseLibraryType("s".to_string())));
+    }
+
+    #[test]
+    fn target_nucleotide() {
+        use Orientation::*;
+        use Strand::*;
+        for strand in [Forward, Reverse] {
+            assert_eq!(LibraryType::DoubleStranded.target_nucleotide(&FivePrime,  &strand), b'C');
+            assert_eq!(LibraryType::DoubleStranded.target_nucleotide(&ThreePrime, &strand), b'G');
+        }
+        for end in [FivePrime, ThreePrime] {
+            assert_eq!(LibraryType::SingleStranded.target_nucleotide(&end, &Forward), b'C');
+            assert_eq!(LibraryType::SingleStranded.target_nucleotide(&end, &Reverse), b'G');
+        }
+    }
+}
@@ -8,4 +8,8 @@ pub use orientation::Orientation;
 
 mod strand;
 pub use strand::Strand;
-pub use strand::StrandError;
\ No newline at end of file
+pub use strand::StrandError;
+
+mod library;
+pub use library::LibraryType;
+pub use library::LibraryTypeError;
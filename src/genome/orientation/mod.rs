@@ -1,5 +1,5 @@
 use std::fmt::{self, Display, Formatter};
-use serde::Deserialize;
+use serde::{Serialize, Deserialize};
 
 /// Encodes the orientation of a relative bam record position. Two possible variants:  
 /// - [`Orientation::ThreePrime`]|`'3p'`: 3'OH end of a fragment
@@ -7,7 +7,7 @@ use serde::Deserialize;
 /// 
 /// Largely associated with [`crate::misincorporation::MisincorporationRecord`], 
 /// to encode the relative position of a nucleotide within a read.
-#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum Orientation {
     #[serde(rename = "3p")]
     ThreePrime,
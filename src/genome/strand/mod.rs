@@ -1,6 +1,6 @@
 use std::{fmt::{self, Display, Formatter}, str::FromStr};
 
-use serde::Deserialize;
+use serde::{Serialize, Deserialize};
 use rust_htslib::bam::Record;
 
 mod error;
@@ -11,7 +11,7 @@ pub use error::StrandError;
 /// Two possible variants:  
 /// - [`Strand::Forward`]|`'+'`
 /// - [`Strand::Reverse`]|`'-'`
-#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)] 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)] 
 pub enum Strand {
     #[serde(rename = "+")] Forward,
     #[serde(rename = "-")] Reverse
@@ -1,160 +1,280 @@
-use std::{str, ops::Range};
+use std::{str, collections::BTreeMap, thread};
+use std::sync::{Arc, Mutex, mpsc};
 
 
 pub mod misincorporation;
 pub mod genome;
 pub mod mask;
 pub mod error;
+pub mod clip;
+pub mod reference;
+pub mod region;
+pub mod panel;
+pub mod metrics;
+pub mod annotate;
+pub mod reversible;
+pub mod provenance;
+pub mod format;
+pub mod profiler;
+pub mod report;
 
 use error::RuntimeError;
+use reference::ReferenceCache;
+use region::ResolvedRegion;
+use metrics::{EndCounts, MaskStats};
 use genome::Orientation;
-pub use mask::{Masks, MaskEntry, MaskThreshold};
+pub use mask::{Masks, MaskEntry, MaskThreshold, MaskOptions, MaskMode, DamageProfile};
+use mask::{ResolvedMask, MissingPolicy};
 
 use anyhow::{Result, Context};
-use rust_htslib::{faidx, bam};
+use rust_htslib::bam;
 use log::{debug, trace, warn};
 
 
 use rust_htslib::bam::ext::BamRecordExtensions;
 
 
-/// Generic internal function intended to apply selective masking on either end of a raw `&mut [u8]` read.
-/// Mainly used within [`mask_5p`] and [`mask_3p`].
+/// Read indices of every masked nucleotide of a read's end, along with its [`EndCounts`] (see [`mask_read`]).
+type MaskedEnd = (Vec<usize>, EndCounts);
+
+/// Apply selective masking on both ends of a raw `&mut [u8]` read.
+/// 
+/// Reference nucleotides matching the target nucleotide of an end lying within the threshold of this end are considered
+/// as masking candidates. Each read offset is visited, counted and masked at most once: when both ends consider an 
+/// offset as a candidate (e.g. single-stranded libraries, where the windows of short reads overlap), it is only 
+/// assigned to the nearest end. Mismatch status is thus always decided from the original, unmasked nucleotide.
 /// 
 /// # Parameters
-/// - `range`: half-bounded [`Range`] of indices where masking should be applied within `seq`.
+/// - `thresholds`: reference to a mask [`MaskThreshold`]. This is where the relative [`Position`](`crate::genome::Position`)
+///   of the [`FivePrime`](`Orientation::FivePrime`) and [`ThreePrime`](`Orientation::ThreePrime`) ends are retrieved.
 /// - `reference`: raw byte representation of reference sequence corresponding to the sequence to mask. 
-///    Note that this sequence must contain and take into account any indel found within `seq`
+///   Note that this sequence must contain and take into account any indel found within `seq`
 /// - `seq`: raw byte representation of the sequence to mask. Note that this sequence must preserve any indel found within it.
 /// - `quals`: raw byte representation of the phred-scores of `seq`.
 /// - `positions`: matching positions found between `seq` and `ref`. These can be retrieved using 
-///    [`rust_htslib::bam::Reader::aligned_pairs()`](`rust_htslib::bam::Reader`)
-
+///   [`rust_htslib::bam::Reader::aligned_pairs()`](`rust_htslib::bam::Reader`)
+/// - `targets`: reference nucleotide considered as a candidate at the 5p and 3p ends (see 
+///   [`LibraryType::target_nucleotide()`](`genome::LibraryType::target_nucleotide`)). Ends set to [`None`] are left untouched.
+/// - `options`: user-defined [`MaskOptions`], defining how candidates should be masked (see [`MaskOptions::mask()`]).
+///   When `options.mismatches_only` is set, only candidates carrying the deamination product of their target nucleotide
+///   (see [`deamination_product`]) are masked.
+///
+/// # Returns
+/// For the 5p and 3p ends, the read indices of every masked nucleotide, in ascending order, along with the number of 
+/// candidates and mismatches assigned to this end (see [`EndCounts`]).
+/// 
 /// # Errors
 /// - May emit a [`RuntimeError::ReferenceOutOfIndexError`] if the function ever fails to retrieve a reference nucleotide.
 #[inline]
-fn mask_sequence(range: Range<usize>, reference: &[u8], seq: &mut [u8], quals: &mut [u8], target_nucleotide: u8, positions: &[[usize; 2]]) -> Result<(), RuntimeError> {
-    'mask: for [readpos, refpos] in positions.iter().copied().skip(range.start).take_while(|[readpos, _]| *readpos < range.end ) {
-        if readpos >= seq.len() { break 'mask }
-        let reference_nucleotide = reference.get(refpos).ok_or_else(|| RuntimeError::ReferenceOutOfIndexError)?;
-        if *reference_nucleotide == target_nucleotide {
-            seq[readpos] = b'N';
-            quals[readpos] = 0;
+fn mask_read(thresholds: &MaskThreshold, reference: &[u8], seq: &mut [u8], quals: &mut [u8], positions: &[[usize; 2]], targets: [Option<u8>; 2], options: &MaskOptions) -> Result<(MaskedEnd, MaskedEnd), RuntimeError> {
+    // Unwrap cause we have previously validated the struct. [Code smell]
+    let windows    = [Orientation::FivePrime, Orientation::ThreePrime].map(|end| thresholds.get_threshold(&end).unwrap().inner().saturating_sub(1));
+    let mut masked = [Vec::new(), Vec::new()];
+    let mut counts = [EndCounts::default(); 2];
+    let len        = seq.len();
+    for [readpos, refpos] in positions.iter().copied().take_while(|[readpos, _]| *readpos < len) {
+        // ---- Find which ends may consider this offset as a candidate.
+        let distances = [readpos, len - 1 - readpos];
+        let targets   = [0, 1].map(|end| targets[end].filter(|_| distances[end] < windows[end]));
+        if targets.iter().all(Option::is_none) {
+            continue
+        }
+
+        // ---- Assign the offset to the nearest end, among those considering it as a candidate.
+        let reference_nucleotide = *reference.get(refpos).ok_or(RuntimeError::ReferenceOutOfIndexError)?;
+        let Some(end) = (0..2).filter(|end| targets[*end] == Some(reference_nucleotide)).min_by_key(|end| distances[*end]) else { continue };
+
+        let mismatch            = seq[readpos] == deamination_product(reference_nucleotide);
+        counts[end].candidates += 1;
+        counts[end].mismatches += mismatch as u64;
+        if mismatch || !options.mismatches_only {
+            options.mask(&mut seq[readpos], &mut quals[readpos]);
+            masked[end].push(readpos);
         }
     }
-    Ok(())
+    let [masked_5p, masked_3p] = masked;
+    Ok(((masked_5p, counts[0]), (masked_3p, counts[1])))
 }
 
-/// Apply selective masking from the [`Orientation::FivePrime`] end of a read.
-/// 
-/// # Parameters:  
-/// - `threshold`: reference to a mask [`MaskThreshold`]. This is where the relative [`Position`](`crate::genome::Position`)
-///   of the [`ThreePrime`](`Orientation::ThreePrime`) end is retrieved.
-/// - `reference`: raw byte representation of reference sequence corresponding to the sequence to mask. 
-///    Note that this sequence must contain and take into account any indel found within `seq`
-/// - `seq`: raw byte representation of the read sequence to mask. Note that this sequence must preserve any indel found within it.
-/// - `quals`: raw byte representation of the phred-scores of `seq`.
-/// - `positions`: matching positions found between `seq` and `ref`. These can be retrieved using 
-///    [`rust_htslib::bam::Reader::aligned_pairs()`](`rust_htslib::bam::Reader`)
+
+/// Return the nucleotide resulting from the deamination of a reference `nucleotide`, as observed within a read.
+/// i.e. `C>T` and `G>A`. Any other nucleotide is returned as is.
+#[inline]
+fn deamination_product(nucleotide: u8) -> u8 {
+    match nucleotide {
+        b'C'  => b'T',
+        b'G'  => b'A',
+        other => other,
+    }
+}
+
+/// Combine the sequencing error probability of a Phred base quality `qual` with a `damage` probability, and return the
+/// resulting Phred base quality, i.e. `-10 * log10(1 - (1 - e)(1 - p))`, where `e` is the sequencing error and `p` the
+/// probability that the observed nucleotide is a deamination product.
 /// 
-/// # Errors: 
-///  - May emit a [`RuntimeError::ReferenceOutOfIndexError`] (see [`mask_sequence`])
-///
-/// # @TODO:
-/// Fix this horrible code stench: [`mask_3p`] and [`mask_5p`] quite identical, and may have to much responsibility
+/// The returned quality may never be greater than `qual`. Missing base qualities (`0xff`) are returned as is.
 #[inline]
-fn mask_5p(thresholds: &MaskThreshold, reference: &[u8], seq: &mut [u8], quals: &mut [u8], positions: &[[usize; 2]]) -> Result<(), RuntimeError> {
-    // Unwrap cause we have previously validated the struct. [Code smell]
-    let mask_5p_threshold = thresholds.get_threshold(&Orientation::FivePrime).unwrap().inner();
-    let mask_5p_range     = 0..mask_5p_threshold -1;
-    mask_sequence(mask_5p_range, reference, seq, quals, b'C', positions)
+fn rescale_quality(qual: u8, damage: f32) -> u8 {
+    if qual == u8::MAX || damage <= 0.0 {
+        return qual
+    }
+    let error     = 10f64.powf(-(qual as f64) / 10.0);
+    let damage    = (damage as f64).min(1.0);
+    let new_error = error + damage - error * damage;
+    (-10.0 * new_error.log10()).round().clamp(0.0, qual as f64) as u8
 }
 
-/// Apply selective masking from the [`Orientation::ThreePrime`] end of a read.
+/// Rescale base qualities along both ends of a raw `&mut [u8]` read, according to a [`DamageProfile`].
 /// 
-/// # Parameters:  
-/// - `threshold`: reference to a mask [`MaskThreshold`]. This is where the relative [`Position`](`crate::genome::Position`)
-///   of the [`ThreePrime`](`Orientation::ThreePrime`) end is retrieved.
-/// - `reference`: raw byte representation of reference sequence corresponding to the sequence to mask. 
-///    Note that this sequence must contain and take into account any indel found within `seq`
-/// - `seq`: raw byte representation of the read sequence to mask. Note that this sequence must preserve any indel found within it.
-/// - `quals`: raw byte representation of the phred-scores of `seq`.
-/// - `positions`: matching positions found between `seq` and `ref`. These can be retrieved using 
-///    [`rust_htslib::bam::Reader::aligned_pairs()`](`rust_htslib::bam::Reader`)
+/// Only nucleotides matching the deamination product of the target nucleotide of an end (i.e. a `T` over a reference
+/// `C`, or an `A` over a reference `G`) are rescaled (see [`rescale_quality`]), using the misincorporation frequency 
+/// found at their relative position, starting from this end of the read. Nucleotides lying beyond the length of the
+/// profile are left untouched.
 /// 
-/// # Errors: 
-///  - May emit a [`RuntimeError::ReferenceOutOfIndexError`] (see [`mask_sequence`])
-///
-/// # @TODO:
-/// Fix this horrible code stench: [`mask_3p`] and [`mask_5p`] quite identical, and may have to much responsibility
+/// Each read offset is rescaled at most once: when both ends consider an offset as a candidate (e.g. single-stranded
+/// libraries, where the profiles of short reads overlap), the highest of the two frequencies is used, and the offset is
+/// only counted within the [`EndCounts`] of the corresponding end.
+/// 
+/// # Parameters
+/// - `profile`: [`DamageProfile`] of the read's [`MaskEntry`].
+/// - `reference`, `seq`, `quals`, `positions`: see [`mask_read`]
+/// - `targets`: reference nucleotide considered as a candidate at the 5p and 3p ends (see 
+///   [`LibraryType::target_nucleotide()`](`genome::LibraryType::target_nucleotide`)). Ends set to [`None`] are left untouched.
+/// 
+/// # Returns
+/// The number of candidates found within the length of the profile, along with the number of rescaled mismatches, 
+/// for the 5p and 3p ends.
+/// 
+/// # Errors
+/// - May emit a [`RuntimeError::ReferenceOutOfIndexError`] if the function ever fails to retrieve a reference nucleotide.
 #[inline]
-fn mask_3p(thresholds: &MaskThreshold, reference: &[u8], seq: &mut [u8], quals: &mut [u8], positions: &[[usize; 2]]) -> Result<(), RuntimeError> {
-    // Unwrap cause we have previously validated the struct. [Code smell]
-    let mask_3p_threshold = thresholds.get_threshold(&Orientation::ThreePrime).unwrap().inner();
-    let mask_3p_range     = seq.len().saturating_sub(mask_3p_threshold-1)..seq.len();
-    mask_sequence(mask_3p_range, reference, seq, quals, b'G', positions)
+fn rescale_read(profile: &DamageProfile, reference: &[u8], seq: &[u8], quals: &mut [u8], positions: &[[usize; 2]], targets: [Option<u8>; 2]) -> Result<(EndCounts, EndCounts), RuntimeError> {
+    let frequencies = [Orientation::FivePrime, Orientation::ThreePrime].map(|end| profile.get_frequencies(&end));
+    let mut counts  = [EndCounts::default(); 2];
+    for [readpos, refpos] in positions.iter().copied().take_while(|[readpos, _]| *readpos < seq.len()) {
+        // ---- Find the end holding the highest frequency at this offset, among those considering it as a candidate.
+        let distances = [readpos, seq.len() - 1 - readpos];
+        let mut relevant: Option<(usize, f32)> = None;
+        for end in 0..2 {
+            let (Some(target), Some(frequency)) = (targets[end], frequencies[end].get(distances[end])) else { continue };
+            let reference_nucleotide = reference.get(refpos).ok_or(RuntimeError::ReferenceOutOfIndexError)?;
+            if *reference_nucleotide == target && relevant.is_none_or(|(_, other)| *frequency > other) {
+                relevant = Some((end, *frequency));
+            }
+        }
+
+        let Some((end, frequency)) = relevant else { continue };
+        counts[end].candidates += 1;
+        if targets[end].map(deamination_product) == Some(seq[readpos]) {
+            counts[end].mismatches += 1;
+            quals[readpos] = rescale_quality(quals[readpos], frequency);
+        }
+    }
+    Ok((counts[0], counts[1]))
 }
 
 
-/// Apply selective masking on any struct implementing [`rust_htslib::bam::Read`], using a reference genome and a
-/// structured set of masking thresholds ([`Masks`]). Masked records and then written to the provided `writer`.
-/// # Usage
-/// ```
-/// # use std::error::Error;
-/// use rust_htslib::{bam::{self, Read}, faidx};
-/// use pmd_mask::mask::Masks;
-/// fn main() -> Result<(), Box<dyn Error>> {
+/// Determine whether the leftmost ([`Orientation::FivePrime`]) and rightmost ([`Orientation::ThreePrime`]) ends of an
+/// aligned read correspond to actual ends of the sequenced DNA fragment, using its FLAG and mate information.
 /// 
-///     // ---- Get an input bam, a reference genome, and a misincorpooration file.
-///     let reference  = faidx::Reader::from_path("tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz")?;
-///     let mut reader = bam::Reader::from_path("tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam")?;
-///     let masks = Masks::from_path("tests/test-data/bam/dummy-MTonly/misincorporation.txt", 0.01)?;
-///
-///     // ----- Prepare an output
-///     let header = bam::Header::from_template(reader.header());
-///     let mut output = bam::Writer::from_stdout(&header, bam::Format::Sam)?;
-/// 
-///     // ---- Apply pmd-mask
-///     pmd_mask::apply_pmd_mask(&mut reader, &reference, &masks, &mut output)?;
+/// # Behavior
+/// - Unpaired reads, and reads whose pair is either improper or lacks a template length (`TLEN`) are considered as
+///   spanning the whole fragment. Both ends are thus returned as `true`.
+/// - The read's own 5' end (i.e. leftmost end of forward reads, rightmost end of reverse reads) is always a fragment end.
+/// - The read's 3' end is only considered as a fragment end if the alignment reaches the end of the template, as 
+///   defined by `TLEN`.
 /// 
-///     Ok(())
-/// }
-/// ```
+/// # Returns
+/// A `(leftmost, rightmost)` tuple of booleans, specifying whether each end of the read is a fragment end.
 #[inline]
-pub fn apply_pmd_mask<B>(bam: &mut B, reference: &faidx::Reader, masks: &Masks, writer: &mut bam::Writer) -> Result<()>
-where   B: bam::Read,
-{
-    // ---- Get header template
-    let header            = bam::Header::from_template(bam.header());    
-    let header_view       = bam::HeaderView::from_header(&header);
+fn fragment_ends(record: &bam::Record) -> (bool, bool) {
+    let template_len = record.insert_size().abs();
+    if !record.is_paired() || !record.is_proper_pair() || record.is_mate_unmapped() || template_len == 0 {
+        return (true, true)
+    }
 
-    let mut bam_record    = bam::Record::new(); // Input record buffer
-    let mut out_record    = bam::Record::new(); // Output record buffer
-    let default_threshold = MaskThreshold::default();
-    // ---- Loop along input records
-    while let Some(result) = bam.read(&mut bam_record) {
-        result.unwrap();
-
-        // ---- Get chromosome and strand info of this record.
-        // EXPENSIVE: converting tid to string.
-        // @ TODO: map Misincorporation chromosome names to tid. once, before looping.
-        let current_record = MaskEntry::from_htslib_record(&header_view, &mut bam_record).map_err(RuntimeError::ParseMask)?;
-        
+    match record.is_reverse() {
+        false => (true, record.reference_end() >= record.pos() + template_len),
+        true  => (record.pos() <= record.reference_end() - template_len, true),
+    }
+}
+
+/// Number of records read, masked and written at once by [`apply_pmd_mask`].
+#[cfg(not(test))] const BATCH_SIZE: usize = 4096;
+#[cfg(test)]      const BATCH_SIZE: usize = 64;
+
+/// A single record awaiting masking, along with its [`ResolvedMask`] and the full sequence of its reference contig.
+/// Unplaced records (`tid == -1`) carry no mask, and are written back untouched.
+struct Task<'a> {
+    record: bam::Record,
+    target: Option<(&'a ResolvedMask<'a>, Arc<[u8]>)>,
+}
+
+/// Per-record masking logic of [`apply_pmd_mask`], shared across worker threads.
+struct Masker<'a> {
+    options          : &'a MaskOptions,
+    default_threshold: MaskThreshold,
+    default_profile  : DamageProfile,
+}
+
+impl<'a> Masker<'a> {
+    fn new(options: &'a MaskOptions) -> Self {
+        Self { options, default_threshold: MaskThreshold::default(), default_profile: DamageProfile::default() }
+    }
+
+    /// Mask a batch of [`Task`]s, and return the resulting records, in order, along with their [`MaskStats`].
+    fn mask_batch(&self, batch: Vec<Task>) -> Result<(Vec<bam::Record>, MaskStats)> {
+        let mut stats  = MaskStats::default();
+        let records = batch.into_iter().map(|task| match task.target {
+            Some((resolved, contig)) => self.mask(&task.record, resolved, &contig, &mut stats),
+            None                     => { stats.reads += 1; Ok(task.record) },
+        }).collect::<Result<Vec<_>>>()?;
+        Ok((records, stats))
+    }
+
+    /// Mask a single record, using its [`ResolvedMask`] and the full sequence of its reference `contig`. Candidate 
+    /// counts are added to the provided `stats`.
+    fn mask(&self, bam_record: &bam::Record, resolved: &ResolvedMask, contig: &[u8], stats: &mut MaskStats) -> Result<bam::Record> {
+        let options        = self.options;
+        let current_record = &resolved.entry;
+
+        // ---- Leave records untouched if their entry is missing, and this was requested.
+        if resolved.fallback == Some(MissingPolicy::Skip) {
+            stats.add_record(bam_record.tid() as usize, current_record.strand, false, &EndCounts::default(), &EndCounts::default());
+            return Ok(bam_record.clone())
+        }
 
         // ---- Get relevant misincorporation frequency:
-        let relevant_thresholds = match masks.get(&current_record) {
+        let relevant_thresholds = match resolved.threshold {
             Some(threshold) => threshold,
+            None if resolved.fallback == Some(MissingPolicy::Fail) => {
+                return Err(RuntimeError::MissingMaskEntry(current_record.clone()).into())
+            },
             None => {
-                debug!("{current_record} Not found in threshold dictionary. Setting default threshold {default_threshold}");
-                &default_threshold
+                debug!("{current_record} Not found in threshold dictionary. Setting default threshold {}", self.default_threshold);
+                &self.default_threshold
             }
         };
 
+        // ---- Get the relevant damage profile, if base qualities are to be rescaled.
+        let relevant_profile = match (options.mode, resolved.profile) {
+            (MaskMode::Rescale, None) => {
+                debug!("{current_record} Not found in damage profiles. Base qualities will not be rescaled");
+                &self.default_profile
+            },
+            (_, profile) => profile.unwrap_or(&self.default_profile),
+        };
 
         // ---- Get the reference's position 
-        // Memory leak here...
-        let refseq = reference.fetch_seq(current_record.chromosome.inner(), bam_record.reference_start() as usize, bam_record.reference_end() as usize -1 )?;
-        
+        let refseq = reference::slice_contig(contig, bam_record.reference_start() as usize, bam_record.reference_end() as usize);
+
+        // ---- Restrict masking candidates to panel sites, if requested.
+        let restricted;
+        let refseq = match resolved.sites {
+            Some(sites) => { restricted = panel::restrict_reference(sites, bam_record.reference_start() as u64, refseq); &restricted },
+            None        => refseq,
+        };
+
         let aligned_pos = bam_record.aligned_pairs().map(|[readpos, refpos]| [readpos as usize, (refpos - bam_record.pos()) as usize]).collect::<Vec<[usize; 2]>>();
     
         trace!("-----------------------");
@@ -173,40 +293,389 @@ where   B: bam::Read,
         trace!("Sequence : {}", unsafe { str::from_utf8_unchecked(&sequence) });
 
         const UNMAPPED_CONTEXT: &str = "(This is merely a warning because this record was already set as UnMapped (0x4)";
-        let err_msg = |e: &RuntimeError , end: Orientation | {
-            let position = bam_record.pos();
-            format!("While attempting to mask the {end} end of record [{current_record} {position}]: {e}")
+        // ---- Find which ends of the read are actual fragment ends.
+        let (mask_5p_end, mask_3p_end) = match options.fragment_aware {
+            true  => fragment_ends(bam_record),
+            false => (true, true),
         };
-        // ---- Mask 5p' positions
-        if let Err(e) = mask_5p(relevant_thresholds, refseq, &mut new_seq, &mut new_quals, &aligned_pos) {
-            let context = err_msg(&e, Orientation::FivePrime); 
-            match bam_record.is_unmapped() {
-                true => warn!("@ {context} {UNMAPPED_CONTEXT}"),
-                false => return Err(e).with_context(|| format!("{current_record}"))
-            }
+        trace!("Fragment ends      : (5p: {mask_5p_end}) (3p: {mask_3p_end})");
+        let targets = [(Orientation::FivePrime, mask_5p_end), (Orientation::ThreePrime, mask_3p_end)]
+            .map(|(end, fragment_end)| fragment_end.then(|| options.library.target_nucleotide(&end, &current_record.strand)));
+
+        // ---- Mask or rescale both ends at once, so that offsets lying within the window of either end are only 
+        //      considered once.
+        let result = match options.mode {
+            MaskMode::Rescale => rescale_read(relevant_profile, refseq, &new_seq, &mut new_quals, &aligned_pos, targets)
+                .map(|(counts_5p, counts_3p)| ((Vec::new(), counts_5p), (Vec::new(), counts_3p))),
+            _ => mask_read(relevant_thresholds, refseq, &mut new_seq, &mut new_quals, &aligned_pos, targets, options),
+        };
+        let ((masked_5p, mut counts_5p), (masked_3p, mut counts_3p)) = match result {
+            Ok(masked) => masked,
+            Err(e) if bam_record.is_unmapped() => {
+                warn!("While attempting to mask record [{current_record} {}]: {e} {UNMAPPED_CONTEXT}", bam_record.pos());
+                ((Vec::new(), EndCounts::default()), (Vec::new(), EndCounts::default()))
+            },
+            Err(e) => return Err(e).with_context(|| format!("While attempting to mask record [{current_record} {}]", bam_record.pos())),
+        };
+
+        // ---- Number of bases actually altered at either end.
+        let mut altered = match options.mode {
+            MaskMode::Hard | MaskMode::Soft                             => (masked_5p.len(), masked_3p.len()),
+            MaskMode::SoftClip | MaskMode::Rescale | MaskMode::Annotate => (0, 0),
         };
 
-        // ---- Mask 3p' positions
-        if let Err(e) = mask_3p(relevant_thresholds, refseq, &mut new_seq, &mut new_quals, &aligned_pos) {
-            let context = err_msg(&e, Orientation::ThreePrime);
-            match bam_record.is_unmapped() { 
-                true  => warn!("{context} {UNMAPPED_CONTEXT}"), 
-                false => return Err(e).context(context)
+        // ---- Convert masked regions into soft-clips, if requested.
+        let mut new_cigar = bam_record.cigar().take();
+        let mut new_pos   = bam_record.pos();
+        let mut clipped   = false;
+        if options.mode == MaskMode::SoftClip && !bam_record.is_unmapped() {
+            let clip_5p = masked_5p.last().map(|readpos| readpos + 1).unwrap_or(0);
+            let clip_3p = masked_3p.first().map(|readpos| new_seq.len() - readpos).unwrap_or(0);
+            match clip::soft_clip(&new_cigar, clip_5p, clip_3p) {
+                Some((cigar, shift)) => {
+                    new_cigar = cigar;
+                    new_pos  += shift as i64;
+                    altered   = (clip_5p, clip_3p);
+                    clipped   = true;
+                },
+                None => {
+                    debug!("[{current_record} {new_pos}] Soft-clipping would leave no aligned nucleotide. Hard-masking this record instead.");
+                    let hard_mask = MaskOptions{mode: MaskMode::Hard, ..*options};
+                    for readpos in masked_5p.iter().chain(masked_3p.iter()) {
+                        hard_mask.mask(&mut new_seq[*readpos], &mut new_quals[*readpos]);
+                    }
+                    altered = (masked_5p.len(), masked_3p.len());
+                }
             }
+            trace!("Clipped  : {new_cigar} (pos: {new_pos})");
+        }
+
+        // ---- Keep track of masking statistics.
+        let masked = match options.mode {
+            MaskMode::Rescale => counts_5p.mismatches + counts_3p.mismatches > 0,
+            _                 => !masked_5p.is_empty() || !masked_3p.is_empty(),
         };
+        (counts_5p.masked, counts_3p.masked) = (altered.0 as u64, altered.1 as u64);
+        stats.add_record(bam_record.tid() as usize, current_record.strand, masked, &counts_5p, &counts_3p);
 
         // SAFETY: samtools performs UTF8 sanity checks on the raw sequence. So we're ok.
         trace!("Masked   : {}", unsafe{ std::str::from_utf8_unchecked(&new_seq) });
 
         // ---- Flush tampered record to the output.
-        bam_record.clone_into(&mut out_record);
-        out_record.set(bam_record.qname(), Some(&bam_record.cigar().take()), &new_seq, &new_quals);
-        writer.write(&out_record)?;
+        let mut out_record = bam_record.clone();
+        if options.mode == MaskMode::Annotate {
+            let offsets = masked_5p.into_iter().chain(masked_3p).collect::<Vec<_>>();
+            annotate::annotate(&mut out_record, &offsets).with_context(|| format!("While annotating record [{current_record} {new_pos}]"))?;
+            return Ok(out_record)
+        }
+        out_record.set(bam_record.qname(), Some(&new_cigar), &new_seq, &new_quals);
+        // ---- Clipping either end changes the alignment span: the BAM bin must be recomputed, even if pos is kept.
+        if clipped {
+            out_record.set_pos(new_pos);
+            out_record.set_bin(clip::reg2bin(new_pos, out_record.reference_end()));
+        }
+
+        // ---- Keep track of the original nucleotides, qualities and alignment, if requested.
+        if options.reversible {
+            reversible::store(bam_record, &mut out_record).with_context(|| format!("While storing the original state of record [{current_record} {}]", bam_record.pos()))?;
+        }
+        Ok(out_record)
+    }
+}
+
+/// Apply selective masking on any struct implementing [`rust_htslib::bam::Read`], using a cached reference genome 
+/// ([`ReferenceCache`]) and a structured set of masking thresholds ([`Masks`]). Masked records and then written to the
+/// provided `writer`.
+/// 
+/// The provided [`MaskOptions`] dictate which reference nucleotide is targeted at either end of each read, and how
+/// these candidates are masked. When using [`MaskMode::Rescale`], base qualities are rescaled according to the 
+/// [`DamageProfile`] of each read instead (see [`Masks::get_profile()`]). When requesting fragment-aware masking, read ends
+/// which do not correspond to the end of the sequenced fragment are left untouched (see [`fragment_ends`]). When using
+/// [`MaskMode::Annotate`], sequences and base qualities are left untouched, and the read offsets which would have been
+/// masked are listed within aux tags instead (see [`annotate::annotate()`]).
+/// When `options.reversible` is set, the original nucleotides, base qualities and alignment of every altered record are
+/// kept within aux tags, so that it may later be restored (see [`reversible::store()`] and [`apply_pmd_unmask`]).
+/// 
+/// Records are read and masked in batches. When `threads` is greater than one, batches are dispatched to a pool of
+/// `threads` worker threads, and written back in their original order.
+/// 
+/// # Returns
+/// Summary [`MaskStats`] of the run, i.e. the number of processed and masked reads, along with the number of candidates
+/// and mismatches found at either end of reads.
+/// 
+/// # Usage
+/// ```
+/// # use std::error::Error;
+/// use rust_htslib::{bam::{self, Read}, faidx};
+/// use pmd_mask::mask::{Masks, MaskOptions};
+/// use pmd_mask::genome::LibraryType;
+/// use pmd_mask::reference::ReferenceCache;
+/// fn main() -> Result<(), Box<dyn Error>> {
+/// 
+///     // ---- Get an input bam, a reference genome, and a misincorpooration file.
+///     let mut reader    = bam::Reader::from_path("tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam")?;
+///     let fasta         = faidx::Reader::from_path("tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz")?;
+///     let mut reference = ReferenceCache::for_header(fasta, &bam::Header::from_template(reader.header()), 4)?;
+///     let options = MaskOptions::default();
+///     let masks = Masks::from_path("tests/test-data/bam/dummy-MTonly/misincorporation.txt", 0.01, options.library, Default::default())?;
+///
+///     // ----- Prepare an output
+///     let header = bam::Header::from_template(reader.header());
+///     let mut output = bam::Writer::from_stdout(&header, bam::Format::Sam)?;
+/// 
+///     // ---- Apply pmd-mask
+///     pmd_mask::apply_pmd_mask(&mut reader, &mut reference, &masks, &options, 1, &mut output)?;
+/// 
+///     Ok(())
+/// }
+/// ```
+#[inline]
+pub fn apply_pmd_mask<B>(bam: &mut B, reference: &mut ReferenceCache, masks: &Masks, options: &MaskOptions, threads: usize, writer: &mut bam::Writer) -> Result<MaskStats>
+where   B: bam::Read,
+{
+    let header = bam::Header::from_template(bam.header());
+    mask_records(&header, |record| bam.read(record), reference, masks, options, threads, writer)
+}
+
+/// Apply selective masking on the records of an indexed alignment file ([`bam::IndexedReader`]) overlapping a set of
+/// [`ResolvedRegion`]s. See [`apply_pmd_mask`].
+/// 
+/// Regions are expected to be sorted and non-overlapping (see [`Regions::resolve()`](region::Regions::resolve)).
+/// Records overlapping several regions are only masked and written once.
+/// 
+/// # Usage
+/// ```
+/// # use std::error::Error;
+/// use rust_htslib::{bam::{self, Read}, faidx};
+/// use pmd_mask::mask::{Masks, MaskOptions};
+/// use pmd_mask::reference::ReferenceCache;
+/// use pmd_mask::region::Regions;
+/// fn main() -> Result<(), Box<dyn Error>> {
+///     let mut reader    = bam::IndexedReader::from_path("tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam")?;
+///     let header        = bam::Header::from_template(reader.header());
+///     let fasta         = faidx::Reader::from_path("tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz")?;
+///     let mut reference = ReferenceCache::for_header(fasta, &header, 4)?;
+///     let options       = MaskOptions::default();
+///     let masks         = Masks::from_path("tests/test-data/bam/dummy-MTonly/misincorporation.txt", 0.01, options.library, Default::default())?;
+///
+///     // ---- Only mask records overlapping the first kilobase of MT.
+///     let mut regions = Regions::default();
+///     regions.push("MT:1-1000".parse()?);
+///     let regions = regions.resolve(reader.header())?;
+/// 
+///     let mut output = bam::Writer::from_stdout(&header, bam::Format::Sam)?;
+///     pmd_mask::apply_pmd_mask_regions(&mut reader, &regions, &mut reference, &masks, &options, 1, &mut output)?;
+///     Ok(())
+/// }
+/// ```
+pub fn apply_pmd_mask_regions(bam: &mut bam::IndexedReader, regions: &[ResolvedRegion], reference: &mut ReferenceCache, masks: &Masks, options: &MaskOptions, threads: usize, writer: &mut bam::Writer) -> Result<MaskStats> {
+    use bam::Read;
+    let header = bam::Header::from_template(bam.header());
+
+    let mut regions  = regions.iter();
+    let mut current  = None;
+    let mut previous: Option<&ResolvedRegion> = None;
+    let next_record  = |record: &mut bam::Record| loop {
+        // ---- Fetch the next region once the current one is exhausted.
+        let region = match current {
+            Some(region) => region,
+            None => {
+                let region = regions.next()?;
+                debug!("Fetching records overlapping region [tid: {}] {}-{}", region.tid, region.start, region.end);
+                if let Err(e) = bam.fetch((region.tid, region.start, region.end)) {
+                    return Some(Err(e))
+                }
+                current = Some(region);
+                region
+            }
+        };
 
-        // Manually remove rust-htslib fetch_seq leak.
-        unsafe {libc::free(refseq.as_ptr() as *mut std::ffi::c_void)}
+        match bam.read(record) {
+            None                => { previous = current.take(); },
+            Some(Err(e))        => return Some(Err(e)),
+            // Records overlapping the previous region of the same contig were already written.
+            Some(Ok(())) if previous.is_some_and(|prev| prev.tid == region.tid && record.pos() < prev.end) => {},
+            Some(Ok(()))        => return Some(Ok(())),
+        }
+    };
+    mask_records(&header, next_record, reference, masks, options, threads, writer)
+}
+
+/// Restore the original records of an alignment file which was reversibly masked (see [`reversible::restore()`]), and
+/// write them to the provided `writer`. Records which do not carry any reversible masking tag are written back untouched.
+/// 
+/// # Returns
+/// The number of restored records.
+/// 
+/// # Errors
+/// Returns a [`reversible::ReversibleError`] if any record carries invalid reversible masking tags, or an htslib error if
+/// any record cannot be read or written.
+/// 
+/// # Usage
+/// ```
+/// # use std::error::Error;
+/// use rust_htslib::bam::{self, Read};
+/// use pmd_mask::apply_pmd_unmask;
+/// fn main() -> Result<(), Box<dyn Error>> {
+///     let mut reader = bam::Reader::from_path("tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam")?;
+///     let header     = bam::Header::from_template(reader.header());
+///     let mut output = bam::Writer::from_stdout(&header, bam::Format::Sam)?;
+/// 
+///     // ---- This file was never masked: nothing to restore.
+///     assert_eq!(apply_pmd_unmask(&mut reader, &mut output)?, 0);
+///     Ok(())
+/// }
+/// ```
+pub fn apply_pmd_unmask<B: bam::Read>(bam: &mut B, writer: &mut bam::Writer) -> Result<u64> {
+    let mut record   = bam::Record::new();
+    let mut restored = 0;
+    while let Some(result) = bam.read(&mut record) {
+        result?;
+        let qname = String::from_utf8_lossy(record.qname()).to_string();
+        restored += reversible::restore(&mut record).with_context(|| format!("While restoring record {qname}"))? as u64;
+        writer.write(&record)?;
     }
-    Ok(())
+    Ok(restored)
+}
+
+/// Stream through an alignment file, and estimate its misincorporation profile over the first `length` positions of
+/// each read end (see [`profiler::DamageProfiler`]). Unplaced records are ignored.
+///
+/// # Errors
+/// Returns an htslib error if any record cannot be read, or a [`reference::ReferenceError`] if the reference contig of
+/// any record cannot be fetched.
+///
+/// # Usage
+/// ```
+/// # use std::error::Error;
+/// use rust_htslib::{faidx, bam};
+/// use pmd_mask::profile_damage;
+/// use pmd_mask::reference::ReferenceCache;
+/// use pmd_mask::genome::LibraryType;
+/// fn main() -> Result<(), Box<dyn Error>> {
+///     let mut reader    = bam::Reader::from_path("tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam")?;
+///     let mut reference = ReferenceCache::new(faidx::Reader::from_path("tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz")?, 1)?;
+///
+///     let profiler = profile_damage(&mut reader, &mut reference, 70)?;
+///     assert_eq!(profiler.misincorporations(LibraryType::DoubleStranded).len(), 2 * 2 * 70);
+///     Ok(())
+/// }
+/// ```
+pub fn profile_damage<B: bam::Read>(bam: &mut B, reference: &mut ReferenceCache, length: usize) -> Result<profiler::DamageProfiler> {
+    let header_view  = bam.header().clone();
+    let mut profiler = profiler::DamageProfiler::new(length);
+    let mut record   = bam::Record::new();
+    let mut chromosome: Option<(i32, genome::ChrName)> = None;
+    while let Some(result) = bam.read(&mut record) {
+        result?;
+        if record.tid() < 0 || record.is_unmapped() { continue }
+
+        // ---- Only look up the name of the chromosome when switching contigs.
+        if !matches!(chromosome, Some((tid, _)) if tid == record.tid()) {
+            chromosome = Some((record.tid(), genome::ChrName::from_htslib_record(&header_view, &record)?));
+        }
+        let (tid, name) = chromosome.as_ref().expect("Unset chromosome name");
+        let contig = reference.fetch_contig(*tid as usize, name)?;
+        profiler.add_record(&record, name, &contig);
+    }
+    Ok(profiler)
+}
+
+/// Core masking loop of [`apply_pmd_mask`] and [`apply_pmd_mask_regions`]: `next_record` reads the next record of the
+/// input within the provided buffer, and returns [`None`] once the input is exhausted.
+fn mask_records<F>(header: &bam::Header, mut next_record: F, reference: &mut ReferenceCache, masks: &Masks, options: &MaskOptions, threads: usize, writer: &mut bam::Writer) -> Result<MaskStats>
+where   F: FnMut(&mut bam::Record) -> Option<std::result::Result<(), rust_htslib::errors::Error>>,
+{
+    // ---- Get header template
+    let header_view       = bam::HeaderView::from_header(header);
+    let mask_table        = masks.resolve(&header_view).map_err(RuntimeError::ParseMisincorporation)?;
+    let masker            = Masker::new(options);
+
+    // ---- Read a batch of records, along with their resolved masks and reference contig.
+    let mut bam_record    = bam::Record::new(); // Input record buffer
+    let mut read_batch    = || -> Result<Vec<Task>> {
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        while batch.len() < BATCH_SIZE {
+            match next_record(&mut bam_record) {
+                Some(result) => result?,
+                None         => break,
+            }
+            // Unplaced records (tid == -1) cannot be compared against the reference: write them back untouched.
+            let target = match mask_table.get(&bam_record) {
+                Some(resolved) => Some((resolved, reference.fetch_contig(bam_record.tid() as usize, &resolved.entry.chromosome)?)),
+                None => {
+                    debug!("Record {} is unplaced. Skipping.", String::from_utf8_lossy(bam_record.qname()));
+                    None
+                }
+            };
+            // NOTE: Cloning detaches the record from the reader's header (Rc<HeaderView>), which must never cross threads.
+            batch.push(Task{record: bam_record.clone(), target});
+        }
+        Ok(batch)
+    };
+
+    // ---- Single-threaded masking: process batches in place.
+    let mut stats = MaskStats::default();
+    if threads <= 1 {
+        loop {
+            let batch = read_batch()?;
+            if batch.is_empty() { break }
+            let (records, batch_stats) = masker.mask_batch(batch)?;
+            for record in records {
+                writer.write(&record)?;
+            }
+            stats.add(&batch_stats);
+        }
+        return Ok(stats)
+    }
+
+    // ---- Multi-threaded masking: dispatch batches to a pool of workers, and write them back in their original order.
+    debug!("Masking records using {threads} worker threads.");
+    let (task_sender, task_receiver)     = mpsc::channel::<(usize, Vec<Task>)>();
+    let (result_sender, result_receiver) = mpsc::channel::<(usize, Result<(Vec<bam::Record>, MaskStats)>)>();
+    let task_receiver = Mutex::new(task_receiver);
+    thread::scope(|scope| {
+        // Move the task sender within the scope: workers must hang up if we ever return early.
+        let task_sender = task_sender;
+        for _ in 0..threads {
+            let (task_receiver, result_sender, masker) = (&task_receiver, result_sender.clone(), &masker);
+            scope.spawn(move || loop {
+                // Release the lock before masking, so that other workers may pick up the next batch.
+                let next = task_receiver.lock().expect("Poisoned task queue").recv();
+                let Ok((index, batch)) = next else { break };
+                if result_sender.send((index, masker.mask_batch(batch))).is_err() { break }
+            });
+        }
+        drop(result_sender);
+
+        let max_in_flight   = 2 * threads;
+        let mut pending     = BTreeMap::new();
+        let (mut sent, mut written) = (0, 0);
+        let mut exhausted   = false;
+        while !exhausted || written < sent {
+            // ---- Keep workers busy, up to a bounded number of batches in flight.
+            while !exhausted && sent - written < max_in_flight {
+                let batch = read_batch()?;
+                if batch.is_empty() { exhausted = true; break }
+                task_sender.send((sent, batch)).expect("Worker threads unexpectedly hung up");
+                sent += 1;
+            }
+            if written == sent { continue }
+
+            // ---- Write every completed batch which directly follows the last written one.
+            let (index, result) = result_receiver.recv().expect("Worker threads unexpectedly hung up");
+            pending.insert(index, result);
+            while let Some(result) = pending.remove(&written) {
+                let (records, batch_stats) = result?;
+                for record in records {
+                    writer.write(&record)?;
+                }
+                stats.add(&batch_stats);
+                written += 1;
+            }
+        }
+        Ok(stats)
+    })
 }
 
 
@@ -223,7 +692,6 @@ where   B: bam::Read,
 /// Reference: TGTAGTGAGCTGAGATCGTGCCATTGCACTCCAGCCTGGGCAACAGGAGTGAAACTCTATCTCAAAAAAAAAAAAAAATTAAACAAAAACAAACCTGCCTC
 /// Sequence : TGTAGTGAGCTGAGATCGTGCCATTGCACTCCAGCCTGGGCAACAGGAGTGAAACTCTATCTC AAAAAAAAAAAAAATTAAACAAAAACAAACCTGCCTC
 /// Masked   : TGTAGTGAGCTGAGATCGTGCCATTGCACTCCAGCCTGGGCAACAGGAGTGAAACTCTATCTC AAAAAAAAAAAAAATTAAACAAAAACAAACCTNCCTC
-
 #[cfg(test)]
 mod test {
     use super::*;
@@ -291,7 +759,7 @@ mod test {
 
     /// 1. Takes an input reference, nucleotide sequence and PHRED qualities in their string representation,
     /// 2. Convert these to bytes,
-    /// 2. Applies masking on these vector using [`mask_5p`] and [`mask_3p`]
+    /// 2. Applies masking on these vector using [`mask_read`], one end at a time.
     fn mask_and_validate(threshold_len: usize, reference: &str, seq: &str, quals: &str, cigar: &[Cigar]) -> Result<(String, String)> {
         let reference = reference.as_bytes();
         let mut seq   = seq.as_bytes().to_vec();
@@ -307,7 +775,7 @@ mod test {
         // Mask 5p
         let pair_indices = cigar2paired_indices(cigar);
         println!("---- 5p masking with threshold set at {threshold_len}");
-        mask_5p(&threshold, reference, &mut seq, &mut quals, &pair_indices)?;
+        mask_read(&threshold, reference, &mut seq, &mut quals, &pair_indices, [Some(b'C'), None], &MaskOptions::default())?;
         print_align!(reference, seq, quals);
 
         // ---- Validate 5p masking.
@@ -331,7 +799,7 @@ mod test {
 
         // Mask 3p
         println!("---- 3p masking with threshold set at {threshold_len}");
-        mask_3p(&threshold, reference, &mut seq, &mut quals, &pair_indices)?;
+        mask_read(&threshold, reference, &mut seq, &mut quals, &pair_indices, [None, Some(b'G')], &MaskOptions::default())?;
         print_align!(reference, seq, quals);
 
         // ---- Validate 3p masking
@@ -466,6 +934,154 @@ mod test {
         }
     }
 
+    #[test]
+    fn mask_single_stranded_3p() {
+        let reference = b"CCGCCGCCGCCGCCGCCGCC";
+        let mut seq   = b"TTGTTGTTGTTGTTGTTGTT".to_vec();
+        let mut quals = vec![37; seq.len()];
+        let positions = cigar2paired_indices(&[Cigar::Match(seq.len())]);
+        let threshold = dummy_threshold(6);
+
+        let options = MaskOptions{library: genome::LibraryType::SingleStranded, ..Default::default()};
+        mask_read(&threshold, reference, &mut seq, &mut quals, &positions, [None, Some(b'C')], &options).expect("Failed to mask 3p end");
+        print_align!(reference, seq, quals);
+
+        // Last 5 positions are candidates: reference C's are masked, while G's are left untouched.
+        assert_eq!(&seq, b"TTGTTGTTGTTGTTGNNGNN");
+        assert_eq!(&quals[15..], &[0, 0, 37, 0, 0]);
+    }
+
+    #[test]
+    fn soft_mask_custom_quality() {
+        let reference = b"CCCCCAAAAAAAAAAGGGGG";
+        let mut seq   = b"TCTCTAAAAAAAAAAGAGAG".to_vec();
+        let mut quals = vec![37; seq.len()];
+        let positions = cigar2paired_indices(&[Cigar::Match(seq.len())]);
+        let threshold = dummy_threshold(4);
+
+        let options = MaskOptions{mode: mask::MaskMode::Soft, quality: 2, ..Default::default()};
+        mask_read(&threshold, reference, &mut seq, &mut quals, &positions, [Some(b'C'), Some(b'G')], &options).expect("Failed to mask read");
+        print_align!(reference, seq, quals);
+
+        // Nucleotides must remain untouched, while candidate qualities are downscaled.
+        assert_eq!(&seq, b"TCTCTAAAAAAAAAAGAGAG");
+        assert_eq!(quals, [2, 2, 2, 37, 37, 37, 37, 37, 37, 37, 37, 37, 37, 37, 37, 37, 37, 2, 2, 2]);
+    }
+
+    #[test]
+    fn hard_mask_custom_replacement() {
+        let reference = b"CCCCCAAAAAAAAAAGGGGG";
+        let mut seq   = b"TCTCTAAAAAAAAAAGAGAG".to_vec();
+        let mut quals = vec![37; seq.len()];
+        let positions = cigar2paired_indices(&[Cigar::Match(seq.len())]);
+        let threshold = dummy_threshold(4);
+
+        let options = MaskOptions{replacement: b'X', quality: 1, ..Default::default()};
+        mask_read(&threshold, reference, &mut seq, &mut quals, &positions, [Some(b'C'), Some(b'G')], &options).expect("Failed to mask read");
+        print_align!(reference, seq, quals);
+
+        assert_eq!(&seq, b"XXXCTAAAAAAAAAAGAXXX");
+        assert_eq!(quals, [1, 1, 1, 37, 37, 37, 37, 37, 37, 37, 37, 37, 37, 37, 37, 37, 37, 1, 1, 1]);
+    }
+
+    #[test]
+    fn rescale_quality_bounds() {
+        // No damage -> quality is left untouched.
+        assert_eq!(rescale_quality(37, 0.0), 37);
+        // Certain damage -> quality drops to zero.
+        assert_eq!(rescale_quality(37, 1.0), 0);
+        // Missing qualities are left untouched.
+        assert_eq!(rescale_quality(u8::MAX, 0.5), u8::MAX);
+        // 10% damage probability caps quality at ~Q10.
+        assert_eq!(rescale_quality(40, 0.1), 10);
+        for qual in 0..=60 {
+            assert!(rescale_quality(qual, 0.05) <= qual);
+        }
+    }
+
+    #[test]
+    fn rescale_both_ends() {
+        let reference = b"CCCCCAAAAAAAAAAGGGGG";
+        let seq       = b"TCTCTAAAAAAAAAAGAGAA".to_vec();
+        let mut quals = vec![40; seq.len()];
+        let positions = cigar2paired_indices(&[Cigar::Match(seq.len())]);
+
+        let mut profile = DamageProfile::default();
+        for (i, freq) in [0.3, 0.1, 0.01].into_iter().enumerate() {
+            for end in [Orientation::FivePrime, Orientation::ThreePrime] {
+                profile.set_frequency(end, genome::Position::new(i+1), freq);
+            }
+        }
+
+        rescale_read(&profile, reference, &seq, &mut quals, &positions, [Some(b'C'), Some(b'G')]).expect("Failed to rescale read");
+
+        // Only T's over C's (5p) and A's over G's (3p) within the profile's length are rescaled.
+        let (q1, q2, q3) = (rescale_quality(40, 0.3), rescale_quality(40, 0.1), rescale_quality(40, 0.01));
+        assert_eq!(quals, [q1, 40, q3, 40, 40, 40, 40, 40, 40, 40, 40, 40, 40, 40, 40, 40, 40, 40, q2, q1]);
+    }
+
+    #[test]
+    fn rescale_single_stranded_overlap() {
+        // ---- Read is shorter than twice the profile: every offset lies within the profile of both ends.
+        let reference = b"CCCCC";
+        let seq       = b"TTCTT".to_vec();
+        let mut quals = vec![40; seq.len()];
+        let positions = cigar2paired_indices(&[Cigar::Match(seq.len())]);
+
+        let mut profile = DamageProfile::default();
+        for (i, freq) in [0.3, 0.2, 0.1, 0.05, 0.01].into_iter().enumerate() {
+            for end in [Orientation::FivePrime, Orientation::ThreePrime] {
+                profile.set_frequency(end, genome::Position::new(i+1), freq);
+            }
+        }
+
+        let (counts_5p, counts_3p) = rescale_read(&profile, reference, &seq, &mut quals, &positions, [Some(b'C'), Some(b'C')]).expect("Failed to rescale read");
+
+        // ---- Each offset is rescaled once, using the highest frequency of either end.
+        let (q1, q2) = (rescale_quality(40, 0.3), rescale_quality(40, 0.2));
+        assert_eq!(quals, [q1, q2, 40, q2, q1]);
+        assert_eq!(counts_5p, EndCounts{candidates: 3, mismatches: 2, masked: 0});
+        assert_eq!(counts_3p, EndCounts{candidates: 2, mismatches: 2, masked: 0});
+    }
+
+    fn dummy_pair(pos: i64, len: u32, template_len: i64, reverse: bool) -> bam::Record {
+        use bam::record::{Cigar as HtsCigar, CigarString};
+        let mut record = bam::Record::new();
+        let seq = vec![b'A'; len as usize];
+        record.set(b"read", Some(&CigarString(vec![HtsCigar::Match(len)])), &seq, &vec![37; len as usize]);
+        record.set_pos(pos);
+        record.set_paired();
+        record.set_proper_pair();
+        record.set_insert_size(if reverse { -template_len } else { template_len });
+        if reverse { record.set_reverse() };
+        record
+    }
+
+    #[test]
+    fn fragment_ends_unpaired() {
+        let mut record = dummy_pair(100, 50, 200, false);
+        record.unset_paired();
+        assert_eq!(fragment_ends(&record), (true, true));
+
+        let mut record = dummy_pair(100, 50, 200, false);
+        record.unset_proper_pair();
+        assert_eq!(fragment_ends(&record), (true, true));
+
+        let record = dummy_pair(100, 50, 0, true);
+        assert_eq!(fragment_ends(&record), (true, true));
+    }
+
+    #[test]
+    fn fragment_ends_paired() {
+        // ---- Reads shorter than the template: only the read's 5' end is a fragment end.
+        assert_eq!(fragment_ends(&dummy_pair(100, 50, 200, false)), (true, false));
+        assert_eq!(fragment_ends(&dummy_pair(250, 50, 200, true)), (false, true));
+
+        // ---- Reads spanning the whole template: both ends are fragment ends.
+        assert_eq!(fragment_ends(&dummy_pair(100, 50, 50, false)), (true, true));
+        assert_eq!(fragment_ends(&dummy_pair(100, 50, 50, true)), (true, true));
+    }
+
     #[test]
     fn mask_spurious_unmapped_sequence() {
         let reference = "N";
@@ -474,6 +1090,104 @@ mod test {
         let cigar     = vec![Cigar::Match(seq.len())];
         println!("{:?}", mask_and_validate(10, reference, seq, quals, &cigar));
     }
-}
 
+    #[test]
+    fn multithreaded_masking_preserves_order() {
+        use rust_htslib::{faidx, bam::Read};
+        const BAM: &str = "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam";
+        const REFERENCE: &str = "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz";
+
+        let masks   = Masks::from_path("tests/test-data/bam/dummy-MTonly/misincorporation.txt", 0.01, Default::default(), Default::default()).expect("Invalid masks");
+        let outputs = [1, 4].map(|threads| {
+            let mut bam       = bam::Reader::from_path(BAM).expect("Failed to open bam");
+            let header        = bam::Header::from_template(bam.header());
+            let fasta         = faidx::Reader::from_path(REFERENCE).expect("Failed to open reference");
+            let mut reference = ReferenceCache::for_header(fasta, &header, 1).expect("Invalid cache");
+            let output        = assert_fs::NamedTempFile::new(format!("output-{threads}.sam")).expect("Failed to create fixture");
+            let mut writer    = bam::Writer::from_path(output.path(), &header, bam::Format::Sam).expect("Failed to open writer");
+            apply_pmd_mask(&mut bam, &mut reference, &masks, &MaskOptions::default(), threads, &mut writer).expect("Failed to mask");
+            drop(writer);
+            output
+        });
+
+        // ---- Input spans several batches: records must be written back in their original order.
+        let read_back = |output: &assert_fs::NamedTempFile| bam::Reader::from_path(output.path()).expect("Failed to read output")
+            .records()
+            .map(|record| record.expect("Invalid record"))
+            .map(|record| (record.qname().to_vec(), record.seq().as_bytes(), record.qual().to_vec()))
+            .collect::<Vec<_>>();
+        let (single, multi) = (read_back(&outputs[0]), read_back(&outputs[1]));
+        assert!(single.len() > 4 * BATCH_SIZE);
+        assert_eq!(single, multi);
+    }
+
+    #[test]
+    fn mask_mismatches_only() {
+        let reference = b"CCCCCTTTTTGGGGG";
+        let mut seq   = b"CTCTCTTTTTGAGAG".to_vec();
+        let mut quals = vec![37; seq.len()];
+        let positions = (0..seq.len()).map(|i| [i, i]).collect::<Vec<_>>();
+        let threshold = dummy_threshold(6);
+        let options   = MaskOptions{ mismatches_only: true, ..Default::default() };
+
+        let ((masked_5p, counts_5p), (masked_3p, counts_3p)) = mask_read(&threshold, reference, &mut seq, &mut quals, &positions, [Some(b'C'), Some(b'G')], &options).expect("Failed to mask read");
 
+        assert_eq!(seq, b"CNCNCTTTTTGNGNG");
+        assert_eq!((masked_5p, masked_3p), (vec![1, 3], vec![11, 13]));
+        assert_eq!(counts_5p, EndCounts{candidates: 5, mismatches: 2, masked: 0});
+        assert_eq!(counts_3p, EndCounts{candidates: 5, mismatches: 2, masked: 0});
+    }
+
+    #[test]
+    fn softclip_3p_recomputes_bin() {
+        use bam::record::{Cigar as HtsCigar, CigarString};
+        // ---- Read spans two 16kb bins. Clipping its last 12 nucleotides keeps its position, but not its bin.
+        let (pos, len) = (16374, 20);
+        let mut contig = vec![b'A'; 16400];
+        contig[(pos + 8) as usize..(pos + len) as usize].fill(b'G');
+
+        let mut record = bam::Record::new();
+        record.set(b"read", Some(&CigarString(vec![HtsCigar::Match(len as u32)])), &vec![b'A'; len as usize], &vec![37; len as usize]);
+        record.set_tid(0);
+        record.set_pos(pos);
+        record.set_bin(clip::reg2bin(pos, record.reference_end()));
+
+        let mut threshold = MaskThreshold::default();
+        threshold.set_threshold(Orientation::FivePrime, Position::new(1));
+        threshold.set_threshold(Orientation::ThreePrime, Position::new(14));
+        let resolved = ResolvedMask{entry: MaskEntry{chromosome: genome::ChrName::new("chr1"), strand: genome::Strand::Forward}, threshold: Some(&threshold), profile: None, details: None, sites: None, fallback: None};
+        let options  = MaskOptions{mode: MaskMode::SoftClip, ..Default::default()};
+
+        let clipped = Masker::new(&options).mask(&record, &resolved, &contig, &mut MaskStats::default()).expect("Failed to mask record");
+        assert_eq!(clipped.cigar().take(), CigarString(vec![HtsCigar::Match(8), HtsCigar::SoftClip(12)]));
+        assert_eq!(clipped.pos(), pos);
+        assert_ne!(clipped.bin(), record.bin());
+        assert_eq!(clipped.bin(), clip::reg2bin(clipped.pos(), clipped.reference_end()));
+    }
+
+    #[test]
+    fn mask_single_stranded_overlap() {
+        // ---- Read is shorter than twice the threshold: every offset lies within the window of both ends.
+        let reference = b"CCCCCCC";
+        let seq       = b"TCTCTCT".to_vec();
+        let quals     = vec![37; seq.len()];
+        let positions = cigar2paired_indices(&[Cigar::Match(seq.len())]);
+        let options   = MaskOptions{ library: genome::LibraryType::SingleStranded, mismatches_only: true, ..Default::default() };
+
+        for threshold in [dummy_threshold(8), MaskThreshold::default()] {
+            let (mut seq, mut quals) = (seq.clone(), quals.clone());
+            let ((masked_5p, counts_5p), (masked_3p, counts_3p)) = mask_read(&threshold, reference, &mut seq, &mut quals, &positions, [Some(b'C'), Some(b'C')], &options).expect("Failed to mask read");
+
+            // ---- Each offset is assigned to its nearest end, and counted once.
+            assert_eq!(seq, b"NCNCNCN");
+            assert_eq!((masked_5p, masked_3p), (vec![0, 2], vec![4, 6]));
+            assert_eq!(counts_5p, EndCounts{candidates: 4, mismatches: 2, masked: 0});
+            assert_eq!(counts_3p, EndCounts{candidates: 3, mismatches: 2, masked: 0});
+        }
+
+        // ---- Ends that are left untouched never claim an offset.
+        let (mut seq, mut quals) = (seq.clone(), quals.clone());
+        let ((masked_5p, _), (masked_3p, _)) = mask_read(&dummy_threshold(8), reference, &mut seq, &mut quals, &positions, [Some(b'C'), None], &options).expect("Failed to mask read");
+        assert_eq!((masked_5p, masked_3p), (vec![0, 2, 4, 6], vec![]));
+    }
+}
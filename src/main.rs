@@ -2,7 +2,10 @@
 //! 
 //! # What `pmd-mask` needs:
 //! 1. A sam/bam/cram file
-//! 2. A `misincorporation.txt` file, obtained from the same input bam file, using MapDamage
+//! 2. A `misincorporation.txt` file, obtained from the same input bam file, using MapDamage (or `pmd-mask profile`).
+//!    Misincorporations may also be estimated in memory, through a first pass over the input (see `--estimate-damage`),
+//!    or a fixed number of positions may be masked from either end of the reads (see `--mask-5p` and `--mask-3p`).
+//!    Thresholds may also be reloaded from a previous metrics file (see `--thresholds-file`).
 //! 3. A reference genome
 //! # What `pmd-mask` does:
 //! 
@@ -19,24 +22,31 @@
 //!    - starting at the 5p end, if at any point there is a C in the **reference** sequence AND we've not attained the treshold, mask it.
 //!    - starting at the 3p end, if at any point there is a G in the **reference** sequence AND we've not attained the treshold, mask it.
 //! 
-//!    By masking, we mean *hard masking*. i.e. downscale the Phred base-quality to 0, and set the nucleotide value to 'N'.
+//!    By default, we mean *hard masking*. i.e. downscale the Phred base-quality to 0, and set the nucleotide value to 'N'.
+//!    *Soft masking* (i.e. only downscaling the Phred base-quality) may also be requested.
 //! 
 //! 4. Stream the masked sequences to either an output file, or the standard output. The output format and compression level 
 //!    can be specified by the used. 
 
-use std::fs::File;
-use std::io::BufWriter;
-use std::path::Path;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
 
-use pmd_mask::apply_pmd_mask;
-use pmd_mask::mask::Masks;
+use pmd_mask::{apply_pmd_mask, apply_pmd_mask_regions, apply_pmd_unmask, profile_damage};
+use pmd_mask::mask::{Masks, MaskOptions, MaskMode, MissingPolicy};
 use pmd_mask::error::RuntimeError;
+use pmd_mask::reference::ReferenceCache;
+use pmd_mask::region::Regions;
+use pmd_mask::panel::SitePanel;
+use pmd_mask::profiler::DamageProfiler;
+use pmd_mask::report::RunReport;
+use pmd_mask::{provenance, format};
 
 mod logger;
 use logger::Logger;
 
 mod parser;
-use parser::Cli;
+use parser::{Cli, Command, UnmaskArgs, ProfileArgs};
 
 use clap::Parser;
 use anyhow::Result;
@@ -74,85 +84,318 @@ where   F: AsRef<Path>
 }
 
 
-/// Main logic for command line `pmd-mask` binary
-fn run(args: &Cli) -> Result<()> {
+/// Open an indexed alignment file, and return a [`rust_htslib::bam::IndexedReader`]
+/// 
+/// # Errors
+/// Returns a [`RuntimeError::MissingIndex`] if the file is not indexed, or if its index could not be loaded.
+fn open_indexed_bam_reader(path: &Path) -> Result<bam::IndexedReader, RuntimeError> {
+    info!("Opening {} (indexed)", path.display());
+    bam::IndexedReader::from_path(path).map_err(|source| RuntimeError::MissingIndex{path: path.to_path_buf(), source})
+}
+
+/// Open an indexed reference genome, and return a [`rust_htslib::faidx::Reader`]
+/// 
+/// # Errors
+/// Returns a [`RuntimeError::LoadFaidx`] if the fasta index could not be loaded.
+fn open_reference(path: &Path) -> Result<faidx::Reader> {
+    info!("Opening reference file {}", path.display());
+    let reference = faidx::Reader::from_path(path)?;
+
+    // ---- Absolutely *horrendous* workaround to issue #8 :
+    // Scan through the Debug repr of reference and check if any private field
+    // contains the value 0x0 (i.e.: NULL pointer).
+    if format!("{reference:?}").contains("0x0") {
+        anyhow::bail!(RuntimeError::LoadFaidx)
+    }
+    Ok(reference)
+}
+
+/// Stream through an alignment file (or the standard input if `bam` is [`None`]), and estimate its misincorporation
+/// profile over the first `length` positions of each read end.
+fn estimate_damage(bam: &Option<PathBuf>, reference: &Path, max_cached_contigs: u32, length: usize, thread_pool: &Option<ThreadPool>) -> Result<DamageProfiler> {
+    let mut reader = open_bam_reader(bam)?;
+    reader.set_reference(reference)?;
+    if let Some(ref pool) = thread_pool {
+        reader.set_thread_pool(pool)?;
+    }
+    let header        = bam::Header::from_template(reader.header());
+    let mut reference = ReferenceCache::for_header(open_reference(reference)?, &header, max_cached_contigs as usize)?;
+
+    info!("Estimating misincorporation frequencies over the first {length} positions of each read end...");
+    let profiler = profile_damage(&mut reader, &mut reference, length)?;
+    info!("Profiled {} reads", profiler.reads());
+    Ok(profiler)
+}
+
+/// Gather the regions requested by the user, through `--region` and `--regions-file`.
+fn requested_regions(args: &Cli) -> Result<Regions> {
+    let mut regions = Regions::default();
+    for region in args.regions.iter() {
+        regions.push_str(region);
+    }
+    if let Some(ref bed) = args.regions_file {
+        info!("Reading regions from {}", bed.display());
+        regions.extend(Regions::from_bed(bed)?);
+    }
+    Ok(regions)
+}
+
+/// Prepare an input alignment file for masking, along with the corresponding reference cache and output writer.
+/// The output header documents the run with a `@PG` record, along with the masking thresholds (`@CO` records).
+fn prepare_io<B: bam::Read>(bam: &mut B, reference: faidx::Reader, masks: &Masks, thread_pool: &Option<ThreadPool>, args: &Cli) -> Result<(ReferenceCache, bam::Writer)> {
+    // ---- Define an output format if the user never specified it: use the output's extension, or the input's format.
+    let output_format = format::output_format(args.output_fmt, args.output.as_deref(), format::input_format(bam));
+    debug!("Output format: {output_format:?}");
+
+    // ---- Prepare Bam Writer
+    let mut output_header = bam::Header::from_template(bam.header());
+    provenance::push_program(&mut output_header, &provenance::command_line(std::env::args()));
+    provenance::push_thresholds(&mut output_header, masks);
+
+    // ---- Cache reference contigs, according to the input's sort order.
+    let reference  = ReferenceCache::for_header(reference, &output_header, args.max_cached_contigs as usize)?;
+    let mut writer = open_bam_writer(&args.output, &output_header, output_format)?;
+
+    // ---- Set output compression level for BAM/CRAM output.
+    writer.set_compression_level(bam::CompressionLevel::Level(args.compress_level))?;
+
+    // ---- Set reference for CRAM files. (NOTE: the reader's reference is set by the caller, since 
+    //      bam::Read does not expose set_reference())
+    writer.set_reference(args.reference())?;
 
+    // ---- Set thread pool if the user requested multi-threading
+    if let Some(ref pool) = thread_pool { 
+        debug!("Allocating threadpool to Reader and Writer");
+        bam.set_thread_pool(pool)?;
+        writer.set_thread_pool(pool)?;
+    };
+    Ok((reference, writer))
+}
+
+/// Main logic of the `pmd-mask unmask` command: restore the original records of a reversibly masked alignment file.
+fn run_unmask(args: &UnmaskArgs, threads: u32) -> Result<()> {
     // ---- Ensure Input bam and output bam are not the same.
-    if let Some(ref input_file) = args.bam{
-        if let Some(ref output_file) = args.output {
-            if *input_file == *output_file {
-                anyhow::bail!(RuntimeError::InputIsOutput)
-            }
-        }
+    if args.bam.is_some() && args.bam == args.output {
+        anyhow::bail!(RuntimeError::InputIsOutput)
     }
 
     // ---- Ensure the stdin is being sollicited if there are no specified input bams.
     if atty::is(atty::Stream::Stdin) && args.bam.is_none() {
-            anyhow::bail!(RuntimeError::NoStdin)
+        anyhow::bail!(RuntimeError::NoStdin)
     }
 
+    let mut bam    = open_bam_reader(&args.bam)?;
+    let mut header = bam::Header::from_template(bam.header());
+    provenance::push_program(&mut header, &provenance::command_line(std::env::args()));
+    let output_format = format::output_format(args.output_fmt, args.output.as_deref(), format::input_format(&bam));
+    let mut writer = open_bam_writer(&args.output, &header, output_format)?;
+    writer.set_compression_level(bam::CompressionLevel::Level(args.compress_level))?;
 
+    if let Some(ref reference) = args.reference {
+        bam.set_reference(reference)?;
+        writer.set_reference(reference)?;
+    }
 
-    // ---- define threadpool if required:
-    let thread_pool = match args.threads {
-        1    => None ,
+    let thread_pool = match threads {
+        1    => None,
         more => {debug!("Firing up threadpool..."); Some(ThreadPool::new(more)?) }
     };
+    if let Some(ref pool) = thread_pool {
+        bam.set_thread_pool(pool)?;
+        writer.set_thread_pool(pool)?;
+    }
+
+    info!("Restoring reversibly masked records...");
+    let restored = apply_pmd_unmask(&mut bam, &mut writer)?;
+    info!("Restored {restored} records");
+    info!("Done");
+    Ok(())
+}
+
+/// Main logic of the `pmd-mask profile` command: estimate the misincorporation profile of an alignment file, and write
+/// it as a mapDamage-v2 `misincorporation.txt` file.
+fn run_profile(args: &ProfileArgs, threads: u32) -> Result<()> {
+    // ---- Ensure the stdin is being sollicited if there are no specified input bams.
+    if atty::is(atty::Stream::Stdin) && args.bam.is_none() {
+        anyhow::bail!(RuntimeError::NoStdin)
+    }
+
+    let thread_pool = match threads {
+        1    => None,
+        more => {debug!("Firing up threadpool..."); Some(ThreadPool::new(more)?) }
+    };
+    let profiler = estimate_damage(&args.bam, &args.reference, args.max_cached_contigs, args.length as usize, &thread_pool)?;
+
+    let comments = [
+        format!("table produced by {} version {}", provenance::PROGRAM_NAME, provenance::PROGRAM_VERSION),
+        format!("using mapped file {} and {} as reference file",
+            args.bam.as_ref().map_or("-".into(), |bam| bam.display().to_string()), args.reference.display()
+        ),
+        "Chr: reference from sam/bam header, End: from which termini of DNA sequences, Std: strand of reads".to_string(),
+    ];
+    let mut writer: Box<dyn Write> = match args.output {
+        Some(ref path) => {
+            info!("Writing misincorporation profile to {}", path.display());
+            Box::new(BufWriter::new(File::create(path).map_err(RuntimeError::OpenProfile)?))
+        },
+        None => {info!("Writing misincorporation profile to standard output"); Box::new(BufWriter::new(io::stdout().lock()))},
+    };
+    profiler.write(&mut writer, &comments).and_then(|_| writer.flush()).map_err(RuntimeError::WriteProfile)?;
+    info!("Done");
+    Ok(())
+}
+
+/// Compute the masking thresholds of every chromosome and strand, using either a misincorporation file, a previous 
+/// metrics file, an estimated misincorporation profile, or a fixed number of positions from either end of the reads (in which case every target of 
+/// the alignment file's `header` is assigned the same thresholds). Thresholds are then written to the metrics file, if
+/// requested.
+fn build_masks(args: &Cli, header: &bam::HeaderView, thread_pool: &Option<ThreadPool>) -> Result<Masks> {
+    // ---- Base qualities cannot be rescaled without a misincorporation profile.
+    if args.mask_mode == MaskMode::Rescale && (args.thresholds_file.is_some() || args.mask_5p.is_some()) {
+        anyhow::bail!(RuntimeError::RescaleWithoutProfile)
+    }
 
     // ---- Read Misincorporation file as a tsv file and obtain a list of Masking threshold
     //      for each chromosome, strand, and orientation.
-    info!("Computing masking positions from {}, using {} as threshold", &args.misincorporation.display(), args.threshold);
-    let thresholds = Masks::from_path(&args.misincorporation, args.threshold)?;
+    let mut thresholds = match (&args.misincorporation, &args.thresholds_file, args.mask_5p.zip(args.mask_3p)) {
+        (Some(path), _, _) => {
+            info!("Computing masking positions from {}, using {} as threshold ({}-stranded library, {})", path.display(), args.threshold, args.library, args.threshold_options());
+            Masks::from_path(path, args.threshold, args.library, args.threshold_options())?
+        },
+        // ---- Reuse the thresholds of a previous metrics file, if requested.
+        (None, Some(path), _) => {
+            info!("Loading masking positions from {} ({}-stranded library)", path.display(), args.library);
+            Masks::from_metrics_path(path)?
+        },
+        // ---- Mask a fixed number of positions from either end, if requested.
+        (None, None, Some((mask_5p, mask_3p))) => {
+            info!("Masking the first {mask_5p} (5p) and last {mask_3p} (3p) positions of every read ({}-stranded library)", args.library);
+            Masks::uniform(header, mask_5p, mask_3p)?
+        },
+        // ---- Otherwise, estimate misincorporations from the input alignment file, through a first pass.
+        (None, None, None) => {
+            let profiler = estimate_damage(&args.bam, args.reference(), args.max_cached_contigs, args.profile_length as usize, thread_pool)?;
+            info!("Computing masking positions from the estimated profile, using {} as threshold ({}-stranded library, {})", args.threshold, args.library, args.threshold_options());
+            Masks::from_misincorporations(&profiler.misincorporations(args.library), args.threshold, &args.threshold_options())?
+        },
+    };
+
+    // ---- Use separate thresholds for specific read groups, if requested.
+    for (key, path) in args.read_group_misincorporations.iter() {
+        info!("Computing masking positions of read group {key} from {}", path.display());
+        thresholds.set_read_group(key, Masks::from_path(path, args.threshold, args.library, args.threshold_options())?);
+    }
+
+    // ---- Use separate thresholds for specific read lengths, if requested.
+    for (bin, path) in args.length_misincorporations.iter() {
+        info!("Computing masking positions of read length bin {bin} from {}", path.display());
+        thresholds.set_length_bin(*bin, Masks::from_path(path, args.threshold, args.library, args.threshold_options())?)?;
+    }
+
+    // ---- Handle contigs missing from the misincorporation profile according to the requested policy.
+    if args.missing_contigs == MissingPolicy::Pooled && thresholds.pooled().is_none() {
+        anyhow::bail!(RuntimeError::MissingPooledProfile)
+    }
+    thresholds.set_missing_policy(args.missing_contigs);
+
+    // ---- Restrict masking to the sites of a SNP panel, if requested.
+    if let Some(ref panel) = args.panel {
+        info!("Restricting masking to the SNP panel sites of {}{}", panel.display(), if args.panel_transitions_only {" (transitions only)"} else {""});
+        thresholds.set_panel(SitePanel::from_path(panel, args.panel_transitions_only).map_err(RuntimeError::Panel)?);
+    }
 
     if let Some(ref file) = args.metrics_file {
         info!("Writing masking thresholds to {}", file.display());
         let mut metrics_writer = BufWriter::new(File::create(file).map_err(RuntimeError::OpenMetrics)?);
+        match (&args.thresholds_file, args.mask_5p.zip(args.mask_3p)) {
+            (Some(path), _)                  => writeln!(metrics_writer, "# Thresholds file: {}", path.display()),
+            (None, Some((mask_5p, mask_3p))) => writeln!(metrics_writer, "# Fixed-length masking: {mask_5p} (5p) | {mask_3p} (3p)"),
+            (None, None)                     => writeln!(metrics_writer, "# Threshold strategy: {}", args.threshold_strategy),
+        }.map_err(RuntimeError::WriteMasksMetrics)?;
+        if let Some(interval) = args.confidence_interval {
+            writeln!(metrics_writer, "# Confidence interval: {interval} ({})", args.confidence_level).map_err(RuntimeError::WriteMasksMetrics)?;
+        }
         thresholds.write(&mut metrics_writer).map_err(RuntimeError::WriteMasksMetrics)?;
     }
+    Ok(thresholds)
+}
 
-    // ---- Open Reference File
-    info!("Opening reference file {}", &args.reference.display());
-    let reference = faidx::Reader::from_path(&args.reference)?;
+/// Main logic for command line `pmd-mask` binary
+fn run(args: &Cli) -> Result<()> {
 
-    // ---- Absolutely *horrendous* workaround to issue #8 :
-    // Scan through the Debug repr of reference and check if any private field
-    // contains the value 0x0 (i.e.: NULL pointer).
-    if format!("{reference:?}").contains("0x0") {
-        anyhow::bail!(RuntimeError::LoadFaidx)
+    // ---- Ensure Input bam and output bam are not the same.
+    if let Some(ref input_file) = args.bam{
+        if let Some(ref output_file) = args.output {
+            if *input_file == *output_file {
+                anyhow::bail!(RuntimeError::InputIsOutput)
+            }
+        }
     }
 
-    // ---- Open bam file
-    let mut bam = open_bam_reader(&args.bam)?;
-    
-    // ---- IndexedRead: Does not seem to provide with any underlying async/multithread support either..
-    //let mut bam = bam::IndexedReader::from_path(args.bam.unwrap()).unwrap();
-    //bam.fetch(bam::FetchDefinition::All);
-    
+    // ---- Ensure the stdin is being sollicited if there are no specified input bams.
+    if atty::is(atty::Stream::Stdin) && args.bam.is_none() {
+            anyhow::bail!(RuntimeError::NoStdin)
+    }
 
-    // ---- Define an output format if the user never specified it.
-    // @TODO: It'd be nice to set this to the same format as the input... rust_htslib might have a way to access the header's magic number
-    let output_format = args.output_fmt.unwrap_or(bam::Format::Sam);
 
-    // ---- Prepare Bam Writer
-    let output_header = bam::Header::from_template(bam.header());
-    let mut writer = open_bam_writer(&args.output, &output_header, output_format)?;
 
-    // ---- Set output compression level for BAM/CRAM output.
-    writer.set_compression_level(bam::CompressionLevel::Level(args.compress_level))?;
+    // ---- define threadpool if required:
+    let thread_pool = match args.threads {
+        1    => None ,
+        more => {debug!("Firing up threadpool..."); Some(ThreadPool::new(more)?) }
+    };
 
-    // ---- Set reference for CRAM files. 
-    bam.set_reference(&args.reference)?;
-    writer.set_reference(&args.reference)?;
+    // ---- Open Reference File
+    let reference = open_reference(args.reference())?;
+
+    let options = MaskOptions {
+        library    : args.library,
+        mode       : args.mask_mode,
+        replacement: args.mask_char,
+        quality    : args.mask_quality,
+        fragment_aware: args.fragment_aware,
+        mismatches_only: args.mismatches_only,
+        reversible: args.reversible,
+    };
 
-    // ---- Set thread pool if the user requested multi-threading
-    if let Some(ref pool) = thread_pool { 
-        debug!("Allocating threadpool to Reader and Writer");
-        bam.set_thread_pool(pool)?;
-        writer.set_thread_pool(pool)?;
+    // ---- Open bam file, and apply PMD-masking. Region-restricted processing requires an indexed input.
+    let regions = requested_regions(args)?;
+    let (stats, thresholds, header) = match (&args.bam, regions.is_empty()) {
+        (Some(path), false) => {
+            let mut bam = open_indexed_bam_reader(path)?;
+            bam.set_reference(args.reference())?;
+            let regions = regions.resolve(bam.header())?;
+            let thresholds = build_masks(args, bam.header(), &thread_pool)?;
+            let (mut reference, mut writer) = prepare_io(&mut bam, reference, &thresholds, &thread_pool, args)?;
+            info!("Applying PMD-masking over {} region(s) ({options})...", regions.len());
+            let stats = apply_pmd_mask_regions(&mut bam, &regions, &mut reference, &thresholds, &options, args.threads as usize, &mut writer)?;
+            (stats, thresholds, bam.header().clone())
+        },
+        _ => {
+            let mut bam = open_bam_reader(&args.bam)?;
+            bam.set_reference(args.reference())?;
+            let thresholds = build_masks(args, bam.header(), &thread_pool)?;
+            let (mut reference, mut writer) = prepare_io(&mut bam, reference, &thresholds, &thread_pool, args)?;
+            info!("Applying PMD-masking ({options})...");
+            let stats = apply_pmd_mask(&mut bam, &mut reference, &thresholds, &options, args.threads as usize, &mut writer)?;
+            (stats, thresholds, bam.header().clone())
+        }
     };
+    info!("Processed {} reads ({} masked). Masked bases: {} (5p) | {} (3p)", stats.reads, stats.masked_reads, stats.five_prime.masked, stats.three_prime.masked);
 
-    info!("Applying PMD-masking...");
-    apply_pmd_mask(&mut bam, &reference, &thresholds, &mut writer)?;
+    // ---- Append masking statistics to the metrics file.
+    if let Some(ref file) = args.metrics_file {
+        let mut metrics_writer = BufWriter::new(OpenOptions::new().append(true).open(file).map_err(RuntimeError::OpenMetrics)?);
+        stats.write(&mut metrics_writer).map_err(RuntimeError::WriteStatsMetrics)?;
+    }
+
+    // ---- Write a structured JSON report, if requested.
+    if let Some(ref file) = args.json_metrics {
+        info!("Writing JSON metrics report to {}", file.display());
+        let report = RunReport::new(&thresholds, &stats, &header, args.library)?;
+        let mut json_writer = BufWriter::new(File::create(file).map_err(RuntimeError::OpenMetrics)?);
+        report.write(&mut json_writer).map_err(RuntimeError::WriteJsonMetrics)?;
+    }
     info!("Done");
     Ok(())
 }
@@ -167,7 +410,12 @@ fn main() {
     debug!("Provided Command line Arguments:{args:#?}");
 
     // ---- Run main process
-    if let Err(e) = run(&args) {
+    let result = match args.command {
+        Some(Command::Unmask(ref unmask))   => run_unmask(unmask, args.threads),
+        Some(Command::Profile(ref profile)) => run_profile(profile, args.threads),
+        None                                => run(&args),
+    };
+    if let Err(e) = result {
         error!("{e}");
         std::process::exit(1)
     }
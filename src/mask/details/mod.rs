@@ -0,0 +1,83 @@
+use std::fmt::{self, Display, Formatter};
+
+use serde::{Serialize, Serializer};
+
+use crate::genome::Orientation;
+use crate::misincorporation::MisincorporationRecord;
+use super::MissingPolicy;
+
+/// Reason why a [`MaskEntry`](super::MaskEntry) does not use thresholds computed from its own misincorporation 
+/// profile. Possible variants:
+/// - [`Fallback::MinCount`]: the profile holds too few observations, and the genome-wide pooled profile is used 
+///   instead (see [`ThresholdOptions::min_count`](crate::misincorporation::ThresholdOptions)).
+/// - [`Fallback::InvalidFrequency`]: the profile holds abnormal misincorporation frequencies (NaN, infinite values, 
+///   etc.). Masking applies along the full length of the read for the affected end(s).
+/// - [`Fallback::ThresholdNeverMet`]: the misincorporation frequency never falls below the threshold on at least one 
+///   end. Masking applies along the full length of the read for this end.
+/// - [`Fallback::MergedReference`]: the entry is absent, but the merged entry of a mapDamage-v2 run performed with 
+///   `--merge-reference-sequences` applies (see [`MERGED_REFERENCE`](crate::misincorporation::MERGED_REFERENCE)).
+/// - [`Fallback::Missing`]: the entry is absent from the misincorporation profile, and handled according to a 
+///   [`MissingPolicy`].
+/// 
+/// [`Fallback`]s are serialized using their [`Display`] representation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fallback {
+    MinCount{observed: usize, required: usize},
+    InvalidFrequency,
+    ThresholdNeverMet,
+    MergedReference,
+    Missing(MissingPolicy),
+}
+
+impl Display for Fallback {
+    /// Obtain a formatted [`String`] representation of a [`Fallback`] reason.
+    /// ```
+    /// use pmd_mask::mask::{Fallback, MissingPolicy};
+    /// 
+    /// assert_eq!(Fallback::MinCount{observed: 12, required: 100}.to_string(), "min-count: 12 < 100 (pooled profile)");
+    /// assert_eq!(Fallback::Missing(MissingPolicy::Skip).to_string(), "missing (skip)");
+    /// ```
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MinCount{observed, required} => write!(f, "min-count: {observed} < {required} (pooled profile)"),
+            Self::InvalidFrequency             => write!(f, "invalid frequency (full-length masking)"),
+            Self::ThresholdNeverMet            => write!(f, "threshold never met (full-length masking)"),
+            Self::MergedReference              => write!(f, "merged reference"),
+            Self::Missing(policy)              => write!(f, "missing ({policy})"),
+        }
+    }
+}
+
+impl Serialize for Fallback {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Details on how the [`MaskThreshold`](super::MaskThreshold) of a [`MaskEntry`](super::MaskEntry) was obtained:
+/// i.e. the [`MisincorporationRecord`] found at the selected threshold position of each end (if any), and the 
+/// [`Fallback`] reason, if the entry does not use thresholds computed from its own profile.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ThresholdDetails {
+    pub five_prime : Option<MisincorporationRecord>,
+    pub three_prime: Option<MisincorporationRecord>,
+    pub fallback   : Option<Fallback>,
+}
+
+impl ThresholdDetails {
+    /// Retrieve the [`MisincorporationRecord`] found at the selected threshold position of a given end, if any.
+    pub fn get(&self, end: &Orientation) -> Option<&MisincorporationRecord> {
+        match end {
+            Orientation::FivePrime  => self.five_prime.as_ref(),
+            Orientation::ThreePrime => self.three_prime.as_ref(),
+        }
+    }
+
+    /// Keep track of the [`MisincorporationRecord`] found at the selected threshold position of its end.
+    pub fn set(&mut self, record: MisincorporationRecord) {
+        match record.end {
+            Orientation::FivePrime  => self.five_prime  = Some(record),
+            Orientation::ThreePrime => self.three_prime = Some(record),
+        }
+    }
+}
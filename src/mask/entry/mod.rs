@@ -4,6 +4,8 @@ use std::fmt::{self, Display, Formatter};
 
 use crate::genome::{ChrName, Strand};
 
+use serde::{Serialize, Deserialize};
+
 use rust_htslib::bam::{HeaderView, Record};
 
 mod error;
@@ -27,7 +29,7 @@ pub use error::MaskEntryError;
 ///     strand: Strand::Reverse
 /// };
 /// ```
-#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct MaskEntry {
     pub chromosome: ChrName,
     pub strand    : Strand
@@ -93,12 +95,12 @@ pub mod dummy {
     pub fn dummy_bam(strand: Strand, pos: usize) -> (Header, Record){
         let mut header = Header::new();
         let mut chr_record = HeaderRecord::new("SQ".as_bytes());
-        chr_record.push_tag("SN".as_bytes(), &"chr1".to_string());
+        chr_record.push_tag("SN".as_bytes(), "chr1");
         chr_record.push_tag("LN".as_bytes(), 249250621);
         header.push_record(&chr_record);
 
         let mut record = Record::new();
-        record.set("*".as_bytes(), None, &[b'A', b'T', b'C', b'G'], &[37, 37, 37, 37]);
+        record.set("*".as_bytes(), None, b"ATCG", &[37, 37, 37, 37]);
         record.set_tid(0);
         record.set_pos(pos as i64);
         if strand == Strand::Reverse {
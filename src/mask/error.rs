@@ -2,7 +2,7 @@ use thiserror::Error;
 
 use crate::misincorporation::MisincorporationsError;
 
-use super::{entry::MaskEntry, threshold::MaskThresholdError};
+use super::{entry::MaskEntry, threshold::MaskThresholdError, length::LengthBin};
 
 /// Error type enum for [`crate::mask::Masks`]
 #[derive(Debug, Error)]
@@ -14,6 +14,18 @@ pub enum MasksError {
     OpenFile{#[source] source: std::io::Error},
 
     #[error("Failed to obtain Misincorporations from misincorporation file")]
-    GenerateMisincorporations(#[from] MisincorporationsError)
+    GenerateMisincorporations(#[from] MisincorporationsError),
+
+    #[error("Failed to parse a target name from the alignment file's header as valid UTF-8 [{0}]")]
+    ParseHeader(#[source] std::str::Utf8Error),
+
+    #[error("Read length bin {0} overlaps with the previously set bin {1}")]
+    OverlappingLengthBins(LengthBin, LengthBin),
+
+    #[error("@line {0}: Failed to deserialize record in metrics file. Got {1}")]
+    DeserializeMetrics(usize, String),
+
+    #[error("{0} is found more than once within the metrics file")]
+    DuplicateEntry(MaskEntry),
 
 }
@@ -0,0 +1,8 @@
+use thiserror::Error;
+
+/// Error type enum for [`crate::mask::LengthBin`]
+#[derive(Debug, Error, PartialEq)]
+pub enum LengthBinError {
+    #[error("Failed to parse '{0}' into a valid read length bin. Expected '<min>-<max>' or '<min>-' (inclusive, with min <= max)")]
+    ParseLengthBin(String),
+}
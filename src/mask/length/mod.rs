@@ -0,0 +1,117 @@
+use std::{fmt::{self, Display, Formatter}, str::FromStr};
+
+use rust_htslib::bam::Record;
+use serde::{Serialize, Deserialize};
+
+mod error;
+pub use error::LengthBinError;
+
+/// An inclusive range of read lengths, used to stratify masking thresholds according to the length of each record
+/// (see [`crate::mask::Masks::set_length_bin()`] and [`record_length()`]). An unspecified `max` spans every length
+/// greater or equal to `min`.
+/// 
+/// [`LengthBin`]s may be parsed from `<min>-<max>` or `<min>-` strings.
+/// 
+/// # Usage
+/// ```
+/// use pmd_mask::mask::LengthBin;
+/// 
+/// let bin: LengthBin = "30-49".parse().expect("Invalid bin");
+/// assert_eq!(bin, LengthBin{min: 30, max: Some(49)});
+/// assert!(bin.contains(49) && !bin.contains(50));
+/// 
+/// let bin: LengthBin = "50-".parse().expect("Invalid bin");
+/// assert!(bin.contains(usize::MAX));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct LengthBin {
+    pub min: usize,
+    pub max: Option<usize>,
+}
+
+impl LengthBin {
+    /// Check whether a given read `length` lies within this bin.
+    #[inline]
+    pub fn contains(&self, length: usize) -> bool {
+        length >= self.min && self.max.is_none_or(|max| length <= max)
+    }
+
+    /// Check whether two bins share any read length.
+    /// ```
+    /// use pmd_mask::mask::LengthBin;
+    /// 
+    /// let short = LengthBin{min: 0, max: Some(49)};
+    /// assert!(!short.overlaps(&LengthBin{min: 50, max: None}));
+    /// assert!(short.overlaps(&LengthBin{min: 49, max: Some(60)}));
+    /// ```
+    pub fn overlaps(&self, other: &Self) -> bool {
+        self.contains(other.min) || other.contains(self.min)
+    }
+}
+
+impl FromStr for LengthBin {
+    type Err = LengthBinError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || LengthBinError::ParseLengthBin(s.to_string());
+        let (min, max) = s.split_once('-').ok_or_else(err)?;
+        let min = min.parse::<usize>().map_err(|_| err())?;
+        let max = match max {
+            ""  => None,
+            max => Some(max.parse::<usize>().map_err(|_| err())?),
+        };
+        match max {
+            Some(max) if max < min => Err(err()),
+            _                      => Ok(Self{min, max}),
+        }
+    }
+}
+
+impl Display for LengthBin {
+    /// Return a formatted [`String`] representation of a [`LengthBin`], i.e. `<min>-<max>`
+    /// ```
+    /// use pmd_mask::mask::LengthBin;
+    /// assert_eq!(LengthBin{min: 30, max: Some(49)}.to_string(), "30-49");
+    /// assert_eq!(LengthBin{min: 50, max: None}.to_string(), "50-");
+    /// ```
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.max {
+            Some(max) => format!("{}-{max}", self.min).fmt(f),
+            None      => format!("{}-", self.min).fmt(f),
+        }
+    }
+}
+
+/// Return the length of the DNA fragment a record originates from, i.e. its absolute template length (`TLEN`) when the
+/// record is part of a proper pair. Otherwise, the length of its sequence is used.
+#[inline]
+pub fn record_length(record: &Record) -> usize {
+    match record.is_paired() && record.is_proper_pair() && record.insert_size() != 0 {
+        true  => record.insert_size().unsigned_abs() as usize,
+        false => record.seq_len(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn from_str() {
+        assert_eq!(LengthBin::from_str("0-0"), Ok(LengthBin{min: 0, max: Some(0)}));
+        for invalid in ["", "30", "-30", "50-30", "a-b", "30-b", "30--"] {
+            assert_eq!(LengthBin::from_str(invalid), Err(LengthBinError::ParseLengthBin(invalid.to_string())));
+        }
+    }
+
+    #[test]
+    fn fragment_length() {
+        let mut record = Record::new();
+        record.set(b"read", None, b"ACGT", &[37; 4]);
+        record.set_insert_size(-120);
+        assert_eq!(record_length(&record), 4);
+
+        record.set_flags(0x1 | 0x2);
+        assert_eq!(record_length(&record), 120);
+    }
+}
@@ -0,0 +1,8 @@
+use thiserror::Error;
+
+/// Error type enum for [`crate::mask::MissingPolicy`]
+#[derive(Debug, Error, PartialEq)]
+pub enum MissingPolicyError {
+    #[error("Failed to parse string value '{0}' into a valid missing contig policy. Accepted values: 'mask|skip|pooled|fail'")]
+    ParseMissingPolicy(String),
+}
@@ -0,0 +1,95 @@
+use std::{fmt::{self, Display, Formatter}, str::FromStr};
+
+use serde::{Serialize, Deserialize};
+
+mod error;
+pub use error::MissingPolicyError;
+
+/// Defines how records are handled when their [`MaskEntry`](super::MaskEntry) is absent from the
+/// [`Masks`](super::Masks), i.e. when their chromosome is missing from the misincorporation file, or never met the 
+/// masking threshold. Possible variants:
+/// - [`MissingPolicy::Mask`]|`'mask'`: mask masking candidates along the full length of the read.
+/// - [`MissingPolicy::Skip`]|`'skip'`: leave the record untouched.
+/// - [`MissingPolicy::Pooled`]|`'pooled'`: use the thresholds of the genome-wide profile, pooled across all chromosomes
+///   (see [`Misincorporations::merged()`](crate::misincorporation::Misincorporations::merged)). 
+/// - [`MissingPolicy::Fail`]|`'fail'`: abort with an error.
+/// 
+/// Note that entries of a mapDamage-v2 run performed with `--merge-reference-sequences` apply to every contig, and are
+/// thus never considered missing (see [`MERGED_REFERENCE`](crate::misincorporation::MERGED_REFERENCE)).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MissingPolicy {
+    #[default]
+    Mask,
+    Skip,
+    Pooled,
+    Fail,
+}
+
+impl AsRef<str> for MissingPolicy {
+    /// Obtain the [`str`] representation of a [`MissingPolicy`]
+    /// ```
+    /// use pmd_mask::mask::MissingPolicy;
+    /// 
+    /// assert_eq!(MissingPolicy::Mask.as_ref(), "mask");
+    /// assert_eq!(MissingPolicy::Pooled.as_ref(), "pooled");
+    /// ```
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Mask   => "mask",
+            Self::Skip   => "skip",
+            Self::Pooled => "pooled",
+            Self::Fail   => "fail",
+        }
+    }
+}
+
+impl Display for MissingPolicy {
+    /// Obtain a formatted [`String`] representation of a [`MissingPolicy`].
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.as_ref().fmt(f)
+    }
+}
+
+impl FromStr for MissingPolicy {
+    type Err = MissingPolicyError;
+
+    /// Attempt to convert a string sequence into a [`MissingPolicy`]. Matching is case-insensitive.
+    /// 
+    /// # Errors
+    /// Returns a [`MissingPolicyError::ParseMissingPolicy`] upon encountering any value that is neither `mask`, `skip`,
+    /// `pooled` nor `fail`.
+    /// ```
+    /// use pmd_mask::mask::MissingPolicy;
+    /// 
+    /// assert_eq!("Skip".parse::<MissingPolicy>(), Ok(MissingPolicy::Skip));
+    /// assert!("ignore".parse::<MissingPolicy>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "mask"   => Ok(Self::Mask),
+            "skip"   => Ok(Self::Skip),
+            "pooled" => Ok(Self::Pooled),
+            "fail"   => Ok(Self::Fail),
+            _        => Err(Self::Err::ParseMissingPolicy(s.to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn display() {
+        assert_eq!("mask|skip|pooled|fail", format!("{}|{}|{}|{}", MissingPolicy::Mask, MissingPolicy::Skip, MissingPolicy::Pooled, MissingPolicy::Fail));
+    }
+
+    #[test]
+    fn from_str() {
+        for (input, want) in [("mask", MissingPolicy::Mask), ("SKIP", MissingPolicy::Skip), ("Pooled", MissingPolicy::Pooled), ("fAiL", MissingPolicy::Fail)] {
+            assert_eq!(MissingPolicy::from_str(input), Ok(want));
+        }
+        assert_eq!(MissingPolicy::from_str("ignore"), Err(MissingPolicyError::ParseMissingPolicy("ignore".to_string())));
+    }
+}
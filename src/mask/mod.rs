@@ -1,10 +1,13 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{HashMap, BTreeMap};
 use std::fs::File;
 use std::io::Write;
 use std::path::Path;
 
 
-use log::{warn, debug, trace};
+use log::{info, warn, debug, trace};
+use rust_htslib::bam::HeaderView;
+use serde::{Serialize, Serializer, Deserialize, Deserializer, de};
 
 pub mod entry;
 pub use entry::MaskEntry;
@@ -15,11 +18,33 @@ pub use entry::MaskEntryError;
 mod threshold;
 pub use threshold::MaskThreshold;
 
+mod mode;
+pub use mode::{MaskMode, MaskModeError};
+
+mod options;
+pub use options::MaskOptions;
+
+mod profile;
+pub use profile::DamageProfile;
+
+mod table;
+pub use table::{MaskTable, ResolvedMask};
+
+mod missing;
+pub use missing::{MissingPolicy, MissingPolicyError};
+
+mod details;
+pub use details::{ThresholdDetails, Fallback};
+
+mod length;
+pub use length::{LengthBin, LengthBinError, record_length};
+
 mod error;
 pub use error::MasksError;
 
-use crate::misincorporation::Misincorporations;
-use crate::genome::Orientation;
+use crate::misincorporation::{Misincorporations, MisincorporationRecord, ThresholdOptions};
+use crate::genome::{ChrName, Orientation, Position, Strand, LibraryType};
+use crate::panel::SitePanel;
 
 
 /// A [`HashMap`] collection of [`MaskThreshold`]s, mapped according to their respective [`MaskEntry`].
@@ -30,8 +55,71 @@ use crate::genome::Orientation;
 /// - keys are [`MaskEntry`] (themselves, containing the chromosome and Strand information of the entry)
 /// - values are [`MaskThreshold`]s (themselves, containing the relative threshold positions for the 5p and 3p end of a read.)
 /// 
-#[derive(Debug)]
-pub struct Masks {inner: HashMap<MaskEntry, MaskThreshold>}
+/// [`Masks`] may additionally keep track of the full [`DamageProfile`] of each [`MaskEntry`], when constructed from a
+/// misincorporation file (see [`Masks::from_path()`]), restrict masking to the sites of a [`SitePanel`]
+/// (see [`Masks::set_panel()`]), and use separate thresholds for the records of specific read groups 
+/// (see [`Masks::set_read_group()`]) or read lengths (see [`Masks::set_length_bin()`]). Records whose entry is absent
+/// from the collection are handled according to a [`MissingPolicy`] (see [`Masks::set_missing_policy()`]). 
+/// [`ThresholdDetails`] are kept for each entry computed from a misincorporation profile (see [`Masks::get_details()`]).
+/// 
+/// [`Masks`] can be (de)serialized with serde: thresholds are written as a sorted sequence of `(MaskEntry, MaskThreshold)`
+/// pairs, along with the thresholds of read groups, length bins and the genome-wide profile, and the [`MissingPolicy`].
+/// [`DamageProfile`]s, [`ThresholdDetails`] and the [`SitePanel`] are skipped.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Masks {
+    #[serde(serialize_with = "serialize_thresholds", deserialize_with = "deserialize_thresholds")]
+    inner      : HashMap<MaskEntry, MaskThreshold>,
+    #[serde(skip)]
+    profiles   : HashMap<MaskEntry, DamageProfile>,
+    #[serde(skip)]
+    panel      : Option<SitePanel>,
+    #[serde(default)]
+    read_groups: HashMap<String, Masks>,
+    #[serde(default)]
+    length_bins: Vec<(LengthBin, Masks)>,
+    #[serde(default)]
+    pooled     : Option<Box<Masks>>,
+    #[serde(default)]
+    missing    : MissingPolicy,
+    #[serde(skip)]
+    details    : HashMap<MaskEntry, ThresholdDetails>,
+}
+
+/// Serialize the thresholds of a [`Masks`] struct as a sequence of `(MaskEntry, MaskThreshold)` pairs, sorted by entry,
+/// since [`MaskEntry`] keys cannot be represented within most self-describing formats (e.g. JSON).
+fn serialize_thresholds<S: Serializer>(inner: &HashMap<MaskEntry, MaskThreshold>, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut sorted = inner.iter().collect::<Vec<_>>();
+    sorted.sort_by(|a, b| a.0.cmp(b.0));
+    serializer.collect_seq(sorted)
+}
+
+/// Deserialize the thresholds of a [`Masks`] struct from a sequence of `(MaskEntry, MaskThreshold)` pairs 
+/// (see [`serialize_thresholds()`]).
+fn deserialize_thresholds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<HashMap<MaskEntry, MaskThreshold>, D::Error> {
+    Ok(Vec::<(MaskEntry, MaskThreshold)>::deserialize(deserializer)?.into_iter().collect())
+}
+
+/// A single record of a metrics file, as written by [`Masks::write()`]. Fields are '<Chr> <Std> <5p> <3p>'.
+#[derive(Debug, Deserialize)]
+struct MetricsRecord {
+    #[serde(rename = "Chr")] chromosome : ChrName,
+    #[serde(rename = "Std")] strand     : Strand,
+    #[serde(rename = "5p", deserialize_with = "deserialize_metrics_position")] five_prime : Position,
+    #[serde(rename = "3p", deserialize_with = "deserialize_metrics_position")] three_prime: Position,
+}
+
+/// Deserialize a threshold position of a metrics file. `NA` values (i.e. the threshold was never met) are converted 
+/// to [`usize::MAX`], thus applying masking along the full length of reads.
+fn deserialize_metrics_position<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Position, D::Error> {
+    let field = String::deserialize(deserializer)?;
+    match field.trim() {
+        "NA"  => Ok(Position::new(usize::MAX)),
+        value => match value.parse::<usize>() {
+            Ok(position) if position > 0 => Ok(Position::new(position)),
+            _ => Err(de::Error::custom(format!("Invalid threshold position '{value}'. Expected a 1-based position, or 'NA'"))),
+        }
+    }
+}
 
 
 impl TryFrom<&Misincorporations> for Masks {
@@ -45,17 +133,18 @@ impl TryFrom<&Misincorporations> for Masks {
     /// ```
     /// use pmd_mask::misincorporation::Misincorporations;
     /// use pmd_mask::mask::Masks;
+    /// use pmd_mask::genome::LibraryType;
     /// 
     /// fn main() -> Result<(), Box<dyn std::error::Error>> {
     ///     let reader = "tests/test-data/bam/dummy-MTonly/misincorporation.txt";
-    ///     let misincorporations = Misincorporations::from_path(reader, 0.01)?;
+    ///     let misincorporations = Misincorporations::from_path(reader, 0.01, LibraryType::DoubleStranded, Default::default())?;
     ///     let masks = Masks::try_from(&misincorporations)?;
     ///     Ok(())
     /// }
     /// ```
     fn try_from(value: &Misincorporations) -> Result<Self, Self::Error> {
         // ---- Restructure Misincorporation records as a HashMap<MaskEntry, MaskThreshold>
-        let mut masks = Self{ inner: HashMap::with_capacity(value.len()) };
+        let mut masks = Self{ inner: HashMap::with_capacity(value.len()), ..Default::default() };
         for position in value.iter() {
             let record = MaskEntry{chromosome: position.chromosome.clone(), strand: position.strand};
             masks.inner.entry(record)
@@ -73,15 +162,18 @@ impl Masks {
 
     /// Instantiate a [`Masks`] struct from a [mapDamage-v2](https://github.com/ginolhac/mapDamage)'s
     /// [`misincorporation.txt`](https://ginolhac.github.io/mapDamage/#a4) output file and a set, user-defined 
-    /// threshold value.
+    /// threshold value. The [`LibraryType`] dictates which misincorporation frequencies are used to 
+    /// compute thresholds at either end of a read (see [`Misincorporations::from_path()`]), while the 
+    /// [`ThresholdOptions`] dictate how the threshold position is selected from each profile.
     /// 
     /// # Usage
     /// ```
     /// use pmd_mask::mask::Masks;
+    /// use pmd_mask::genome::LibraryType;
     /// use std::error::Error;
     /// fn main() -> Result<(), Box<dyn Error>> {
     ///     let file  = "tests/test-data/bam/dummy-MTonly/misincorporation.txt";
-    ///     let masks = Masks::from_path(&file, 0.01)?;
+    ///     let masks = Masks::from_path(&file, 0.01, LibraryType::DoubleStranded, Default::default())?;
     ///     Ok(())
     /// }
     /// ```
@@ -92,10 +184,10 @@ impl Masks {
     /// - a [`MasksError::OpenFile`] if the method failed to open the provided `misincorporations` file.
     /// - any [`MasksError`] spat out from the private [`Masks::from_reader()`](Masks::from_reader) function
     /// 
-    pub fn from_path(misincorporations: impl AsRef<Path>, threshold: f32) -> Result<Self, MasksError> {
+    pub fn from_path(misincorporations: impl AsRef<Path>, threshold: f32, library: LibraryType, options: ThresholdOptions) -> Result<Self, MasksError> {
         let file = File::open(&misincorporations)
             .map_err(|e| MasksError::OpenFile{source: e})?;
-        Self::from_reader(file, threshold)
+        Self::from_reader(file, threshold, library, options)
     }
 
     /// Instantiate a [`Masks`] struct from a generic Reader and a set threshold. Used by [`Masks::from_path()`](Masks::from_path)
@@ -103,13 +195,141 @@ impl Masks {
     /// # Errors
     /// - May bubble out any errors arising from [`Misincorporations::from_reader()`](Misincorporations::from_reader)
     /// - May bubble out any errors arising from [`Masks::try_from::<&Misincorporations>()`]
-    fn from_reader<R: std::io::Read>(misincorporations: R, threshold: f32) -> Result<Self, MasksError> {
+    fn from_reader<R: std::io::Read>(misincorporations: R, threshold: f32, library: LibraryType, options: ThresholdOptions) -> Result<Self, MasksError> {
+
+        let profile = Misincorporations::profile_from_reader(misincorporations, library)?;
+        Self::from_misincorporations(&profile, threshold, &options)
+    }
+
+    /// Instantiate a [`Masks`] struct from a full, in-memory misincorporation profile and a set threshold (see 
+    /// [`Misincorporations::profile_from_path()`], or [`DamageProfiler`](crate::profiler::DamageProfiler)).
+    /// 
+    /// Entries whose profile falls short of the minimum count requirement of the provided [`ThresholdOptions`] use the
+    /// genome-wide, pooled profile instead (see [`Misincorporations::pooled()`]). The thresholds of this genome-wide 
+    /// profile are additionally kept for contigs missing from `profile` (see [`MissingPolicy::Pooled`]).
+    /// 
+    /// # Errors
+    /// - May bubble out any errors arising from [`Masks::try_from::<&Misincorporations>()`]
+    pub fn from_misincorporations(profile: &Misincorporations, threshold: f32, options: &ThresholdOptions) -> Result<Self, MasksError> {
+        let (pooled_profile, sparse_entries) = Self::pool_sparse_entries(profile, threshold, options);
+        let mut masks = Self::from_profile(&pooled_profile, threshold, options)?;
+        for (entry, observed) in sparse_entries {
+            masks.details.entry(entry).or_default().fallback = Some(Fallback::MinCount{observed, required: options.min_count});
+        }
+        masks.pooled  = Some(Box::new(Self::from_profile(&profile.merged(options.pool_strands), threshold, options)?));
+        Ok(masks)
+    }
+
+    /// Instantiate a uniform [`Masks`] struct, where the first `mask_5p` and last `mask_3p` positions of every read are 
+    /// masked, regardless of their chromosome and strand. Entries are created for every target of the alignment 
+    /// file's [`HeaderView`]. This requires no misincorporation profile.
+    /// 
+    /// # Usage
+    /// ```
+    /// use std::error::Error;
+    /// use rust_htslib::bam::{self, Read};
+    /// use pmd_mask::mask::{Masks, MaskEntry};
+    /// use pmd_mask::genome::{ChrName, Strand, Orientation, Position};
+    /// fn main() -> Result<(), Box<dyn Error>> {
+    ///     let bam   = bam::Reader::from_path("tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam")?;
+    ///     let masks = Masks::uniform(bam.header(), 5, 3)?;
+    /// 
+    ///     let threshold = masks.get(&MaskEntry{ chromosome: ChrName::new("MT"), strand: Strand::Reverse }).expect("Missing entry");
+    ///     assert_eq!(threshold.get_threshold(&Orientation::FivePrime),  Some(&Position::new(6)));
+    ///     assert_eq!(threshold.get_threshold(&Orientation::ThreePrime), Some(&Position::new(4)));
+    ///     Ok(())
+    /// }
+    /// ```
+    /// 
+    /// # Errors
+    /// Returns a [`MasksError::ParseHeader`] if any target name of the header is not valid UTF-8.
+    pub fn uniform(header_view: &HeaderView, mask_5p: usize, mask_3p: usize) -> Result<Self, MasksError> {
+        let mut masks = Self::default();
+        for name in header_view.target_names() {
+            let chromosome = ChrName::new(std::str::from_utf8(name).map_err(MasksError::ParseHeader)?);
+            for strand in [Strand::Forward, Strand::Reverse] {
+                // ---- Thresholds define the first position where masking does *not* apply.
+                let threshold = masks.inner.entry(MaskEntry{chromosome: chromosome.clone(), strand}).or_default();
+                threshold.set_threshold(Orientation::FivePrime,  Position::new(mask_5p + 1));
+                threshold.set_threshold(Orientation::ThreePrime, Position::new(mask_3p + 1));
+            }
+        }
+        masks.validate()?;
+        Ok(masks)
+    }
+
+    /// Instantiate a [`Masks`] struct from a metrics file, previously written by [`Masks::write()`] (e.g. using 
+    /// `--metrics-file`). This allows thresholds to be computed once, reviewed and edited, before being reused.
+    /// 
+    /// `NA` positions apply masking along the full length of reads. `#`-prefixed comment lines are ignored, such as 
+    /// the threshold strategy or the masking statistics (see [`MaskStats::write()`](crate::metrics::MaskStats::write)).
+    /// No [`DamageProfile`] is available from such files.
+    /// 
+    /// # Usage
+    /// ```
+    /// use std::error::Error;
+    /// use pmd_mask::mask::{Masks, MaskEntry};
+    /// use pmd_mask::genome::{ChrName, Strand, Orientation, Position, LibraryType};
+    /// fn main() -> Result<(), Box<dyn Error>> {
+    ///     let file  = "tests/test-data/bam/dummy-MTonly/misincorporation.txt";
+    ///     let masks = Masks::from_path(&file, 0.01, LibraryType::DoubleStranded, Default::default())?;
+    /// 
+    ///     let mut metrics = Vec::new();
+    ///     masks.write(&mut metrics)?;
+    ///     let reloaded = Masks::from_metrics_reader(metrics.as_slice())?;
+    /// 
+    ///     let entry = MaskEntry{chromosome: ChrName::new("MT"), strand: Strand::Forward};
+    ///     assert_eq!(reloaded.get(&entry), masks.get(&entry));
+    ///     Ok(())
+    /// }
+    /// ```
+    /// 
+    /// # Errors
+    /// - Returns a [`MasksError::OpenFile`] if the method failed to open the provided `metrics` file.
+    /// - May bubble out any error arising from [`Masks::from_metrics_reader()`]
+    pub fn from_metrics_path(metrics: impl AsRef<Path>) -> Result<Self, MasksError> {
+        let file = File::open(&metrics)
+            .map_err(|e| MasksError::OpenFile{source: e})?;
+        Self::from_metrics_reader(file)
+    }
 
-        let mut threshold_positions = Misincorporations::from_reader(misincorporations, threshold)?;
+    /// Instantiate a [`Masks`] struct from a generic Reader, over the contents of a metrics file (see 
+    /// [`Masks::from_metrics_path()`]).
+    /// 
+    /// # Errors
+    /// - Returns a [`MasksError::DeserializeMetrics`] upon encountering an invalid record, or an invalid position.
+    /// - Returns a [`MasksError::DuplicateEntry`] if any chromosome and strand is found more than once.
+    pub fn from_metrics_reader<R: std::io::Read>(metrics: R) -> Result<Self, MasksError> {
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(b'\t')
+            .has_headers(true)
+            .comment(Some(b'#'))
+            .from_reader(metrics);
+
+        let mut masks = Self::default();
+        for result in reader.deserialize::<MetricsRecord>() {
+            // ---- Report the 1-based line of the file, comments and header included.
+            let record = result.map_err(|e| MasksError::DeserializeMetrics(e.position().map_or(0, |pos| pos.line() as usize), e.to_string()))?;
+            let entry  = MaskEntry{chromosome: record.chromosome, strand: record.strand};
+            if masks.inner.contains_key(&entry) {
+                return Err(MasksError::DuplicateEntry(entry))
+            }
+            let threshold = masks.inner.entry(entry).or_default();
+            threshold.set_threshold(Orientation::FivePrime,  record.five_prime);
+            threshold.set_threshold(Orientation::ThreePrime, record.three_prime);
+        }
+        masks.validate()?;
+        Ok(masks)
+    }
+
+    /// Compute the thresholds and keep track of the [`DamageProfile`]s of every entry of a misincorporation profile.
+    /// Used by [`Masks::from_misincorporations()`].
+    fn from_profile(profile: &Misincorporations, threshold: f32, options: &ThresholdOptions) -> Result<Self, MasksError> {
+        let mut threshold_positions = profile.thresholds(threshold, options);
 
         // ---- Validate misincorporation and issue warnings for any 'abnormal' frequency found.
-        let mut abnormal_frequencies = threshold_positions
-            .extrude_invalid_frequencies()
+        let invalid_positions = threshold_positions.extrude_invalid_frequencies();
+        let mut abnormal_frequencies = invalid_positions
             .iter()
             .fold(String::new(), |abnormal_freqs, pos| abnormal_freqs + &format!("{pos}\n"));
         abnormal_frequencies.pop();
@@ -127,7 +347,265 @@ impl Masks {
             })
         );
 
-        Masks::try_from(&threshold_positions)
+        let mut masks = Masks::try_from(&threshold_positions)?;
+        masks.set_profiles(profile);
+        masks.set_details(&threshold_positions, &invalid_positions);
+        Ok(masks)
+    }
+
+    /// Keep track of the [`ThresholdDetails`] of every entry, given the records found at the selected threshold 
+    /// positions, and those holding invalid frequencies. Must be called after [`Masks::set_profiles()`], since entries
+    /// which never met the threshold are solely found within the profiles.
+    fn set_details(&mut self, threshold_positions: &Misincorporations, invalid_positions: &[MisincorporationRecord]) {
+        for record in threshold_positions.iter() {
+            let entry = MaskEntry{chromosome: record.chromosome.clone(), strand: record.strand};
+            self.details.entry(entry).or_default().set(record.clone());
+        }
+        for record in invalid_positions {
+            let entry = MaskEntry{chromosome: record.chromosome.clone(), strand: record.strand};
+            self.details.entry(entry).or_default().fallback = Some(Fallback::InvalidFrequency);
+        }
+        for entry in self.profiles.keys() {
+            let details = self.details.entry(entry.clone()).or_default();
+            if details.fallback.is_none() && (details.five_prime.is_none() || details.three_prime.is_none()) {
+                details.fallback = Some(Fallback::ThresholdNeverMet);
+            }
+        }
+    }
+
+    /// Return the [`ThresholdDetails`] of a provided [`MaskEntry`], if any. Details are only available for entries 
+    /// computed from a misincorporation profile (see [`Masks::from_misincorporations()`]).
+    /// 
+    /// # Usage
+    /// ```
+    /// use std::error::Error;
+    /// use pmd_mask::mask::{Masks, MaskEntry};
+    /// use pmd_mask::genome::{ChrName, Strand, Orientation, LibraryType};
+    /// fn main() -> Result<(), Box<dyn Error>> {
+    ///     let file  = "tests/test-data/bam/dummy-MTonly/misincorporation.txt";
+    ///     let masks = Masks::from_path(&file, 0.01, LibraryType::DoubleStranded, Default::default())?;
+    ///     let entry = MaskEntry{ chromosome: ChrName::new("MT"), strand: Strand::Forward };
+    /// 
+    ///     let details   = masks.get_details(&entry).expect("Missing details");
+    ///     let threshold = masks.get(&entry).and_then(|threshold| threshold.get_threshold(&Orientation::FivePrime));
+    ///     assert_eq!(details.get(&Orientation::FivePrime).map(|record| &record.position), threshold);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn get_details(&self, entry: &MaskEntry) -> Option<&ThresholdDetails> {
+        self.details.get(entry)
+    }
+
+    /// Replace the records of every [`MaskEntry`] holding fewer than `options.min_count` observed target nucleotides at
+    /// any of the leading positions of its profile, with those of the genome-wide, pooled profile (see 
+    /// [`Misincorporations::pooled()`]). Leading positions span from the first position of each end, up to and including
+    /// the selected threshold position (see [`Misincorporations::leading_counts()`]): sparse tail positions, which never
+    /// take part in threshold selection, are thus ignored. The decision taken for each [`MaskEntry`] is logged.
+    /// 
+    /// Returns the resulting profile, along with the lowest number of observations of every pooled entry.
+    fn pool_sparse_entries<'a>(profile: &'a Misincorporations, threshold: f32, options: &ThresholdOptions) -> (Cow<'a, Misincorporations>, BTreeMap<MaskEntry, usize>) {
+        if options.min_count == 0 {
+            return (Cow::Borrowed(profile), BTreeMap::new())
+        }
+
+        // ---- Find the lowest number of observations found within the leading positions of each entry.
+        let library = profile.library();
+        let mut min_counts: BTreeMap<MaskEntry, usize> = BTreeMap::new();
+        for (record, trials) in profile.leading_counts(threshold, options) {
+            let entry = MaskEntry{chromosome: record.chromosome.clone(), strand: record.strand};
+            min_counts.entry(entry)
+                .and_modify(|count| *count = (*count).min(trials))
+                .or_insert(trials);
+        }
+
+        let pooling = if options.pool_strands { "chromosomes and strands" } else { "chromosomes" };
+        for (entry, count) in min_counts.iter() {
+            match *count < options.min_count {
+                true  => info!("{entry}: found only {count} observations at some leading positions (min-count: {}). Falling back to a profile pooled across all {pooling}.", options.min_count),
+                false => debug!("{entry}: found at least {count} observations at every leading position (min-count: {}). Using its own profile.", options.min_count),
+            }
+        }
+
+        min_counts.retain(|_, count| *count < options.min_count);
+        if min_counts.is_empty() {
+            return (Cow::Borrowed(profile), min_counts)
+        }
+
+        let pooled  = profile.pooled(options.pool_strands);
+        let records = profile.iter().zip(pooled.iter()).map(|(record, pooled)| {
+            let entry = MaskEntry{chromosome: record.chromosome.clone(), strand: record.strand};
+            match min_counts.contains_key(&entry) {
+                true  => pooled.clone(),
+                false => record.clone(),
+            }
+        }).collect();
+        (Cow::Owned(Misincorporations::new(records, library)), min_counts)
+    }
+
+    /// Keep track of the full [`DamageProfile`] of each [`MaskEntry`] found within the provided [`Misincorporations`].
+    fn set_profiles(&mut self, misincorporations: &Misincorporations) {
+        let library = misincorporations.library();
+        for record in misincorporations.iter() {
+            let entry = MaskEntry{chromosome: record.chromosome.clone(), strand: record.strand};
+            self.profiles.entry(entry)
+                .or_default()
+                .set_frequency(record.end, record.position, record.target_freq(&library));
+        }
+    }
+
+    /// Return the full [`DamageProfile`] of a provided [`MaskEntry`], if any.
+    /// 
+    /// # Usage
+    /// ```
+    /// use std::error::Error;
+    /// use pmd_mask::mask::{Masks, MaskEntry};
+    /// use pmd_mask::genome::{ChrName, Strand, Orientation, LibraryType};
+    /// fn main() -> Result<(), Box<dyn Error>> {
+    ///     let file  = "tests/test-data/bam/dummy-MTonly/misincorporation.txt";
+    ///     let masks = Masks::from_path(&file, 0.01, LibraryType::DoubleStranded, Default::default())?;
+    ///     let entry = MaskEntry{ chromosome: ChrName::new("MT"), strand: Strand::Forward };
+    /// 
+    ///     let profile = masks.get_profile(&entry).expect("Missing profile");
+    ///     assert!(!profile.get_frequencies(&Orientation::FivePrime).is_empty());
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn get_profile(&self, entry: &MaskEntry) -> Option<&DamageProfile> {
+        self.profiles.get(entry)
+    }
+
+    /// Restrict masking to the sites of a [`SitePanel`]. Candidates found outside of these sites are left untouched.
+    /// 
+    /// # Usage
+    /// ```
+    /// use std::error::Error;
+    /// use pmd_mask::mask::Masks;
+    /// use pmd_mask::panel::SitePanel;
+    /// use pmd_mask::genome::{ChrName, LibraryType};
+    /// fn main() -> Result<(), Box<dyn Error>> {
+    ///     let file      = "tests/test-data/bam/dummy-MTonly/misincorporation.txt";
+    ///     let mut masks = Masks::from_path(&file, 0.01, LibraryType::DoubleStranded, Default::default())?;
+    ///     masks.set_panel(SitePanel::from_bed_reader("MT\t100\t200\n".as_bytes())?);
+    /// 
+    ///     assert_eq!(masks.panel().map(SitePanel::len), Some(100));
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn set_panel(&mut self, panel: SitePanel) {
+        self.panel = Some(panel);
+    }
+
+    /// Return the [`SitePanel`] masking is restricted to, if any.
+    pub fn panel(&self) -> Option<&SitePanel> {
+        self.panel.as_ref()
+    }
+
+    /// Set how records are handled when their [`MaskEntry`] is absent from this collection (see [`MissingPolicy`]). 
+    /// Note that entries of a mapDamage-v2 run performed with `--merge-reference-sequences` apply to every contig 
+    /// (see [`MERGED_REFERENCE`](crate::misincorporation::MERGED_REFERENCE)), and are thus never considered missing.
+    /// 
+    /// # Usage
+    /// ```
+    /// use std::error::Error;
+    /// use pmd_mask::mask::{Masks, MissingPolicy};
+    /// use pmd_mask::genome::LibraryType;
+    /// fn main() -> Result<(), Box<dyn Error>> {
+    ///     let file      = "tests/test-data/bam/dummy-MTonly/misincorporation.txt";
+    ///     let mut masks = Masks::from_path(&file, 0.01, LibraryType::DoubleStranded, Default::default())?;
+    ///     assert_eq!(masks.missing_policy(), MissingPolicy::Mask);
+    /// 
+    ///     masks.set_missing_policy(MissingPolicy::Pooled);
+    ///     assert_eq!(masks.missing_policy(), MissingPolicy::Pooled);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn set_missing_policy(&mut self, policy: MissingPolicy) {
+        self.missing = policy;
+    }
+
+    /// Return how records are handled when their [`MaskEntry`] is absent from this collection (see [`MissingPolicy`]).
+    pub fn missing_policy(&self) -> MissingPolicy {
+        self.missing
+    }
+
+    /// Return the [`Masks`] of the genome-wide profile, pooled across all chromosomes, if any. Its entries are all
+    /// assigned to the [`MERGED_REFERENCE`](crate::misincorporation::MERGED_REFERENCE) chromosome.
+    pub fn pooled(&self) -> Option<&Masks> {
+        self.pooled.as_deref()
+    }
+
+    /// Check whether a [`MaskEntry`] was found within the misincorporation profile, i.e. whether it either holds a 
+    /// [`MaskThreshold`], or a [`DamageProfile`] (Entries which never meet the threshold only hold the latter).
+    pub fn contains(&self, entry: &MaskEntry) -> bool {
+        self.inner.contains_key(entry) || self.profiles.contains_key(entry)
+    }
+
+    /// Use a separate set of [`Masks`] for the records of a read group, identified either by its `ID`, or by its 
+    /// library (`LB`), as found within the `@RG` records of the alignment file's header. Records are then matched to 
+    /// their read group using their `RG` aux tag (see [`MaskTable::get()`]).
+    /// 
+    /// Note that only the thresholds and damage profiles of `masks` are used: masking is always restricted to the 
+    /// [`SitePanel`] of this [`Masks`], if any.
+    /// 
+    /// # Usage
+    /// ```
+    /// use std::error::Error;
+    /// use pmd_mask::mask::Masks;
+    /// use pmd_mask::genome::LibraryType;
+    /// fn main() -> Result<(), Box<dyn Error>> {
+    ///     let file      = "tests/test-data/bam/dummy-MTonly/misincorporation.txt";
+    ///     let mut masks = Masks::from_path(&file, 0.01, LibraryType::DoubleStranded, Default::default())?;
+    ///     masks.set_read_group("Illumina", Masks::from_path(&file, 0.05, LibraryType::DoubleStranded, Default::default())?);
+    /// 
+    ///     assert!(masks.get_read_group("Illumina").is_some());
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn set_read_group(&mut self, key: impl Into<String>, masks: Masks) {
+        self.read_groups.insert(key.into(), masks);
+    }
+
+    /// Return the [`Masks`] of a read group, using the `ID` or `LB` key it was registered with (see [`Masks::set_read_group()`]).
+    pub fn get_read_group(&self, key: &str) -> Option<&Masks> {
+        self.read_groups.get(key)
+    }
+
+    /// Use a separate set of [`Masks`] for the records whose length lies within a given [`LengthBin`] (see 
+    /// [`record_length()`]). Records whose length does not lie within any bin are masked using the thresholds of 
+    /// this [`Masks`]. Note that read group specific thresholds take precedence over length bins (see 
+    /// [`Masks::set_read_group()`]), and that masking is always restricted to the [`SitePanel`] of this [`Masks`], if any.
+    /// 
+    /// # Errors
+    /// Returns a [`MasksError::OverlappingLengthBins`] if `bin` overlaps any previously set [`LengthBin`].
+    /// 
+    /// # Usage
+    /// ```
+    /// use std::error::Error;
+    /// use pmd_mask::mask::{Masks, LengthBin};
+    /// use pmd_mask::genome::LibraryType;
+    /// fn main() -> Result<(), Box<dyn Error>> {
+    ///     let file      = "tests/test-data/bam/dummy-MTonly/misincorporation.txt";
+    ///     let mut masks = Masks::from_path(&file, 0.01, LibraryType::DoubleStranded, Default::default())?;
+    ///     masks.set_length_bin("0-49".parse()?, Masks::from_path(&file, 0.05, LibraryType::DoubleStranded, Default::default())?)?;
+    /// 
+    ///     assert!(masks.get_length_bin(35).is_some());
+    ///     assert!(masks.get_length_bin(50).is_none());
+    ///     assert!(masks.set_length_bin("40-".parse()?, Masks::from_path(&file, 0.05, LibraryType::DoubleStranded, Default::default())?).is_err());
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn set_length_bin(&mut self, bin: LengthBin, masks: Masks) -> Result<(), MasksError> {
+        if let Some((other, _)) = self.length_bins.iter().find(|(other, _)| other.overlaps(&bin)) {
+            return Err(MasksError::OverlappingLengthBins(bin, *other))
+        }
+        self.length_bins.push((bin, masks));
+        self.length_bins.sort_by_key(|(bin, _)| *bin);
+        Ok(())
+    }
+
+    /// Return the [`Masks`] of the [`LengthBin`] a given read `length` lies within, if any (see [`Masks::set_length_bin()`]).
+    pub fn get_length_bin(&self, length: usize) -> Option<&Masks> {
+        self.length_bins.iter().find(|(bin, _)| bin.contains(length)).map(|(_, masks)| masks)
     }
 
     /// Return the [`MaskThreshold`] of a provided [`MaskEntry`].
@@ -136,10 +614,10 @@ impl Masks {
     /// ```
     /// use std::error::Error;
     /// use pmd_mask::mask::{Masks, MaskEntry};
-    /// use pmd_mask::genome::{ChrName, Strand};
+    /// use pmd_mask::genome::{ChrName, Strand, LibraryType};
     /// fn main() -> Result<(), Box<dyn Error>> {
     ///     let file  = "tests/test-data/bam/dummy-MTonly/misincorporation.txt";
-    ///     let masks = Masks::from_path(&file, 0.01)?;
+    ///     let masks = Masks::from_path(&file, 0.01, LibraryType::DoubleStranded, Default::default())?;
     ///     let entry = MaskEntry{ chromosome: ChrName::new("5"), strand: Strand::Reverse };
     /// 
     ///     let threshold = masks.get(&entry);
@@ -169,10 +647,10 @@ impl Masks {
     /// ```
     /// use std::error::Error;
     /// use pmd_mask::mask::{Masks, MaskEntry};
-    /// use pmd_mask::genome::{ChrName, Strand};
+    /// use pmd_mask::genome::{ChrName, Strand, LibraryType};
     /// fn main() -> Result<(), Box<dyn Error>> {
     ///     let file  = "tests/test-data/bam/dummy-MTonly/misincorporation.txt";
-    ///     let masks = Masks::from_path(&file, 0.01)?;
+    ///     let masks = Masks::from_path(&file, 0.01, LibraryType::DoubleStranded, Default::default())?;
     ///
     ///     // ---- Write contents into cursor (or any output file)
     ///     let mut output = std::io::Cursor::new(Vec::new());
@@ -246,9 +724,50 @@ mod test {
         }
     }
 
+    #[test]
+    fn pool_sparse_entries() {
+        // ---- chrY holds too few counts, and fails to display any damage signal.
+        let mut records = Vec::new();
+        for (chr, counts) in [("chr1", 1000), ("chrY", 10)] {
+            for (pos, freq) in [(1, 0.3), (2, 0.2), (3, 0.0)] {
+                let freq = if chr == "chrY" { 0.0 } else { freq };
+                records.push(mis_record!(chr, Forward, FivePrime, pos, counts, freq));
+            }
+            records.push(mis_record!(chr, Forward, ThreePrime, 1, counts, 0.0));
+        }
+        let profile = Misincorporations::new(records, LibraryType::DoubleStranded);
+        let chr_y   = MaskEntry{chromosome: ChrName::new("chrY"), strand: Forward};
+
+        let masks = Masks::from_misincorporations(&profile, 0.01, &ThresholdOptions::default()).expect("Invalid Masks");
+        assert_eq!(masks.get(&chr_y).and_then(|mask| mask.get_threshold(&FivePrime)), Some(&Position::new(1)));
+
+        // ---- With a minimum count requirement, chrY falls back to the genome-wide profile.
+        for min_count in [6, 500] {
+            let options = ThresholdOptions{ min_count, ..Default::default() };
+            let masks   = Masks::from_misincorporations(&profile, 0.01, &options).expect("Invalid Masks");
+            assert_eq!(masks.get(&chr_y).and_then(|mask| mask.get_threshold(&FivePrime)), Some(&Position::new(3)));
+
+            // ---- chr1 keeps its own profile.
+            let chr_1 = MaskEntry{chromosome: ChrName::new("chr1"), strand: Forward};
+            assert_eq!(masks.get(&chr_1).and_then(|mask| mask.get_threshold(&FivePrime)), Some(&Position::new(3)));
+        }
+
+        // ---- Sparse tail positions, lying beyond the selected threshold, are ignored.
+        let records = [(1, 1000, 0.3), (2, 1000, 0.0), (3, 10, 0.0)].into_iter()
+            .map(|(pos, counts, freq)| mis_record!("chr1", Forward, FivePrime, pos, counts, freq))
+            .chain([mis_record!("chr1", Forward, ThreePrime, 1, 1000, 0.0)])
+            .collect();
+        let profile = Misincorporations::new(records, LibraryType::DoubleStranded);
+        let options = ThresholdOptions{ min_count: 500, ..Default::default() };
+        let masks   = Masks::from_misincorporations(&profile, 0.01, &options).expect("Invalid Masks");
+        let chr_1   = MaskEntry{chromosome: ChrName::new("chr1"), strand: Forward};
+        assert!(masks.get_details(&chr_1).and_then(|details| details.fallback.as_ref()).is_none());
+        assert_eq!(masks.get(&chr_1).and_then(|mask| mask.get_threshold(&FivePrime)), Some(&Position::new(2)));
+    }
+
     #[test]
     fn get_threshold() {
-        let mut masks = Masks{inner: HashMap::new()};
+        let mut masks = Masks::default();
 
         let entry = MaskEntry{chromosome: ChrName::new("chr1"), strand: Forward}; 
         masks.inner.insert(entry.clone(), MaskThreshold::default());
@@ -283,4 +802,50 @@ mod test {
         }
     }
 
+    #[test]
+    fn from_metrics_reader() {
+        let metrics = "# Threshold strategy: first-below\n# Confidence interval: wilson (0.95)\n\
+                       Chr\tStd\t5p\t3p\n\
+                       chr1\t+\t10\tNA\n\
+                       chr1\t-\t1\t7\n\
+                       # Reads processed: 10 (masked: 2)\n";
+        let masks = Masks::from_metrics_reader(metrics.as_bytes()).expect("Failed to parse metrics");
+        assert_eq!(masks.inner.len(), 2);
+
+        let forward = masks.get(&MaskEntry{chromosome: ChrName::new("chr1"), strand: Forward}).expect("Missing entry");
+        assert_eq!(forward.get_threshold(&FivePrime), Some(&Position::new(10)));
+        assert_eq!(forward.get_threshold(&ThreePrime), Some(&Position::new(usize::MAX)));
+
+        // ---- Writing reloaded thresholds yields the same table.
+        let mut output = Vec::new();
+        masks.write(&mut output).expect("Failed to write metrics");
+        assert_eq!(String::from_utf8(output).expect("Invalid UTF-8"), "Chr\tStd\t5p\t3p\nchr1\t+\t10\tNA\nchr1\t-\t1\t7\n");
+
+        // ---- Invalid positions and duplicate entries are rejected.
+        for invalid in ["Chr\tStd\t5p\t3p\nchr1\t+\t0\t7\n", "Chr\tStd\t5p\t3p\nchr1\t+\tten\t7\n", "Chr\tStd\t5p\t3p\nchr1\t*\t10\t7\n"] {
+            assert!(matches!(Masks::from_metrics_reader(invalid.as_bytes()), Err(MasksError::DeserializeMetrics(2, _))));
+        }
+        let commented = "# Threshold: 0.01\n# Strategy: first-below\nChr\tStd\t5p\t3p\nchr1\t+\t10\t7\nchr1\t-\tten\t7\n";
+        assert!(matches!(Masks::from_metrics_reader(commented.as_bytes()), Err(MasksError::DeserializeMetrics(5, _))));
+        let duplicate = "Chr\tStd\t5p\t3p\nchr1\t+\t10\t7\nchr1\t+\t3\t7\n";
+        assert!(matches!(Masks::from_metrics_reader(duplicate.as_bytes()), Err(MasksError::DuplicateEntry(_))));
+    }
+
+    #[test]
+    fn serde_roundtrip() {
+        let mut masks = Masks::try_from(&dummy_misincorporations()).expect("Invalid Misincorporations");
+        masks.set_missing_policy(MissingPolicy::Skip);
+        masks.set_read_group("rg1", Masks::try_from(&dummy_misincorporations()).expect("Invalid Misincorporations"));
+
+        let json     = serde_json::to_string(&masks).expect("Failed to serialize masks");
+        let restored = serde_json::from_str::<Masks>(&json).expect("Failed to deserialize masks");
+        assert_eq!(restored.inner, masks.inner);
+        assert_eq!(restored.read_groups["rg1"].inner, masks.read_groups["rg1"].inner);
+        assert_eq!(restored.missing_policy(), MissingPolicy::Skip);
+        assert!(restored.length_bins.is_empty() && restored.pooled.is_none());
+
+        // ---- Thresholds lacking an orientation are rejected.
+        let invalid = json.replacen("\"3p\"", "\"5p\"", 1);
+        assert!(serde_json::from_str::<Masks>(&invalid).is_err());
+    }
 }
\ No newline at end of file
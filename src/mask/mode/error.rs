@@ -0,0 +1,8 @@
+use thiserror::Error;
+
+/// Error type enum for [`crate::mask::MaskMode`]
+#[derive(Debug, Error, PartialEq)]
+pub enum MaskModeError {
+    #[error("Failed to parse string value '{0}' into a valid masking mode. Accepted values: 'hard|soft|rescale|softclip|annotate'")]
+    ParseMaskMode(String),
+}
@@ -0,0 +1,96 @@
+use std::{fmt::{self, Display, Formatter}, str::FromStr};
+
+mod error;
+pub use error::MaskModeError;
+
+/// Defines how masking candidates are tampered with. Possible variants:
+/// - [`MaskMode::Hard`]|`'hard'`: replace the nucleotide with a replacement character (usually `N`), and downscale its base quality.
+/// - [`MaskMode::Soft`]|`'soft'`: keep the original nucleotide, and only downscale its base quality.
+/// - [`MaskMode::Rescale`]|`'rescale'`: keep the original nucleotide, and rescale its base quality according to its 
+///   position-specific probability of being a deamination product (see [`crate::mask::DamageProfile`]).
+/// - [`MaskMode::SoftClip`]|`'softclip'`: soft-clip the read from either end, up to the last masking candidate 
+///   (see [`crate::clip::soft_clip()`]).
+/// - [`MaskMode::Annotate`]|`'annotate'`: keep the original nucleotide and base quality, and only list the read offsets
+///   which would have been masked within aux tags (see [`crate::annotate::annotate()`]).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum MaskMode {
+    #[default]
+    Hard,
+    Soft,
+    Rescale,
+    SoftClip,
+    Annotate,
+}
+
+impl AsRef<str> for MaskMode {
+    /// Obtain the [`str`] representation of a [`MaskMode`]
+    /// ```
+    /// use pmd_mask::mask::MaskMode;
+    /// 
+    /// assert_eq!(MaskMode::Hard.as_ref(), "hard");
+    /// assert_eq!(MaskMode::Soft.as_ref(), "soft");
+    /// ```
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Hard     => "hard",
+            Self::Soft     => "soft",
+            Self::Rescale  => "rescale",
+            Self::SoftClip => "softclip",
+            Self::Annotate => "annotate",
+        }
+    }
+}
+
+impl Display for MaskMode {
+    /// Obtain a formatted [`String`] representation of a [`MaskMode`].
+    /// ```
+    /// # use pmd_mask::mask::MaskMode;
+    /// assert_eq!(&format!("{: ^6}", MaskMode::Soft), " soft ");
+    /// ```
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.as_ref().fmt(f)
+    }
+}
+
+impl FromStr for MaskMode {
+    type Err = MaskModeError;
+
+    /// Attempt to convert a string sequence into a [`MaskMode`]. Matching is case-insensitive.
+    /// 
+    /// # Errors
+    /// Returns a [`MaskModeError::ParseMaskMode`] upon encountering any value that is neither `hard`, `soft`, `rescale`, `softclip` nor `annotate`.
+    /// ```
+    /// use pmd_mask::mask::MaskMode;
+    /// 
+    /// assert_eq!("Soft".parse::<MaskMode>(), Ok(MaskMode::Soft));
+    /// assert!("medium".parse::<MaskMode>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "hard"     => Ok(Self::Hard),
+            "soft"     => Ok(Self::Soft),
+            "rescale"  => Ok(Self::Rescale),
+            "softclip" => Ok(Self::SoftClip),
+            "annotate" => Ok(Self::Annotate),
+            _          => Err(Self::Err::ParseMaskMode(s.to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn display() {
+        assert_eq!("hard|soft|rescale|softclip|annotate", format!("{}|{}|{}|{}|{}", MaskMode::Hard, MaskMode::Soft, MaskMode::Rescale, MaskMode::SoftClip, MaskMode::Annotate));
+    }
+
+    #[test]
+    fn from_str() {
+        for (input, want) in [("hard", MaskMode::Hard), ("HARD", MaskMode::Hard), ("soft", MaskMode::Soft), ("sOfT", MaskMode::Soft), ("Rescale", MaskMode::Rescale), ("SoftClip", MaskMode::SoftClip), ("ANNOTATE", MaskMode::Annotate)] {
+            assert_eq!(MaskMode::from_str(input), Ok(want));
+        }
+        assert_eq!(MaskMode::from_str("h"), Err(MaskModeError::ParseMaskMode("h".to_string())));
+    }
+}
@@ -0,0 +1,92 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::genome::LibraryType;
+use super::MaskMode;
+
+/// User-defined parameters dictating how [`crate::apply_pmd_mask()`] should mask reads.
+/// 
+/// - `library`    : library preparation protocol. Dictates which reference nucleotide is a masking candidate
+///   (see [`LibraryType::target_nucleotide()`]).
+/// - `mode`       : whether the nucleotides of masked candidates should be replaced or kept (see [`MaskMode`]).
+/// - `replacement`: character used to replace masked nucleotides, when using [`MaskMode::Hard`].
+/// - `quality`    : Phred base quality assigned to masked nucleotides.
+/// - `fragment_aware`: only mask read ends which correspond to actual ends of the sequenced fragment, using the FLAG and 
+///   mate information of paired-end reads.
+/// - `mismatches_only`: only mask candidates where the read carries the deamination product of the targeted reference
+///   nucleotide (i.e. a `T` over a reference `C`, or an `A` over a reference `G`).
+/// - `reversible`: keep the original nucleotides, base qualities and alignment of altered records within aux tags 
+///   (see [`crate::reversible::store()`]).
+/// 
+/// # Usage
+/// ```
+/// use pmd_mask::mask::{MaskOptions, MaskMode};
+/// 
+/// let options = MaskOptions{ mode: MaskMode::Soft, quality: 2, ..Default::default() };
+/// assert_eq!(options.replacement, b'N');
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaskOptions {
+    pub library    : LibraryType,
+    pub mode       : MaskMode,
+    pub replacement: u8,
+    pub quality    : u8,
+    pub fragment_aware: bool,
+    pub mismatches_only: bool,
+    pub reversible: bool,
+}
+
+impl Default for MaskOptions {
+    /// Create a default set of [`MaskOptions`], i.e. hard-masking of double stranded libraries, where
+    /// candidates are replaced with `N`, and their base quality set to `0`.
+    fn default() -> Self {
+        Self { library: LibraryType::default(), mode: MaskMode::default(), replacement: b'N', quality: 0, fragment_aware: false, mismatches_only: false, reversible: false }
+    }
+}
+
+impl MaskOptions {
+    /// Mask a single nucleotide and its corresponding Phred base quality, according to the requested [`MaskMode`].
+    /// Note that this is a no-op for [`MaskMode::Rescale`], [`MaskMode::SoftClip`] and [`MaskMode::Annotate`], since 
+    /// these do not alter individual nucleotides with set values.
+    /// 
+    /// # Usage
+    /// ```
+    /// use pmd_mask::mask::{MaskOptions, MaskMode};
+    /// 
+    /// let (mut base, mut qual) = (b'T', 37);
+    /// MaskOptions{ mode: MaskMode::Soft, quality: 5, ..Default::default() }.mask(&mut base, &mut qual);
+    /// assert_eq!((base, qual), (b'T', 5));
+    /// 
+    /// MaskOptions::default().mask(&mut base, &mut qual);
+    /// assert_eq!((base, qual), (b'N', 0));
+    /// ```
+    #[inline]
+    pub fn mask(&self, base: &mut u8, qual: &mut u8) {
+        match self.mode {
+            MaskMode::Hard => {
+                *base = self.replacement;
+                *qual = self.quality;
+            },
+            MaskMode::Soft => *qual = self.quality,
+            MaskMode::Rescale | MaskMode::SoftClip | MaskMode::Annotate => {},
+        }
+    }
+}
+
+impl Display for MaskOptions {
+    /// Return a formatted [`String`] representation of [`MaskOptions`]
+    /// ```
+    /// use pmd_mask::mask::MaskOptions;
+    /// assert_eq!(format!("{}", MaskOptions::default()), "library: double | mode: hard | replacement: N | quality: 0 | fragment-aware: false | mismatches-only: false | reversible: false");
+    /// ```
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        format!("library: {} | mode: {} | replacement: {} | quality: {} | fragment-aware: {} | mismatches-only: {} | reversible: {}", 
+            self.library, 
+            self.mode,
+            self.replacement as char,
+            self.quality,
+            self.fragment_aware,
+            self.mismatches_only,
+            self.reversible,
+        ).fmt(f)
+    }
+}
@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+
+use crate::genome::{Orientation, Position};
+
+/// Full, position-specific misincorporation frequency curve of a [`crate::mask::MaskEntry`], for each
+/// [`Orientation`]. This is mainly used when rescaling base qualities (see [`crate::mask::MaskMode::Rescale`]).
+/// 
+/// Internally, [`DamageProfile`] is a [`HashMap`] where keys are [`Orientation`]s, and values are the misincorporation
+/// frequencies found at each relative position, starting from the corresponding end of the read. i.e. index `0` 
+/// contains the frequency of position `1`.
+/// 
+/// # Usage
+/// ```
+/// use pmd_mask::mask::DamageProfile;
+/// use pmd_mask::genome::{Orientation, Position};
+/// 
+/// let mut profile = DamageProfile::default();
+/// profile.set_frequency(Orientation::FivePrime, Position::new(2), 0.15);
+/// 
+/// assert_eq!(profile.get_frequencies(&Orientation::FivePrime), &[0.0, 0.15]);
+/// assert!(profile.get_frequencies(&Orientation::ThreePrime).is_empty());
+/// ```
+#[derive(Debug, Default, PartialEq)]
+pub struct DamageProfile { inner: HashMap<Orientation, Vec<f32>> }
+
+impl DamageProfile {
+    /// Set the misincorporation frequency of a given `orientation`, at a given 1-based relative `position`.
+    /// Any missing position preceding `position` is set to `0.0`.
+    /// 
+    /// Invalid frequencies (`NaN`, infinite or negative values) are considered as `0.0`.
+    pub fn set_frequency(&mut self, orientation: Orientation, position: Position, frequency: f32) {
+        let index = position.inner().saturating_sub(1);
+        let curve = self.inner.entry(orientation).or_default();
+        if curve.len() <= index {
+            curve.resize(index + 1, 0.0);
+        }
+        curve[index] = if frequency.is_finite() && frequency.is_sign_positive() { frequency } else { 0.0 };
+    }
+
+    /// Retrieve the misincorporation frequencies of a given `orientation`, indexed by their relative position, 
+    /// starting from the corresponding end of a read. Returns an empty slice if this orientation is unknown.
+    pub fn get_frequencies(&self, orientation: &Orientation) -> &[f32] {
+        self.inner.get(orientation).map(Vec::as_slice).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn set_frequency() {
+        let mut profile = DamageProfile::default();
+        profile.set_frequency(Orientation::ThreePrime, Position::new(3), 0.3);
+        profile.set_frequency(Orientation::ThreePrime, Position::new(1), 0.1);
+        assert_eq!(profile.get_frequencies(&Orientation::ThreePrime), &[0.1, 0.0, 0.3]);
+        assert!(profile.get_frequencies(&Orientation::FivePrime).is_empty());
+    }
+
+    #[test]
+    fn invalid_frequencies() {
+        let mut profile = DamageProfile::default();
+        for (pos, freq) in [f32::NAN, f32::INFINITY, -0.1].into_iter().enumerate() {
+            profile.set_frequency(Orientation::FivePrime, Position::new(pos + 1), freq);
+        }
+        assert_eq!(profile.get_frequencies(&Orientation::FivePrime), &[0.0, 0.0, 0.0]);
+    }
+}
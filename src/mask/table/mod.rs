@@ -0,0 +1,320 @@
+use std::{str, collections::HashMap};
+
+use rust_htslib::bam::{self, HeaderView, Record, record::Aux};
+use log::{debug, info, warn};
+
+use crate::genome::{ChrName, Strand};
+use crate::misincorporation::MERGED_REFERENCE;
+use crate::panel::Site;
+use super::{Masks, MaskEntry, MaskThreshold, DamageProfile, ThresholdDetails, MasksError, LengthBin, MissingPolicy, record_length};
+
+/// A resolved [`MaskEntry`], along with its (optional) [`MaskThreshold`], [`DamageProfile`] and [`ThresholdDetails`].
+/// 
+/// When masking is restricted to a [`SitePanel`](crate::panel::SitePanel), `sites` contains the panel [`Site`]s of
+/// the entry's chromosome (possibly none). `sites` is [`None`] when no panel was provided.
+/// 
+/// When the entry is absent from the [`Masks`], `fallback` contains the [`MissingPolicy`] applied to its records. 
+/// Its `threshold` and `profile` are then those of the genome-wide profile when using [`MissingPolicy::Pooled`], or 
+/// [`None`] otherwise.
+/// 
+/// [`ResolvedMask`]s are the building blocks of a [`MaskTable`].
+#[derive(Debug)]
+pub struct ResolvedMask<'a> {
+    pub entry    : MaskEntry,
+    pub threshold: Option<&'a MaskThreshold>,
+    pub profile  : Option<&'a DamageProfile>,
+    pub details  : Option<&'a ThresholdDetails>,
+    pub sites    : Option<&'a [Site]>,
+    pub fallback : Option<MissingPolicy>,
+}
+
+/// A [`Masks`] collection, resolved once against the target ids (`tid`) of an alignment file's [`HeaderView`].
+/// 
+/// Entries are stored within a flat table, indexed by `tid` and [`Strand`], thus allowing O(1), allocation-free
+/// lookups for each [`Record`] (see [`MaskTable::get()`]). This spares us from decoding the chromosome name of every
+/// record into a [`ChrName`] and hashing it when iterating over an alignment file.
+/// 
+/// When [`Masks`] define separate thresholds for some read groups (see [`Masks::set_read_group()`]), an additional
+/// table is resolved for each matching `@RG` record of the header, and records are dispatched according to their `RG`
+/// aux tag. Similarly, an additional table is resolved for each [`LengthBin`] (see [`Masks::set_length_bin()`]).
+/// 
+/// # Usage
+/// ```
+/// use std::error::Error;
+/// use rust_htslib::bam::{self, Read};
+/// use pmd_mask::mask::Masks;
+/// use pmd_mask::genome::LibraryType;
+/// fn main() -> Result<(), Box<dyn Error>> {
+///     let masks  = Masks::from_path("tests/test-data/bam/dummy-MTonly/misincorporation.txt", 0.01, LibraryType::DoubleStranded, Default::default())?;
+///     let mut bam = bam::Reader::from_path("tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam")?;
+///     let table  = masks.resolve(bam.header())?;
+/// 
+///     let record   = bam.records().next().expect("Empty bam")?;
+///     let resolved = table.get(&record).expect("Unplaced record");
+///     assert_eq!(resolved.entry.chromosome.inner(), "MT");
+///     assert!(resolved.threshold.is_some());
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct MaskTable<'a> { 
+    inner      : Vec<ResolvedMask<'a>>,
+    groups     : Vec<Vec<ResolvedMask<'a>>>,
+    read_groups: HashMap<String, usize>,
+    length_bins: Vec<(LengthBin, usize)>,
+}
+
+impl<'a> MaskTable<'a> {
+    /// Resolve every [`MaskEntry`] of a [`Masks`] collection against the target names of a [`HeaderView`]. 
+    /// Contigs which are absent from the [`Masks`] are kept within the table, and use the entry of the merged
+    /// reference, if any (see [`MERGED_REFERENCE`]). Otherwise, their records are handled according to the
+    /// [`MissingPolicy`] of the [`Masks`] (see [`Masks::set_missing_policy()`]).
+    /// 
+    /// Read groups of the [`Masks`] are matched against the `ID`, then the `LB` of every `@RG` header record. 
+    /// A warning is emitted for any read group key which does not match any `@RG` record.
+    /// 
+    /// # Errors
+    /// Returns a [`MasksError::ParseHeader`] if any target name of the header is not valid UTF-8.
+    pub fn new(masks: &'a Masks, header_view: &HeaderView) -> Result<Self, MasksError> {
+        let inner = Self::resolve_targets(masks, masks, header_view)?;
+        let missing = inner.iter().filter(|resolved| resolved.fallback.is_some()).count();
+        if missing > 0 {
+            info!("{missing} chromosome and strand entries of the alignment file's header are absent from the misincorporation profile. Applying the '{}' policy to their records.", masks.missing_policy());
+        }
+
+        let mut table = Self{inner, groups: Vec::new(), read_groups: HashMap::new(), length_bins: Vec::new()};
+        for (bin, bin_masks) in masks.length_bins.iter() {
+            table.groups.push(Self::resolve_targets(bin_masks, masks, header_view)?);
+            table.length_bins.push((*bin, table.groups.len() - 1));
+        }
+
+        if masks.read_groups.is_empty() {
+            return Ok(table)
+        }
+
+        let header  = bam::Header::from_template(header_view).to_hashmap();
+        let records = header.get("RG").map(Vec::as_slice).unwrap_or_default();
+        let mut keys: HashMap<&str, usize> = HashMap::new();
+        for record in records {
+            let Some(id) = record.get("ID") else { continue };
+            let key = [Some(id), record.get("LB")].into_iter().flatten().find(|key| masks.read_groups.contains_key(key.as_str()));
+            let Some(key) = key else {
+                debug!("Read group {id} does not match any read group specific thresholds. Using default thresholds.");
+                continue
+            };
+            debug!("Using {key} thresholds for read group {id}");
+            let index = match keys.get(key.as_str()) {
+                Some(index) => *index,
+                None        => {
+                    table.groups.push(Self::resolve_targets(&masks.read_groups[key], masks, header_view)?);
+                    keys.insert(key, table.groups.len() - 1);
+                    table.groups.len() - 1
+                }
+            };
+            table.read_groups.insert(id.clone(), index);
+        }
+
+        for key in masks.read_groups.keys().filter(|key| !keys.contains_key(key.as_str())) {
+            warn!("Read group thresholds '{key}' do not match the ID or LB of any @RG header record, and will not be used.");
+        }
+        Ok(table)
+    }
+
+    /// Resolve the thresholds and profiles of `masks` against every target of the header, using the panel and 
+    /// [`MissingPolicy`] of `root`.
+    fn resolve_targets(masks: &'a Masks, root: &'a Masks, header_view: &HeaderView) -> Result<Vec<ResolvedMask<'a>>, MasksError> {
+        let mut inner = Vec::with_capacity(header_view.target_count() as usize * 2);
+        for name in header_view.target_names() {
+            let chromosome = ChrName::new(str::from_utf8(name).map_err(MasksError::ParseHeader)?);
+            let sites      = root.panel().map(|panel| panel.get(&chromosome).unwrap_or_default());
+            for strand in [Strand::Forward, Strand::Reverse] {
+                let entry  = MaskEntry{chromosome: chromosome.clone(), strand};
+                let merged = MaskEntry{chromosome: ChrName::new(MERGED_REFERENCE), strand};
+                let (source, key, fallback) = if masks.contains(&entry) {
+                    (Some(masks), &entry, None)
+                } else if masks.contains(&merged) {
+                    (Some(masks), &merged, None)
+                } else {
+                    debug!("{entry} is absent from the misincorporation profile. Applying the '{}' policy to its records.", root.missing_policy());
+                    let pooled = masks.pooled().filter(|_| root.missing_policy() == MissingPolicy::Pooled);
+                    (pooled, &merged, Some(root.missing_policy()))
+                };
+                let threshold = source.and_then(|masks| masks.get(key));
+                let profile   = source.and_then(|masks| masks.get_profile(key));
+                let details   = source.and_then(|masks| masks.get_details(key));
+                inner.push(ResolvedMask{threshold, profile, details, sites, entry, fallback});
+            }
+        }
+        Ok(inner)
+    }
+
+    /// Retrieve the [`ResolvedMask`] of a given [`Record`], using its `tid` and strand orientation. If the record 
+    /// belongs to a read group with specific thresholds (according to its `RG` aux tag), these are used instead. 
+    /// Otherwise, the thresholds of the [`LengthBin`] its length lies within are used, if any (see [`record_length()`]).
+    /// Returns [`None`] if the record is unplaced (i.e. `tid == -1`), or if its `tid` is absent from the header.
+    #[inline]
+    pub fn get(&self, record: &Record) -> Option<&ResolvedMask<'a>> {
+        let tid = usize::try_from(record.tid()).ok()?;
+        let group = self.read_group(record).or_else(|| self.length_bin(record));
+        let table = group.map_or(&self.inner, |index| &self.groups[index]);
+        table.get(tid * 2 + record.is_reverse() as usize)
+    }
+
+    /// Iterate over the default [`ResolvedMask`]s of every target of the header, i.e. excluding those of read groups 
+    /// and length bins. Entries are sorted by `tid`, then strand ([`Strand::Forward`] first).
+    pub fn iter(&self) -> impl Iterator<Item = &ResolvedMask<'a>> {
+        self.inner.iter()
+    }
+
+    /// Return the index of the read group specific table of a record, if any.
+    #[inline]
+    fn read_group(&self, record: &Record) -> Option<usize> {
+        if self.read_groups.is_empty() {
+            return None
+        }
+        match record.aux(b"RG") {
+            Ok(Aux::String(read_group)) => self.read_groups.get(read_group).copied(),
+            _                           => None,
+        }
+    }
+
+    /// Return the index of the length bin specific table of a record, if any.
+    #[inline]
+    fn length_bin(&self, record: &Record) -> Option<usize> {
+        if self.length_bins.is_empty() {
+            return None
+        }
+        let length = record_length(record);
+        self.length_bins.iter().find(|(bin, _)| bin.contains(length)).map(|(_, index)| *index)
+    }
+}
+
+impl Masks {
+    /// Resolve this [`Masks`] collection against the target ids of a [`HeaderView`] (see [`MaskTable`]).
+    /// 
+    /// # Errors
+    /// Bubbles out any error arising from [`MaskTable::new()`].
+    pub fn resolve(&self, header_view: &HeaderView) -> Result<MaskTable<'_>, MasksError> {
+        MaskTable::new(self, header_view)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mask::dummy::dummy_bam;
+    use crate::genome::{Orientation, Position};
+
+    #[test]
+    fn resolve_by_tid_and_strand() {
+        let mut masks = Masks::default();
+        let mut threshold = MaskThreshold::default();
+        threshold.set_threshold(Orientation::FivePrime, Position::new(5));
+        masks.inner.insert(MaskEntry{chromosome: ChrName::new("chr1"), strand: Strand::Reverse}, threshold);
+
+        let (header, forward) = dummy_bam(Strand::Forward, 100);
+        let (_, reverse)      = dummy_bam(Strand::Reverse, 100);
+        let header_view       = HeaderView::from_header(&header);
+        let table             = masks.resolve(&header_view).expect("Failed to resolve masks");
+
+        let got = table.get(&forward).expect("Missing forward entry");
+        assert_eq!(got.entry, MaskEntry{chromosome: ChrName::new("chr1"), strand: Strand::Forward});
+        assert!(got.threshold.is_none());
+
+        let got = table.get(&reverse).expect("Missing reverse entry");
+        assert_eq!(got.entry, MaskEntry{chromosome: ChrName::new("chr1"), strand: Strand::Reverse});
+        assert_eq!(got.threshold.and_then(|t| t.get_threshold(&Orientation::FivePrime)), Some(&Position::new(5)));
+    }
+
+    #[test]
+    fn resolve_read_groups() {
+        let read_group_masks = || {
+            let mut threshold = MaskThreshold::default();
+            threshold.set_threshold(Orientation::FivePrime, Position::new(5));
+            let mut masks = Masks::default();
+            masks.inner.insert(MaskEntry{chromosome: ChrName::new("chr1"), strand: Strand::Forward}, threshold);
+            masks
+        };
+        let mut masks = Masks::default();
+        masks.set_read_group("lib1", read_group_masks());
+        masks.set_read_group("unknown", read_group_masks());
+
+        let (mut header, mut record) = dummy_bam(Strand::Forward, 100);
+        header.push_record(bam::header::HeaderRecord::new(b"RG").push_tag(b"ID", "rg1").push_tag(b"LB", "lib1"));
+        header.push_record(bam::header::HeaderRecord::new(b"RG").push_tag(b"ID", "rg2").push_tag(b"LB", "lib2"));
+        let table = masks.resolve(&HeaderView::from_header(&header)).expect("Failed to resolve masks");
+
+        // ---- Records without a read group, or from a read group without specific thresholds use default thresholds.
+        assert!(table.get(&record).expect("Missing entry").threshold.is_none());
+        record.push_aux(b"RG", Aux::String("rg2")).expect("Failed to push RG tag");
+        assert!(table.get(&record).expect("Missing entry").threshold.is_none());
+
+        // ---- Records of rg1 are matched through the library of their read group.
+        record.remove_aux(b"RG").expect("Failed to remove RG tag");
+        record.push_aux(b"RG", Aux::String("rg1")).expect("Failed to push RG tag");
+        let got = table.get(&record).expect("Missing entry");
+        assert_eq!(got.threshold.and_then(|t| t.get_threshold(&Orientation::FivePrime)), Some(&Position::new(5)));
+    }
+
+    #[test]
+    fn resolve_length_bins() {
+        let mut short = Masks::default();
+        let mut threshold = MaskThreshold::default();
+        threshold.set_threshold(Orientation::FivePrime, Position::new(5));
+        short.inner.insert(MaskEntry{chromosome: ChrName::new("chr1"), strand: Strand::Forward}, threshold);
+
+        let mut masks = Masks::default();
+        masks.set_length_bin(LengthBin{min: 0, max: Some(4)}, short).expect("Failed to set length bin");
+
+        let (header, mut record) = dummy_bam(Strand::Forward, 100); // 4bp long record.
+        let table = masks.resolve(&HeaderView::from_header(&header)).expect("Failed to resolve masks");
+        assert!(table.get(&record).expect("Missing entry").threshold.is_some());
+
+        record.set(b"*", None, b"ATCGA", &[37; 5]);
+        assert!(table.get(&record).expect("Missing entry").threshold.is_none());
+    }
+
+    #[test]
+    fn resolve_missing_entries() {
+        let masks_of = |chromosome: &str, position: usize| {
+            let mut threshold = MaskThreshold::default();
+            threshold.set_threshold(Orientation::FivePrime, Position::new(position));
+            let mut masks = Masks::default();
+            masks.inner.insert(MaskEntry{chromosome: ChrName::new(chromosome), strand: Strand::Forward}, threshold);
+            masks
+        };
+        let (header, record) = dummy_bam(Strand::Forward, 100);
+        let header_view      = HeaderView::from_header(&header);
+        let five_prime       = |resolved: &ResolvedMask| resolved.threshold.and_then(|t| t.get_threshold(&Orientation::FivePrime)).map(|pos| pos.inner());
+
+        // ---- chr1 is missing: only the pooled policy provides thresholds, taken from the genome-wide profile.
+        let mut masks = masks_of("chr2", 5);
+        masks.pooled  = Some(Box::new(masks_of(MERGED_REFERENCE, 7)));
+        for policy in [MissingPolicy::Mask, MissingPolicy::Skip, MissingPolicy::Pooled, MissingPolicy::Fail] {
+            masks.set_missing_policy(policy);
+            let table = masks.resolve(&header_view).expect("Failed to resolve masks");
+            let got   = table.get(&record).expect("Missing entry");
+            assert_eq!(got.fallback, Some(policy));
+            assert_eq!(five_prime(got), (policy == MissingPolicy::Pooled).then_some(7));
+        }
+
+        // ---- Merged entries apply to every contig, and are never considered missing.
+        let mut masks = masks_of(MERGED_REFERENCE, 3);
+        masks.set_missing_policy(MissingPolicy::Fail);
+        let table = masks.resolve(&header_view).expect("Failed to resolve masks");
+        let got   = table.get(&record).expect("Missing entry");
+        assert_eq!((got.fallback, five_prime(got)), (None, Some(3)));
+    }
+
+    #[test]
+    fn resolve_unplaced() {
+        let masks = Masks::default();
+        let (header, mut record) = dummy_bam(Strand::Forward, 100);
+        let table = masks.resolve(&HeaderView::from_header(&header)).expect("Failed to resolve masks");
+
+        record.set_tid(-1);
+        assert!(table.get(&record).is_none());
+        record.set_tid(1);
+        assert!(table.get(&record).is_none());
+    }
+}
@@ -1,5 +1,7 @@
 use std::{fmt::{self, Display, Formatter}, collections::HashMap};
 
+use serde::{Serialize, Serializer, Deserialize};
+
 use crate::genome::{Orientation, Position};
 
 mod error;
@@ -24,7 +26,10 @@ pub(crate) static ORIENTATIONS: [Orientation; 2] = [Orientation::FivePrime, Orie
 /// [`MaskThreshold`]s are internally used by [`crate::mask::Masks`] structs as values within an internal
 /// [`HashMap`](`std::collections::HashMap`)
 /// 
-#[derive(Debug, PartialEq)]
+/// [`MaskThreshold`]s are (de)serialized as a map of orientations and positions (e.g. `{"5p": 12, "3p": 10}`). 
+/// Deserialization fails whenever the map does not contain exactly these two keys (see [`MaskThreshold::validate()`]).
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(try_from = "HashMap<Orientation, Position>")]
 pub struct MaskThreshold { pub(crate) inner: HashMap<Orientation, Position> }
 
 impl Default for MaskThreshold {
@@ -53,6 +58,25 @@ impl Default for MaskThreshold {
 
 }
 
+impl TryFrom<HashMap<Orientation, Position>> for MaskThreshold {
+    type Error = MaskThresholdError;
+
+    /// Attempt to build a [`MaskThreshold`] from a raw [`HashMap`] of orientations and positions.
+    /// 
+    /// # Errors
+    /// Returns a [`MaskThresholdError::ValidateThresh`] if `inner` does not contain both orientations.
+    fn try_from(inner: HashMap<Orientation, Position>) -> Result<Self, Self::Error> {
+        let threshold = Self{inner};
+        threshold.validate()?;
+        Ok(threshold)
+    }
+}
+
+impl Serialize for MaskThreshold {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(ORIENTATIONS.iter().filter_map(|end| self.inner.get(end).map(|position| (end, position))))
+    }
+}
 
 impl MaskThreshold {
 
@@ -141,7 +165,8 @@ impl Display for MaskThreshold {
 #[cfg(test)]
 mod test {
     use super::*;
-    
+    use serde_test::{Token, assert_tokens, assert_de_tokens_error};
+
     #[test]
     fn display() {
         let threshold = MaskThreshold::default();
@@ -180,4 +205,22 @@ mod test {
         funky_threshold.inner.remove(&ORIENTATIONS[0]);
         assert_eq!(funky_threshold.validate(), Err(MaskThresholdError::ValidateThresh{got: 1, want: ORIENTATIONS.len()}));
     }
+
+    #[test]
+    fn serde() {
+        let mut threshold = MaskThreshold::default();
+        threshold.set_threshold(Orientation::FivePrime, Position::new(12));
+        assert_tokens(&threshold, &[
+            Token::Map{len: None},
+            Token::UnitVariant{name: "Orientation", variant: "5p"}, Token::NewtypeStruct{name: "Position"}, Token::U64(12),
+            Token::UnitVariant{name: "Orientation", variant: "3p"}, Token::NewtypeStruct{name: "Position"}, Token::U64(u64::MAX),
+            Token::MapEnd,
+        ]);
+
+        assert_de_tokens_error::<MaskThreshold>(&[
+            Token::Map{len: Some(1)},
+            Token::UnitVariant{name: "Orientation", variant: "5p"}, Token::NewtypeStruct{name: "Position"}, Token::U64(12),
+            Token::MapEnd,
+        ], &MaskThresholdError::ValidateThresh{got: 1, want: ORIENTATIONS.len()}.to_string());
+    }
 }
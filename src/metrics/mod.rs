@@ -0,0 +1,164 @@
+use std::io::Write;
+
+use crate::genome::{Orientation, Strand};
+
+/// Number of masking candidates found at either end of reads, i.e. positions whose reference nucleotide is the target 
+/// of the library (see [`LibraryType::target_nucleotide()`](crate::genome::LibraryType::target_nucleotide)), along 
+/// with the number of these candidates where the read actually carries the deamination product (`T` over a reference
+/// `C`, `A` over a reference `G`), and the number of bases which were actually altered.
+/// 
+/// - `candidates` is the number of bases masked by default.
+/// - `mismatches` is the number of bases masked when only masking mismatches (see [`crate::mask::MaskOptions`]).
+/// - `masked` is the number of bases actually hard- or soft-masked, or soft-clipped. This is always zero when rescaling
+///   base qualities, or when annotating records (see [`crate::mask::MaskMode`]).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct EndCounts {
+    pub candidates: u64,
+    pub mismatches: u64,
+    pub masked    : u64,
+}
+
+impl EndCounts {
+    /// Add the counts of `other` to these.
+    pub fn add(&mut self, other: &EndCounts) {
+        self.candidates += other.candidates;
+        self.mismatches += other.mismatches;
+        self.masked     += other.masked;
+    }
+}
+
+/// Masking statistics of the records aligned to a single chromosome and strand (see [`MaskStats::get_target()`]).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TargetStats {
+    pub reads       : u64,
+    pub masked_reads: u64,
+    pub five_prime  : EndCounts,
+    pub three_prime : EndCounts,
+}
+
+impl TargetStats {
+    /// Add the statistics of `other` to these.
+    pub fn add(&mut self, other: &TargetStats) {
+        self.reads        += other.reads;
+        self.masked_reads += other.masked_reads;
+        self.five_prime.add(&other.five_prime);
+        self.three_prime.add(&other.three_prime);
+    }
+}
+
+/// Summary statistics of a masking run, as returned by [`crate::apply_pmd_mask()`].
+/// 
+/// Statistics are additionally kept for each chromosome and strand within `targets`, indexed using the target id 
+/// (`tid`) of the alignment file's header, and the strand of records (see [`MaskStats::get_target()`]).
+/// 
+/// # Usage
+/// ```
+/// use pmd_mask::metrics::{MaskStats, EndCounts};
+/// use pmd_mask::genome::Orientation;
+/// 
+/// let mut stats = MaskStats::default();
+/// stats.five_prime.add(&EndCounts{candidates: 10, mismatches: 2, masked: 10});
+/// stats.add(&stats.clone());
+/// 
+/// assert_eq!(stats.get(&Orientation::FivePrime), &EndCounts{candidates: 20, mismatches: 4, masked: 20});
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MaskStats {
+    pub reads       : u64,
+    pub masked_reads: u64,
+    pub five_prime  : EndCounts,
+    pub three_prime : EndCounts,
+    pub targets     : Vec<TargetStats>,
+}
+
+impl MaskStats {
+    /// Add the statistics of `other` to these.
+    pub fn add(&mut self, other: &MaskStats) {
+        self.reads        += other.reads;
+        self.masked_reads += other.masked_reads;
+        self.five_prime.add(&other.five_prime);
+        self.three_prime.add(&other.three_prime);
+        if self.targets.len() < other.targets.len() {
+            self.targets.resize(other.targets.len(), TargetStats::default());
+        }
+        for (target, other) in self.targets.iter_mut().zip(other.targets.iter()) {
+            target.add(other);
+        }
+    }
+
+    /// Keep track of a single processed record, aligned to the target id `tid` of the header, on a given `strand`.
+    /// 
+    /// # Usage
+    /// ```
+    /// use pmd_mask::metrics::{MaskStats, EndCounts};
+    /// use pmd_mask::genome::Strand;
+    /// 
+    /// let mut stats = MaskStats::default();
+    /// stats.add_record(2, Strand::Reverse, true, &EndCounts{candidates: 3, mismatches: 1, masked: 3}, &EndCounts::default());
+    /// assert_eq!((stats.reads, stats.masked_reads, stats.five_prime.candidates), (1, 1, 3));
+    /// assert_eq!(stats.get_target(2, Strand::Reverse).map(|target| target.five_prime.candidates), Some(3));
+    /// assert_eq!(stats.get_target(2, Strand::Forward).map(|target| target.reads), Some(0));
+    /// ```
+    pub fn add_record(&mut self, tid: usize, strand: Strand, masked: bool, counts_5p: &EndCounts, counts_3p: &EndCounts) {
+        let record = TargetStats{reads: 1, masked_reads: masked as u64, five_prime: *counts_5p, three_prime: *counts_3p};
+        self.reads        += record.reads;
+        self.masked_reads += record.masked_reads;
+        self.five_prime.add(counts_5p);
+        self.three_prime.add(counts_3p);
+
+        let index = Self::target_index(tid, strand);
+        if self.targets.len() <= index {
+            self.targets.resize(index + 1, TargetStats::default());
+        }
+        self.targets[index].add(&record);
+    }
+
+    /// Retrieve the [`TargetStats`] of the records aligned to the target id `tid` of the header, on a given `strand`. 
+    /// Returns [`None`] if no record was ever processed for this target id, or any greater target id.
+    pub fn get_target(&self, tid: usize, strand: Strand) -> Option<&TargetStats> {
+        self.targets.get(Self::target_index(tid, strand))
+    }
+
+    /// Index of a target id and strand within `targets`.
+    #[inline]
+    fn target_index(tid: usize, strand: Strand) -> usize {
+        tid * 2 + (strand == Strand::Reverse) as usize
+    }
+
+    /// Retrieve the [`EndCounts`] of a given end.
+    pub fn get(&self, end: &Orientation) -> &EndCounts {
+        match end {
+            Orientation::FivePrime  => &self.five_prime,
+            Orientation::ThreePrime => &self.three_prime,
+        }
+    }
+
+    /// Retrieve a mutable reference to the [`EndCounts`] of a given end.
+    pub fn get_mut(&mut self, end: &Orientation) -> &mut EndCounts {
+        match end {
+            Orientation::FivePrime  => &mut self.five_prime,
+            Orientation::ThreePrime => &mut self.three_prime,
+        }
+    }
+
+    /// Serialize these statistics within a writer, as `#`-prefixed comment lines, reporting the number of bases 
+    /// masked at each end when masking every candidate, and when only masking mismatches.
+    /// ```
+    /// use pmd_mask::metrics::{MaskStats, EndCounts};
+    /// let stats = MaskStats{reads: 10, masked_reads: 4, five_prime: EndCounts{candidates: 5, mismatches: 1, masked: 1}, ..Default::default()};
+    /// 
+    /// let mut output = Vec::new();
+    /// stats.write(&mut output).expect("Failed to write stats");
+    /// assert_eq!(String::from_utf8(output).unwrap(), "\
+    /// ## Reads processed: 10 (masked: 4)
+    /// ## Masked bases (all candidates) : 5p: 5 | 3p: 0
+    /// ## Masked bases (mismatches only): 5p: 1 | 3p: 0
+    /// ");
+    /// ```
+    pub fn write(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        writeln!(writer, "# Reads processed: {} (masked: {})", self.reads, self.masked_reads)?;
+        writeln!(writer, "# Masked bases (all candidates) : 5p: {} | 3p: {}", self.five_prime.candidates, self.three_prime.candidates)?;
+        writeln!(writer, "# Masked bases (mismatches only): 5p: {} | 3p: {}", self.five_prime.mismatches, self.three_prime.mismatches)?;
+        writer.flush()
+    }
+}
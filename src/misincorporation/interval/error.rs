@@ -0,0 +1,8 @@
+use thiserror::Error;
+
+/// Error type enum for [`crate::misincorporation::BinomialInterval`]
+#[derive(Debug, Error, PartialEq)]
+pub enum BinomialIntervalError {
+    #[error("Failed to parse string value '{0}' into a valid binomial confidence interval. Accepted values: 'wilson|clopper-pearson'")]
+    ParseBinomialInterval(String),
+}
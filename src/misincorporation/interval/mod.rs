@@ -0,0 +1,227 @@
+use std::{fmt::{self, Display, Formatter}, str::FromStr};
+
+mod error;
+pub use error::BinomialIntervalError;
+
+/// Method used to compute a two-sided binomial confidence interval of a misincorporation frequency, given the number
+/// of observed misincorporations (successes) and the number of observed target nucleotides (trials). Possible variants:
+/// - [`BinomialInterval::Wilson`]|`'wilson'`: Wilson score interval.
+/// - [`BinomialInterval::ClopperPearson`]|`'clopper-pearson'`: Clopper-Pearson ("exact") interval, computed from the
+///   quantiles of the beta distribution. This interval is more conservative than the Wilson score interval.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinomialInterval {
+    Wilson,
+    ClopperPearson,
+}
+
+impl AsRef<str> for BinomialInterval {
+    /// Obtain the [`str`] representation of a [`BinomialInterval`]
+    /// ```
+    /// use pmd_mask::misincorporation::BinomialInterval;
+    ///
+    /// assert_eq!(BinomialInterval::Wilson.as_ref(), "wilson");
+    /// assert_eq!(BinomialInterval::ClopperPearson.as_ref(), "clopper-pearson");
+    /// ```
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Wilson         => "wilson",
+            Self::ClopperPearson => "clopper-pearson",
+        }
+    }
+}
+
+impl Display for BinomialInterval {
+    /// Obtain a formatted [`String`] representation of a [`BinomialInterval`].
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.as_ref().fmt(f)
+    }
+}
+
+impl FromStr for BinomialInterval {
+    type Err = BinomialIntervalError;
+
+    /// Attempt to convert a string sequence into a [`BinomialInterval`]. Matching is case-insensitive.
+    ///
+    /// # Errors
+    /// Returns a [`BinomialIntervalError::ParseBinomialInterval`] upon encountering any value that is neither `wilson`,
+    /// nor `clopper-pearson`.
+    /// ```
+    /// use pmd_mask::misincorporation::BinomialInterval;
+    ///
+    /// assert_eq!("Wilson".parse::<BinomialInterval>(), Ok(BinomialInterval::Wilson));
+    /// assert!("wald".parse::<BinomialInterval>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "wilson"          => Ok(Self::Wilson),
+            "clopper-pearson" => Ok(Self::ClopperPearson),
+            _                 => Err(Self::Err::ParseBinomialInterval(s.to_string())),
+        }
+    }
+}
+
+impl BinomialInterval {
+    /// Compute the upper bound of the two-sided binomial confidence interval of `successes / trials`, at the
+    /// requested `confidence` level (e.g. `0.95`).
+    ///
+    /// Without any trial, nothing is known about the underlying frequency, and the upper bound is thus `1.0`.
+    /// ```
+    /// use pmd_mask::misincorporation::BinomialInterval;
+    ///
+    /// // ---- Observing no misincorporation out of 10 trials tells us little.
+    /// assert!((BinomialInterval::Wilson.upper_bound(0, 10, 0.95) - 0.2775).abs() < 1e-4);
+    /// assert!((BinomialInterval::ClopperPearson.upper_bound(0, 10, 0.95) - 0.3085).abs() < 1e-4);
+    /// ```
+    pub fn upper_bound(&self, successes: usize, trials: usize, confidence: f64) -> f64 {
+        if trials == 0 { return 1.0 }
+        let (x, n) = (successes.min(trials) as f64, trials as f64);
+        let alpha  = 1.0 - confidence;
+        match self {
+            Self::Wilson => {
+                let z      = normal_quantile(1.0 - alpha / 2.0);
+                let p      = x / n;
+                let z2     = z * z;
+                let center = p + z2 / (2.0 * n);
+                let margin = z * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt();
+                ((center + margin) / (1.0 + z2 / n)).min(1.0)
+            },
+            Self::ClopperPearson => match x == n {
+                true  => 1.0,
+                false => beta_quantile(1.0 - alpha / 2.0, x + 1.0, n - x),
+            },
+        }
+    }
+}
+
+/// Quantile function of the standard normal distribution, using the rational approximation of Peter J. Acklam
+/// (relative error < 1.15e-9).
+fn normal_quantile(p: f64) -> f64 {
+    const A: [f64; 6] = [-3.969683028665376e+01, 2.209460984245205e+02, -2.759285104469687e+02, 1.38357751867269e+02, -3.066479806614716e+01, 2.506628277459239e+00];
+    const B: [f64; 5] = [-5.447609879822406e+01, 1.615858368580409e+02, -1.556989798598866e+02, 6.680131188771972e+01, -1.328068155288572e+01];
+    const C: [f64; 6] = [-7.784894002430293e-03, -3.223964580411365e-01, -2.400758277161838e+00, -2.549732539343734e+00, 4.374664141464968e+00, 2.938163982698783e+00];
+    const D: [f64; 4] = [7.784695709041462e-03, 3.224671290700398e-01, 2.445134137142996e+00, 3.754408661907416e+00];
+    const P_LOW: f64 = 0.02425;
+
+    let tail = |q: f64| (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5]) / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0);
+    match p {
+        p if p <= 0.0 => f64::NEG_INFINITY,
+        p if p >= 1.0 => f64::INFINITY,
+        p if p < P_LOW => tail((-2.0 * p.ln()).sqrt()),
+        p if p > 1.0 - P_LOW => -tail((-2.0 * (1.0 - p).ln()).sqrt()),
+        p => {
+            let q = p - 0.5;
+            let r = q * q;
+            (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+        }
+    }
+}
+
+/// Natural logarithm of the gamma function, using the Lanczos approximation.
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [76.18009172947146, -86.50532032941677, 24.01409824083091, -1.231739572450155, 0.1208650973866179e-2, -0.5395239384953e-5];
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let series = COEFFICIENTS.iter().enumerate().fold(1.000000000190015, |acc, (i, c)| acc + c / (x + 1.0 + i as f64));
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+/// Continued fraction expansion of the regularized incomplete beta function (modified Lentz's method).
+fn beta_continued_fraction(x: f64, a: f64, b: f64) -> f64 {
+    const MAX_ITERATIONS: usize = 300;
+    const EPSILON: f64 = 1e-14;
+    const TINY: f64 = 1e-300;
+
+    let (qab, qap, qam) = (a + b, a + 1.0, a - 1.0);
+    let mut c = 1.0;
+    let mut d = 1.0 - qab * x / qap;
+    if d.abs() < TINY { d = TINY }
+    d = 1.0 / d;
+    let mut h = d;
+    for m in 1..=MAX_ITERATIONS {
+        let m  = m as f64;
+        let m2 = 2.0 * m;
+        for aa in [m * (b - m) * x / ((qam + m2) * (a + m2)), -(a + m) * (qab + m) * x / ((a + m2) * (qap + m2))] {
+            d = 1.0 + aa * d;
+            if d.abs() < TINY { d = TINY }
+            c = 1.0 + aa / c;
+            if c.abs() < TINY { c = TINY }
+            d = 1.0 / d;
+            h *= d * c;
+        }
+        if (d * c - 1.0).abs() < EPSILON { break }
+    }
+    h
+}
+
+/// Regularized incomplete beta function `I_x(a, b)`, i.e. the cumulative distribution function of the beta distribution.
+fn regularized_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 { return 0.0 }
+    if x >= 1.0 { return 1.0 }
+    let front = (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    match x < (a + 1.0) / (a + b + 2.0) {
+        true  => front * beta_continued_fraction(x, a, b) / a,
+        false => 1.0 - front * beta_continued_fraction(1.0 - x, b, a) / b,
+    }
+}
+
+/// Quantile function of the beta distribution, found by bisection over the regularized incomplete beta function.
+fn beta_quantile(p: f64, a: f64, b: f64) -> f64 {
+    let (mut low, mut high) = (0.0, 1.0);
+    for _ in 0..100 {
+        let mid = (low + high) / 2.0;
+        match regularized_beta(mid, a, b) < p {
+            true  => low  = mid,
+            false => high = mid,
+        }
+    }
+    (low + high) / 2.0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_close(got: f64, want: f64) {
+        assert!((got - want).abs() < 1e-4, "got: {got} | want: {want}");
+    }
+
+    #[test]
+    fn from_str() {
+        for (input, want) in [("wilson", BinomialInterval::Wilson), ("WILSON", BinomialInterval::Wilson), ("Clopper-Pearson", BinomialInterval::ClopperPearson)] {
+            assert_eq!(BinomialInterval::from_str(input), Ok(want));
+        }
+        assert_eq!(BinomialInterval::from_str("wald"), Err(BinomialIntervalError::ParseBinomialInterval("wald".to_string())));
+    }
+
+    #[test]
+    fn normal_quantiles() {
+        assert_close(normal_quantile(0.5), 0.0);
+        assert_close(normal_quantile(0.975), 1.959964);
+        assert_close(normal_quantile(0.995), 2.575829);
+        assert_close(normal_quantile(0.01), -2.326348);
+    }
+
+    #[test]
+    fn wilson_upper_bound() {
+        assert_close(BinomialInterval::Wilson.upper_bound(5, 10, 0.95), 0.763404);
+        assert_close(BinomialInterval::Wilson.upper_bound(10, 10, 0.95), 1.0);
+        assert_close(BinomialInterval::Wilson.upper_bound(1, 100, 0.99), 0.079801);
+    }
+
+    #[test]
+    fn clopper_pearson_upper_bound() {
+        assert_close(BinomialInterval::ClopperPearson.upper_bound(5, 10, 0.95), 0.812914);
+        assert_close(BinomialInterval::ClopperPearson.upper_bound(10, 10, 0.95), 1.0);
+        assert_close(BinomialInterval::ClopperPearson.upper_bound(1, 100, 0.95), 0.054459);
+    }
+
+    #[test]
+    fn upper_bound_shrinks_with_depth() {
+        for interval in [BinomialInterval::Wilson, BinomialInterval::ClopperPearson] {
+            assert_eq!(interval.upper_bound(0, 0, 0.95), 1.0);
+            let bounds = [10, 100, 1_000, 100_000].map(|n| interval.upper_bound(n / 200, n, 0.95));
+            assert!(bounds.windows(2).all(|pair| pair[0] > pair[1]), "{bounds:?}");
+            assert!(bounds[3] < 0.01);
+        }
+    }
+}
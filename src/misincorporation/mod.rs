@@ -1,5 +1,5 @@
-use std::{fs::File, path::Path, ops::Deref, io::Read};
-use crate::genome::{Strand, Orientation, ChrName};
+use std::{fs::File, path::Path, ops::Deref, io::Read, collections::{HashMap, HashSet}};
+use crate::genome::{ChrName, LibraryType, Orientation, Position, Strand};
 use csv::ReaderBuilder;
 
 mod error;
@@ -8,13 +8,28 @@ pub use error::MisincorporationsError;
 mod record;
 pub use record::MisincorporationRecord;
 
+mod strategy;
+pub use strategy::{ThresholdStrategy, ThresholdStrategyError, DEFAULT_SMOOTHING_WINDOW};
+
+mod interval;
+pub use interval::{BinomialInterval, BinomialIntervalError};
+
+mod options;
+pub use options::{ThresholdOptions, DEFAULT_CONFIDENCE};
+
+
+/// Chromosome name of the single, merged entry reported by [mapDamage-v2](https://github.com/ginolhac/mapDamage) when
+/// run with `--merge-reference-sequences`. The profile of this entry applies to every contig (see also 
+/// [`Misincorporations::merged()`]).
+pub const MERGED_REFERENCE: &str = "*";
 
 /// A collection of *partially* deserialized CSV record from a [mapDamage-v2](https://github.com/ginolhac/mapDamage)'s
 /// [`misincorporation.txt`](https://ginolhac.github.io/mapDamage/#a4) output file. 
 /// 
-/// Each row within the `misincorporation.txt` file is encoded as a [`MisincorporationRecord`]
-#[derive(Debug)]
-pub struct Misincorporations{inner: Vec<MisincorporationRecord>}
+/// Each row within the `misincorporation.txt` file is encoded as a [`MisincorporationRecord`]. The [`LibraryType`]
+/// dictates which misincorporation frequency is used for each record (see [`MisincorporationRecord::target_freq()`])
+#[derive(Debug, Clone)]
+pub struct Misincorporations{inner: Vec<MisincorporationRecord>, library: LibraryType}
 
 impl Deref for Misincorporations {
     type Target = [MisincorporationRecord];
@@ -28,32 +43,70 @@ impl Deref for Misincorporations {
 impl FromIterator<MisincorporationRecord> for  Misincorporations {
     fn from_iter<T: IntoIterator<Item = MisincorporationRecord>>(records: T) -> Self {
         let inner = Vec::from_iter(records);
-        Self{inner}
+        Self{inner, library: LibraryType::default()}
     }
 }
 
 impl Misincorporations {
+    /// Instantiate a [`Misincorporations`] struct from an in-memory collection of [`MisincorporationRecord`]s, e.g.
+    /// obtained from a [`DamageProfiler`](crate::profiler::DamageProfiler).
+    /// 
+    /// Records are expected to be grouped by chromosome, orientation and strand, and sorted by position.
+    pub fn new(records: Vec<MisincorporationRecord>, library: LibraryType) -> Self {
+        Self{inner: records, library}
+    }
+
     /// Generate a [`Misincorporations`] struct from a [mapDamage-v2](https://github.com/ginolhac/mapDamage)'s
     /// [`misincorporation.txt`](https://ginolhac.github.io/mapDamage/#a4) output file.
     /// 
     /// # Usage
     /// ```
     /// use pmd_mask::misincorporation::Misincorporations;
+    /// use pmd_mask::genome::LibraryType;
+    /// use pmd_mask::misincorporation::ThresholdOptions;
     /// fn main() -> Result<(), Box<dyn std::error::Error>> {
     ///     let file = "tests/test-data/bam/dummy-MTonly/misincorporation.txt";
-    ///     let misincorporations = Misincorporations::from_path(&file, 0.01)?;
+    ///     let misincorporations = Misincorporations::from_path(&file, 0.01, LibraryType::DoubleStranded, ThresholdOptions::default())?;
     ///     Ok(())
     /// }
     /// ```
-    pub fn from_path(path: impl AsRef<Path>, threshold: f32) -> Result<Self, MisincorporationsError>{
+    pub fn from_path(path: impl AsRef<Path>, threshold: f32, library: LibraryType, options: ThresholdOptions) -> Result<Self, MisincorporationsError>{
         let file = File::open(&path)
             .map_err(|e| MisincorporationsError::OpenFile(path.as_ref().display().to_string(), e))?;
-        Self::from_reader(file, threshold)
+        Self::from_reader(file, threshold, library, options)
     }
 
     /// Private [`Misincorporations`] struct constructor from a generic Reader.
     /// See [`Misincorporations::from_path`](Misincorporations::from_path) for the public implementation
-    pub(crate) fn from_reader<R: Read>(path: R, threshold: f32) -> Result<Self, MisincorporationsError>{
+    pub(crate) fn from_reader<R: Read>(path: R, threshold: f32, library: LibraryType, options: ThresholdOptions) -> Result<Self, MisincorporationsError>{
+        Ok(Self::profile_from_reader(path, library)?.thresholds(threshold, &options))
+    }
+
+    /// Generate a [`Misincorporations`] struct from a [mapDamage-v2](https://github.com/ginolhac/mapDamage)'s
+    /// [`misincorporation.txt`](https://ginolhac.github.io/mapDamage/#a4) output file, while keeping track of 
+    /// *every* record, i.e. the full misincorporation profile of each chromosome, strand and orientation.
+    /// 
+    /// # Usage
+    /// ```
+    /// use pmd_mask::misincorporation::Misincorporations;
+    /// use pmd_mask::genome::LibraryType;
+    /// fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///     let file = "tests/test-data/bam/dummy-MTonly/misincorporation.txt";
+    ///     let profile    = Misincorporations::profile_from_path(&file, LibraryType::DoubleStranded)?;
+    ///     let thresholds = Misincorporations::from_path(&file, 0.01, LibraryType::DoubleStranded, Default::default())?;
+    ///     assert!(profile.len() > thresholds.len());
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn profile_from_path(path: impl AsRef<Path>, library: LibraryType) -> Result<Self, MisincorporationsError>{
+        let file = File::open(&path)
+            .map_err(|e| MisincorporationsError::OpenFile(path.as_ref().display().to_string(), e))?;
+        Self::profile_from_reader(file, library)
+    }
+
+    /// Private [`Misincorporations`] full-profile constructor from a generic Reader.
+    /// See [`Misincorporations::profile_from_path`](Misincorporations::profile_from_path) for the public implementation
+    pub(crate) fn profile_from_reader<R: Read>(path: R, library: LibraryType) -> Result<Self, MisincorporationsError>{
         use MisincorporationsError::*;
 
         let mut reader = ReaderBuilder::new()
@@ -63,26 +116,118 @@ impl Misincorporations {
             .from_reader(path)
             ; 
 
-        let mut skip_chromosome: Option<(&ChrName, &Orientation, &Strand)> = None; 
-        let mut threshold_positions = Vec::with_capacity(32 * 2 * 2);
+        let inner = reader.deserialize::<MisincorporationRecord>()
+            .enumerate()
+            .map(|(line, result)| result.map_err(|e|DeserializeRecord(line, e.to_string())))
+            .collect::<Result<Vec<_>, _>>()?;
 
-        'nextline: for (line, result) in reader.deserialize::<MisincorporationRecord>().enumerate() {
-            let record = result.map_err(|e|DeserializeRecord(line, e.to_string()))?;
-            // Once we've found a position at which the threshold is met, we 
-            // don't need to parse entries which belong from the same chromosome.
-            if let Some(chromosome_to_skip) = skip_chromosome {
-                if (&record.chromosome, &record.end, &record.strand) == chromosome_to_skip { continue 'nextline }
-            }
+        Ok(Self{ inner, library })
+    }
+
+    /// Return the [`LibraryType`] used to compute the target frequency of each record.
+    pub fn library(&self) -> LibraryType {
+        self.library
+    }
 
-            // If we're below the requested treshold, keep that record!
-            if record.target_freq() <= threshold {
-                threshold_positions.push(record);
-                let last_insert = threshold_positions.last().unwrap(); // We can unwrap here since we know we've just pushed a value
-                skip_chromosome = Some((&last_insert.chromosome, &last_insert.end, &last_insert.strand)); 
-            
+    /// Obtain a new [`Misincorporations`] struct, only containing the record at which the masking threshold is met, 
+    /// for each chromosome, orientation and strand. This record is selected according to the [`ThresholdStrategy`] of 
+    /// the provided [`ThresholdOptions`] (e.g. the first record at which the [`target_freq()`](`MisincorporationRecord::target_freq`)
+    /// is lower or equal to `threshold`, for [`ThresholdStrategy::FirstBelow`]), using either raw frequencies, or the 
+    /// upper bound of their binomial confidence interval (see [`ThresholdOptions::frequency()`]).
+    pub(crate) fn thresholds(&self, threshold: f32, options: &ThresholdOptions) -> Self {
+        let threshold_positions = self.inner
+            .chunk_by(|a, b| (&a.chromosome, &a.end, &a.strand) == (&b.chromosome, &b.end, &b.strand))
+            .filter_map(|profile| {
+                let frequencies = profile.iter().map(|record| options.frequency(record, &self.library)).collect::<Vec<_>>();
+                options.strategy.select(&frequencies, threshold).map(|index| profile[index].clone())
+            })
+            .collect();
+        Self{ inner: threshold_positions, library: self.library }
+    }
+
+    /// Return the lowest number of observed target nucleotides (`C` or `G`) found among the leading positions of each 
+    /// chromosome, orientation and strand, i.e. from the first position, up to and including the position selected by 
+    /// [`Misincorporations::thresholds()`]. Every position is considered for profiles which never meet the threshold.
+    /// 
+    /// Each count is returned along with the first record of its profile.
+    pub(crate) fn leading_counts(&self, threshold: f32, options: &ThresholdOptions) -> Vec<(&MisincorporationRecord, usize)> {
+        self.inner
+            .chunk_by(|a, b| (&a.chromosome, &a.end, &a.strand) == (&b.chromosome, &b.end, &b.strand))
+            .map(|profile| {
+                let frequencies = profile.iter().map(|record| options.frequency(record, &self.library)).collect::<Vec<_>>();
+                let leading     = options.strategy.select(&frequencies, threshold).map_or(profile.len(), |index| index + 1);
+                let count       = profile[..leading].iter().map(|record| record.target_counts(&self.library).1).min().unwrap_or(0);
+                (&profile[0], count)
+            })
+            .collect()
+    }
+
+    /// Obtain a genome-wide misincorporation profile, where the counts of every record are replaced by the sum of the
+    /// counts of all records sharing the same orientation, strand and position, across every chromosome. When 
+    /// `pool_strands` is set, counts are additionally summed across both strands.
+    /// 
+    /// The chromosome, orientation, strand and position of each record are kept as is: i.e. the pooled profile has the
+    /// same layout as this one, and may thus be used in place of it, or of any of its chromosomes.
+    /// 
+    /// # Usage
+    /// ```
+    /// use pmd_mask::misincorporation::Misincorporations;
+    /// use pmd_mask::genome::LibraryType;
+    /// fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///     let file    = "tests/test-data/bam/dummy-MTonly/misincorporation.txt";
+    ///     let profile = Misincorporations::profile_from_path(&file, LibraryType::DoubleStranded)?;
+    ///     let pooled  = profile.pooled(false);
+    /// 
+    ///     assert_eq!(pooled.len(), profile.len());
+    ///     assert!(pooled[0].c_counts > profile[0].c_counts);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn pooled(&self, pool_strands: bool) -> Self {
+        let key = |record: &MisincorporationRecord| -> (Orientation, Option<Strand>, Position) {
+            (record.end, (!pool_strands).then_some(record.strand), record.position)
+        };
+
+        let mut pooled_counts: HashMap<_, [usize; 4]> = HashMap::new();
+        for record in self.inner.iter() {
+            let counts = pooled_counts.entry(key(record)).or_default();
+            for (count, value) in counts.iter_mut().zip([record.c_counts, record.g_counts, record.c_to_t, record.g_to_a]) {
+                *count += value;
             }
         }
-        Ok(Self{ inner: threshold_positions }) 
+
+        let inner = self.inner.iter().map(|record| {
+            let [c_counts, g_counts, c_to_t, g_to_a] = pooled_counts[&key(record)];
+            MisincorporationRecord{ c_counts, g_counts, c_to_t, g_to_a, ..record.clone() }
+        }).collect();
+        Self{ inner, library: self.library }
+    }
+
+    /// Obtain a single, genome-wide misincorporation profile, pooled across all chromosomes (and optionally strands, 
+    /// when `pool_strands` is set). This mimics the output of mapDamage-v2 with `--merge-reference-sequences`: every 
+    /// record is assigned to the [`MERGED_REFERENCE`] chromosome (see [`Misincorporations::pooled()`]).
+    /// 
+    /// # Usage
+    /// ```
+    /// use pmd_mask::misincorporation::{Misincorporations, MERGED_REFERENCE};
+    /// use pmd_mask::genome::LibraryType;
+    /// fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///     let file    = "tests/test-data/bam/dummy-MTonly/misincorporation.txt";
+    ///     let profile = Misincorporations::profile_from_path(&file, LibraryType::DoubleStranded)?;
+    ///     let merged  = profile.merged(false);
+    /// 
+    ///     assert!(merged.iter().all(|record| record.chromosome.inner() == MERGED_REFERENCE));
+    ///     assert_eq!(merged.len(), 4 * 70); // 2 orientations, 2 strands and 70 positions.
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn merged(&self, pool_strands: bool) -> Self {
+        let mut seen = HashSet::new();
+        let inner = self.pooled(pool_strands).inner.into_iter()
+            .filter(|record| seen.insert((record.end, record.strand, record.position)))
+            .map(|record| MisincorporationRecord{ chromosome: ChrName::new(MERGED_REFERENCE), ..record })
+            .collect();
+        Self{ inner, library: self.library }
     }
 
     /// Extrude invalid frequencies from the inner collection of [`MisincorporationRecord`] and return them
@@ -95,10 +240,11 @@ impl Misincorporations {
     /// 
     /// # Usage
     /// ```
-    /// use pmd_mask::misincorporation::Misincorporations;
+    /// use pmd_mask::misincorporation::{Misincorporations, ThresholdOptions};
+    /// use pmd_mask::genome::LibraryType;
     /// fn main() -> Result<(), Box<dyn std::error::Error>> {
     ///     let file = "tests/test-data/bam/dummy-MTonly/misincorporation.txt";
-    ///     let mut misincorporations = Misincorporations::from_path(&file, 0.01)?;
+    ///     let mut misincorporations = Misincorporations::from_path(&file, 0.01, LibraryType::DoubleStranded, ThresholdOptions::default())?;
     /// 
     ///     let invalid_freqs = misincorporations.extrude_invalid_frequencies();
     /// 
@@ -108,9 +254,10 @@ impl Misincorporations {
     /// ```
     pub fn extrude_invalid_frequencies(&mut self) -> Vec<MisincorporationRecord> {
         let mut invalid_positions = Vec::with_capacity(self.inner.len());
-
+        let library = self.library;
         self.inner.retain(|pos| {
-            if pos.target_freq().is_nan() || pos.target_freq().is_infinite() || pos.target_freq().is_sign_negative()  {
+            let target_freq = pos.target_freq(&library);
+            if target_freq.is_nan() || target_freq.is_infinite() || target_freq.is_sign_negative()  {
                 invalid_positions.push(pos.clone());
                 false
             } else {
@@ -148,7 +295,7 @@ macro_rules! mock_file {
 mod test {
     use std::{ops::RangeInclusive, io::Cursor};
 
-    use crate::genome::Position;
+    use crate::genome::{Position, Orientation};
 
     use super::*;
 
@@ -179,7 +326,7 @@ mod test {
             }
         }
 
-        Misincorporations::from_reader(Cursor::new(out), threshold)
+        Misincorporations::from_reader(Cursor::new(out), threshold, LibraryType::DoubleStranded, ThresholdOptions::default())
     }
 
     #[test]
@@ -234,6 +381,34 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_threshold_single_stranded() -> Result<(), MisincorporationsError> {
+        let (start_mis, mis_decay, threshold) = (0.3, 0.88, 0.01);
+        let mut out = String::from(mock_file!(header));
+        for end in ["3p", "5p"] {
+            let mut freq = start_mis;
+            for pos in 1..=70 {
+                out += &mock_file!("chr1", end, "+", pos, 100_000, freq);
+                freq *= mis_decay;
+            }
+        }
+
+        // mock_file! only counts C>T transitions at the 5p end, and G>A transitions at the 3p end.
+        // => Single stranded libraries should only consider C>T transitions, and thus meet the threshold
+        //    right from the start at the 3p end.
+        let expected_5p = ( f64::ln(threshold as f64 / start_mis) / f64::ln(mis_decay) ).ceil() as usize + 1;
+        let misincorporations = Misincorporations::from_reader(Cursor::new(out), threshold, LibraryType::SingleStranded, ThresholdOptions::default())?;
+        assert_eq!(misincorporations.len(), 2);
+        for record in misincorporations.iter() {
+            let expected = match record.end {
+                Orientation::FivePrime  => expected_5p,
+                Orientation::ThreePrime => 1,
+            };
+            assert_eq!(record.position, Position::new(expected));
+        }
+        Ok(())
+    }
+
     macro_rules! tamper_misincorporations {
         ($mis:expr, $field:ident, $pos:expr) => {{
             let funky_record = $mis.inner.get_mut($pos).expect("Empty misincorporations");
@@ -243,6 +418,26 @@ mod test {
         }
     }
 
+    #[test]
+    fn pooled() -> Result<(), MisincorporationsError> {
+        let misincorporations = mock_misincorporation(1..=3, 10, 1_000, 0.3, 0.88, 0.01)?;
+
+        // ---- Each record should sum the counts of every chromosome, while keeping its own coordinates.
+        let pooled = misincorporations.pooled(false);
+        assert_eq!(pooled.len(), misincorporations.len());
+        for (record, pooled) in misincorporations.iter().zip(pooled.iter()) {
+            assert_eq!((&record.chromosome, record.end, record.strand, record.position), (&pooled.chromosome, pooled.end, pooled.strand, pooled.position));
+            assert_eq!(pooled.c_counts, record.c_counts * 3);
+            assert_eq!(pooled.g_to_a,   record.g_to_a * 3);
+        }
+
+        // ---- Pooling strands doubles counts once more.
+        for (record, pooled) in misincorporations.iter().zip(misincorporations.pooled(true).iter()) {
+            assert_eq!(pooled.g_counts, record.g_counts * 6);
+        }
+        Ok(())
+    }
+
     #[test]
     fn extrude_invalid_frequencies() -> Result<(), MisincorporationsError> {
         let (start_mis, mis_decay, threshold) = (0.3, 0.88, 0.5);
@@ -0,0 +1,108 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::genome::LibraryType;
+use super::{MisincorporationRecord, ThresholdStrategy, BinomialInterval};
+
+/// Default confidence level of binomial confidence intervals (see [`ThresholdOptions`]).
+pub const DEFAULT_CONFIDENCE: f64 = 0.95;
+
+/// User-defined parameters dictating how masking thresholds are selected from a misincorporation profile.
+///
+/// - `strategy`  : how the threshold position is selected from each profile (see [`ThresholdStrategy`]).
+/// - `interval`  : when set, a position is only considered below threshold once the upper bound of the binomial
+///   confidence interval of its misincorporation frequency is (see [`BinomialInterval`]). Otherwise, the raw frequency
+///   is used.
+/// - `confidence`: confidence level of the binomial confidence interval. Ignored if `interval` is [`None`].
+/// - `min_count` : minimum number of observed target nucleotides (`C` or `G`) required at every leading position of a
+///   profile, i.e. up to and including the selected threshold position (see 
+///   [`Misincorporations::leading_counts()`](super::Misincorporations::leading_counts)). Chromosomes and strands falling short of this requirement use a profile pooled across all chromosomes instead
+///   (see [`Misincorporations::pooled()`](super::Misincorporations::pooled)). Set to `0` to disable.
+/// - `pool_strands`: additionally pool both strands when computing the fallback profile.
+///
+/// # Usage
+/// ```
+/// use pmd_mask::misincorporation::{ThresholdOptions, ThresholdStrategy, BinomialInterval};
+///
+/// let options = ThresholdOptions{ interval: Some(BinomialInterval::Wilson), ..Default::default() };
+/// assert_eq!(options.strategy, ThresholdStrategy::FirstBelow);
+/// assert_eq!(options.confidence, 0.95);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThresholdOptions {
+    pub strategy  : ThresholdStrategy,
+    pub interval  : Option<BinomialInterval>,
+    pub confidence: f64,
+    pub min_count : usize,
+    pub pool_strands: bool,
+}
+
+impl Default for ThresholdOptions {
+    /// Create a default set of [`ThresholdOptions`], i.e. select the first position whose raw misincorporation
+    /// frequency is lower or equal to the threshold, without any minimum count requirement.
+    fn default() -> Self {
+        Self { strategy: ThresholdStrategy::default(), interval: None, confidence: DEFAULT_CONFIDENCE, min_count: 0, pool_strands: false }
+    }
+}
+
+impl ThresholdOptions {
+    /// Return the frequency of a [`MisincorporationRecord`] which should be compared against the masking threshold, i.e.
+    /// either its raw [`target_freq()`](MisincorporationRecord::target_freq), or the upper bound of its binomial
+    /// confidence interval (see [`BinomialInterval::upper_bound()`]).
+    pub fn frequency(&self, record: &MisincorporationRecord, library: &LibraryType) -> f32 {
+        match self.interval {
+            None           => record.target_freq(library),
+            Some(interval) => {
+                let (misincorporations, trials) = record.target_counts(library);
+                interval.upper_bound(misincorporations, trials, self.confidence) as f32
+            }
+        }
+    }
+}
+
+impl Display for ThresholdOptions {
+    /// Return a formatted [`String`] representation of [`ThresholdOptions`]
+    /// ```
+    /// use pmd_mask::misincorporation::{ThresholdOptions, BinomialInterval};
+    /// assert_eq!(format!("{}", ThresholdOptions::default()), "strategy: first-below | interval: none | min-count: 0");
+    ///
+    /// let options = ThresholdOptions{ interval: Some(BinomialInterval::ClopperPearson), confidence: 0.99, ..Default::default() };
+    /// assert_eq!(format!("{options}"), "strategy: first-below | interval: clopper-pearson (0.99) | min-count: 0");
+    ///
+    /// let options = ThresholdOptions{ min_count: 100, pool_strands: true, ..Default::default() };
+    /// assert_eq!(format!("{options}"), "strategy: first-below | interval: none | min-count: 100 (pooled strands)");
+    /// ```
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let interval = match self.interval {
+            None           => "none".to_string(),
+            Some(interval) => format!("{interval} ({})", self.confidence),
+        };
+        let pooled = if self.pool_strands { " (pooled strands)" } else { "" };
+        write!(f, "strategy: {} | interval: {interval} | min-count: {}{pooled}", self.strategy, self.min_count)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::genome::{ChrName, Orientation, Strand, Position};
+
+    #[test]
+    fn frequency() {
+        let record = MisincorporationRecord {
+            chromosome: ChrName::new("chr1"), end: Orientation::FivePrime, strand: Strand::Forward, position: Position::new(1),
+            c_counts: 10, g_counts: 10, c_to_t: 0, g_to_a: 5
+        };
+        let library = LibraryType::DoubleStranded;
+        assert_eq!(ThresholdOptions::default().frequency(&record, &library), 0.0);
+
+        // ---- A handful of unmutated Cytosines is not enough to conclude the frequency is low.
+        for interval in [BinomialInterval::Wilson, BinomialInterval::ClopperPearson] {
+            let options = ThresholdOptions{ interval: Some(interval), ..Default::default() };
+            assert!(options.frequency(&record, &library) > 0.25);
+
+            // ---- Higher confidence levels yield wider intervals.
+            let stricter = ThresholdOptions{ confidence: 0.99, ..options };
+            assert!(stricter.frequency(&record, &library) > options.frequency(&record, &library));
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use std::fmt::{self, Display, Formatter};
-use crate::genome::{ChrName, Orientation, Strand, Position};
+use crate::genome::{ChrName, Orientation, Strand, Position, LibraryType};
 
 use serde::Deserialize;
 
@@ -33,15 +33,25 @@ impl MisincorporationRecord {
         self.g_to_a as f32 / self.g_counts as f32
     }
 
-    /// Return the misincorporation frequency we're ***really*** interested in:  
+    /// Return the misincorporation frequency we're ***really*** interested in, given the [`LibraryType`]:  
     /// - If this entry is [`Orientation::FivePrime`]  -> return `C>T` relative frequency 
     ///   (see [`MisincorporationRecord::c_to_freq()`](MisincorporationRecord::c_to_t_freq))
     /// - If this entry is [`Orientation::ThreePrime`] -> return `G>A` relative frequency 
     ///   (see [`MisincorporationRecord::g_to_a_freq()`](MisincorporationRecord::g_t_a_freq))
-    pub fn target_freq(&self) -> f32 {
-        match self.end {
-            Orientation::FivePrime  => self.c_to_t_freq(),
-            Orientation::ThreePrime => self.g_to_a_freq(),
+    ///   for [`LibraryType::DoubleStranded`] libraries, or `C>T` relative frequency for
+    ///   [`LibraryType::SingleStranded`] libraries.
+    pub fn target_freq(&self, library: &LibraryType) -> f32 {
+        let (misincorporations, trials) = self.target_counts(library);
+        misincorporations as f32 / trials as f32
+    }
+
+    /// Return the raw counts behind [`MisincorporationRecord::target_freq()`], i.e. the number of observed
+    /// misincorporations (`C>T` or `G>A`), along with the number of observed target nucleotides (`C` or `G`).
+    pub fn target_counts(&self, library: &LibraryType) -> (usize, usize) {
+        match (self.end, library) {
+            (Orientation::FivePrime,  _                          ) => (self.c_to_t, self.c_counts),
+            (Orientation::ThreePrime, LibraryType::DoubleStranded) => (self.g_to_a, self.g_counts),
+            (Orientation::ThreePrime, LibraryType::SingleStranded) => (self.c_to_t, self.c_counts),
         }
     }
 }
@@ -242,17 +252,33 @@ mod test {
         // ---- target_freq should return 0.25 no matter what, the only difference being :
         // - it returns G>A frequencies if the orientation is ThreePrime
         // - it returns C>T frequencies if the orientation is FivePrime
+        let library = LibraryType::DoubleStranded;
         let record = mis_record!("X", Strand::Forward, Orientation::ThreePrime, 1, counts, freq);
-        assert_eq!(record.target_freq(), 0.25);
+        assert_eq!(record.target_freq(&library), 0.25);
         assert_eq!(record.c_to_t, 0);
         assert_eq!(record.g_to_a, ((counts as f64/2.0) * freq).floor() as usize);
 
         let record = mis_record!("X", Strand::Forward, Orientation::FivePrime, 1, counts, freq);
-        assert_eq!(record.target_freq(), 0.25);
+        assert_eq!(record.target_freq(&library), 0.25);
         assert_eq!(record.g_to_a, 0);
         assert_eq!(record.c_to_t, ((counts as f64/2.0) * freq).floor() as usize);
     }
 
+    #[test]
+    fn target_freq_single_stranded() {
+        let (counts, freq) = (2_000, 0.25);
+
+        // ---- target_freq should always return C>T frequencies for single stranded libraries.
+        let library = LibraryType::SingleStranded;
+        let mut record = mis_record!("X", Strand::Forward, Orientation::ThreePrime, 1, counts, freq);
+        assert_eq!(record.target_freq(&library), 0.0);
+        record.c_to_t = record.g_to_a;
+        assert_eq!(record.target_freq(&library), 0.25);
+
+        let record = mis_record!("X", Strand::Reverse, Orientation::FivePrime, 1, counts, freq);
+        assert_eq!(record.target_freq(&library), 0.25);
+    }
+
     #[test]
     fn display() {
         // ---- Ensure the function does not panic, or return an empty string, and that formatting is applied
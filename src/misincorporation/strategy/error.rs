@@ -0,0 +1,8 @@
+use thiserror::Error;
+
+/// Error type enum for [`crate::misincorporation::ThresholdStrategy`]
+#[derive(Debug, Error, PartialEq)]
+pub enum ThresholdStrategyError {
+    #[error("Failed to parse string value '{0}' into a valid threshold strategy. Accepted values: 'first-below|last-above|moving-average[:<window>]'")]
+    ParseThresholdStrategy(String),
+}
@@ -0,0 +1,155 @@
+use std::{fmt::{self, Display, Formatter}, str::FromStr};
+
+mod error;
+pub use error::ThresholdStrategyError;
+
+/// Default window size of [`ThresholdStrategy::MovingAverage`], when unspecified.
+pub const DEFAULT_SMOOTHING_WINDOW: usize = 5;
+
+/// Defines how the masking threshold position of a chromosome, strand and orientation is selected from its
+/// misincorporation profile. Possible variants:
+/// - [`ThresholdStrategy::FirstBelow`]|`'first-below'`: select the first position whose frequency is lower or equal
+///   to the threshold.
+/// - [`ThresholdStrategy::LastAbove`]|`'last-above'`: select the position following the last position whose frequency
+///   is greater than the threshold. This is robust to isolated, noisy dips of the profile.
+/// - [`ThresholdStrategy::MovingAverage`]|`'moving-average[:<window>]'`: smooth the profile using a centered moving
+///   average of `window` positions, and select the first position whose smoothed frequency is lower or equal to the
+///   threshold.
+///
+/// Profiles which never meet the threshold yield no position, i.e. masking applies along the full length of reads.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ThresholdStrategy {
+    #[default]
+    FirstBelow,
+    LastAbove,
+    MovingAverage(usize),
+}
+
+impl Display for ThresholdStrategy {
+    /// Obtain a formatted [`String`] representation of a [`ThresholdStrategy`].
+    /// ```
+    /// use pmd_mask::misincorporation::ThresholdStrategy;
+    ///
+    /// assert_eq!(ThresholdStrategy::FirstBelow.to_string(), "first-below");
+    /// assert_eq!(ThresholdStrategy::MovingAverage(3).to_string(), "moving-average:3");
+    /// ```
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FirstBelow            => "first-below".fmt(f),
+            Self::LastAbove             => "last-above".fmt(f),
+            Self::MovingAverage(window) => format!("moving-average:{window}").fmt(f),
+        }
+    }
+}
+
+impl FromStr for ThresholdStrategy {
+    type Err = ThresholdStrategyError;
+
+    /// Attempt to convert a string sequence into a [`ThresholdStrategy`]. Matching is case-insensitive. The window size
+    /// of `moving-average` defaults to [`DEFAULT_SMOOTHING_WINDOW`] when unspecified.
+    ///
+    /// # Errors
+    /// Returns a [`ThresholdStrategyError::ParseThresholdStrategy`] upon encountering any value that is neither
+    /// `first-below`, `last-above`, nor `moving-average[:<window>]`, or if the window size is not a positive integer.
+    /// ```
+    /// use pmd_mask::misincorporation::ThresholdStrategy;
+    ///
+    /// assert_eq!("Last-Above".parse::<ThresholdStrategy>(), Ok(ThresholdStrategy::LastAbove));
+    /// assert_eq!("moving-average:7".parse::<ThresholdStrategy>(), Ok(ThresholdStrategy::MovingAverage(7)));
+    /// assert!("moving-average:0".parse::<ThresholdStrategy>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err   = || Self::Err::ParseThresholdStrategy(s.to_string());
+        let lower = s.to_ascii_lowercase();
+        match lower.split_once(':') {
+            None => match lower.as_str() {
+                "first-below"    => Ok(Self::FirstBelow),
+                "last-above"     => Ok(Self::LastAbove),
+                "moving-average" => Ok(Self::MovingAverage(DEFAULT_SMOOTHING_WINDOW)),
+                _                => Err(err()),
+            },
+            Some(("moving-average", window)) => match window.parse::<usize>() {
+                Ok(window) if window > 0 => Ok(Self::MovingAverage(window)),
+                _                        => Err(err()),
+            },
+            Some(_) => Err(err()),
+        }
+    }
+}
+
+impl ThresholdStrategy {
+    /// Select the threshold position of a single profile, given the misincorporation `frequencies` of a set of
+    /// [`MisincorporationRecord`](super::MisincorporationRecord)s sharing the same chromosome, orientation and strand,
+    /// sorted by position.
+    ///
+    /// Returns the index of the selected position within `frequencies`, or [`None`] if the threshold is never met.
+    pub(crate) fn select(&self, frequencies: &[f32], threshold: f32) -> Option<usize> {
+        match self {
+            Self::FirstBelow => frequencies.iter().position(|freq| *freq <= threshold),
+            // NOTE: invalid (NaN) frequencies are considered above threshold.
+            Self::LastAbove  => match frequencies.iter().rposition(|freq| freq.is_nan() || *freq > threshold) {
+                Some(last) => Some(last + 1),
+                None       => Some(0),
+            },
+            Self::MovingAverage(window) => {
+                let (before, after) = ((window - 1) / 2, window / 2);
+                (0..frequencies.len()).position(|i| {
+                    let range = i.saturating_sub(before)..(i + after + 1).min(frequencies.len());
+                    let len   = range.len() as f32;
+                    frequencies[range].iter().sum::<f32>() / len <= threshold
+                })
+            },
+        }.filter(|index| *index < frequencies.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Select the 1-based position of a profile, given a set of frequencies (in percent).
+    fn selected_position(strategy: ThresholdStrategy, frequencies: &[usize], threshold: f32) -> Option<usize> {
+        let frequencies = frequencies.iter().map(|freq| *freq as f32 / 100.0).collect::<Vec<_>>();
+        strategy.select(&frequencies, threshold).map(|index| index + 1)
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!("first-below|last-above|moving-average:5", format!("{}|{}|{}", ThresholdStrategy::FirstBelow, ThresholdStrategy::LastAbove, ThresholdStrategy::MovingAverage(DEFAULT_SMOOTHING_WINDOW)));
+    }
+
+    #[test]
+    fn from_str() {
+        for (input, want) in [("first-below", ThresholdStrategy::FirstBelow), ("LAST-ABOVE", ThresholdStrategy::LastAbove), ("moving-average", ThresholdStrategy::MovingAverage(DEFAULT_SMOOTHING_WINDOW)), ("Moving-Average:3", ThresholdStrategy::MovingAverage(3))] {
+            assert_eq!(ThresholdStrategy::from_str(input), Ok(want));
+        }
+        for invalid in ["first", "moving-average:", "moving-average:-1", "moving-average:0", "last-above:3"] {
+            assert_eq!(ThresholdStrategy::from_str(invalid), Err(ThresholdStrategyError::ParseThresholdStrategy(invalid.to_string())));
+        }
+    }
+
+    #[test]
+    fn noisy_dip() {
+        // ---- A single noisy dip at position 2, while positions 3 to 6 are well above threshold.
+        let frequencies = [30, 0, 20, 15, 10, 5, 0, 0, 0, 0];
+        assert_eq!(selected_position(ThresholdStrategy::FirstBelow, &frequencies, 0.01), Some(2));
+        assert_eq!(selected_position(ThresholdStrategy::LastAbove, &frequencies, 0.01), Some(7));
+        assert_eq!(selected_position(ThresholdStrategy::MovingAverage(3), &frequencies, 0.01), Some(8));
+    }
+
+    #[test]
+    fn threshold_never_met() {
+        let frequencies = [30, 20, 10, 5, 5];
+        for strategy in [ThresholdStrategy::FirstBelow, ThresholdStrategy::LastAbove, ThresholdStrategy::MovingAverage(3)] {
+            assert_eq!(selected_position(strategy, &frequencies, 0.01), None);
+        }
+    }
+
+    #[test]
+    fn threshold_met_from_the_start() {
+        let frequencies = [0, 0, 0];
+        for strategy in [ThresholdStrategy::FirstBelow, ThresholdStrategy::LastAbove, ThresholdStrategy::MovingAverage(3)] {
+            assert_eq!(selected_position(strategy, &frequencies, 0.01), Some(1));
+        }
+    }
+}
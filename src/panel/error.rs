@@ -0,0 +1,25 @@
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+/// Error type associated with [`crate::panel::SitePanel`]
+#[derive(Debug, Error)]
+pub enum PanelError {
+    #[error("Failed to open SNP panel file '{path}' [{source}]")]
+    OpenFile{path: PathBuf, #[source] source: std::io::Error},
+
+    #[error("Failed to read line {line} of SNP panel file [{source}]")]
+    ReadLine{line: usize, #[source] source: std::io::Error},
+
+    #[error("Failed to parse line {line} of SNP panel file. Got '{content}'")]
+    ParseLine{line: usize, content: String},
+
+    #[error("Failed to read SNP panel from VCF/BCF file [{0}]")]
+    ReadVcf(#[from] rust_htslib::errors::Error),
+
+    #[error("Unrecognized SNP panel file format for '{0}'. Accepted extensions: '.bed', '.vcf', '.vcf.gz', '.bcf', '.snp'")]
+    UnknownFormat(PathBuf),
+
+    #[error("BED files do not provide alleles, and thus cannot be restricted to transition sites. Please provide a VCF or EIGENSTRAT '.snp' file instead")]
+    MissingAlleles,
+}
@@ -0,0 +1,308 @@
+use std::{collections::HashMap, fs::File, io::{BufRead, BufReader}, path::Path};
+
+use rust_htslib::bcf::{self, Read};
+use log::debug;
+
+use crate::genome::ChrName;
+
+mod error;
+pub use error::PanelError;
+
+/// A single site, or a contiguous interval of sites of a [`SitePanel`].
+/// 
+/// - `start`, `end`: 0-based, half-open reference coordinates of the interval. Sites of VCF and EIGENSTRAT panels span
+///   a single position (i.e. `end == start + 1`), while BED intervals are kept as is.
+/// - `target`: when restricting the panel to transitions, the only reference nucleotide which may be masked at this
+///   site, i.e. `C` for `C/T` sites, and `G` for `G/A` sites. [`None`] if any candidate may be masked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Site {
+    pub start : u64,
+    pub end   : u64,
+    pub target: Option<u8>,
+}
+
+/// A collection of known SNP sites (e.g. the 1240K panel), restricting masking to these positions.
+/// 
+/// Sites are stored per chromosome, as sorted and non-overlapping intervals (see [`Site`]).
+/// 
+/// # Accepted formats
+/// - BED (`.bed`): 0-based, half-open intervals. Every position of each interval is considered as a site. Intervals
+///   are stored as such, and merged when overlapping. Since BED files do not provide alleles, they cannot be 
+///   restricted to transitions.
+/// - VCF (`.vcf`, `.vcf.gz`, `.bcf`): Each record is considered as a site, using its `REF` and `ALT` alleles.
+/// - EIGENSTRAT (`.snp`): whitespace separated `ID CHROM GENETIC_POS PHYSICAL_POS REF ALT` fields. Chromosomes `23`,
+///   `24` and `90` are respectively renamed `X`, `Y` and `MT`.
+/// 
+/// # Usage
+/// ```
+/// use pmd_mask::panel::{SitePanel, Site};
+/// use pmd_mask::genome::ChrName;
+/// 
+/// let snp = "rs1 1 0.0 1001 C T\nrs2 1 0.0 2001 A C\nrs3 90 0.0 73 G A\n";
+/// let panel = SitePanel::from_eigenstrat_reader(snp.as_bytes(), true).expect("Invalid panel");
+/// 
+/// assert_eq!(panel.get(&ChrName::new("1")), Some(&[Site{start: 1000, end: 1001, target: Some(b'C')}][..]));
+/// assert_eq!(panel.get(&ChrName::new("MT")), Some(&[Site{start: 72, end: 73, target: Some(b'G')}][..]));
+/// ```
+#[derive(Debug, Default)]
+pub struct SitePanel { inner: HashMap<ChrName, Vec<Site>> }
+
+impl SitePanel {
+    /// Load a [`SitePanel`] from a file, using its extension to infer its format (see [`SitePanel`]).
+    /// When `transitions_only` is set, only sites whose alleles form a `C/T` or `G/A` transition are kept.
+    /// 
+    /// # Errors
+    /// - Returns a [`PanelError::UnknownFormat`] if the file's extension is not recognized.
+    /// - Returns a [`PanelError::MissingAlleles`] if `transitions_only` is requested with a BED file.
+    /// - Bubbles out any error arising while opening or parsing the file.
+    pub fn from_path(path: impl AsRef<Path>, transitions_only: bool) -> Result<Self, PanelError> {
+        let path = path.as_ref();
+        let name = path.file_name().map(|name| name.to_string_lossy().to_ascii_lowercase()).unwrap_or_default();
+        let open = || File::open(path).map(BufReader::new).map_err(|source| PanelError::OpenFile{path: path.to_path_buf(), source});
+
+        let panel = if name.ends_with(".bed") {
+            match transitions_only {
+                true  => return Err(PanelError::MissingAlleles),
+                false => Self::from_bed_reader(open()?)?,
+            }
+        } else if name.ends_with(".snp") {
+            Self::from_eigenstrat_reader(open()?, transitions_only)?
+        } else if name.ends_with(".vcf") || name.ends_with(".vcf.gz") || name.ends_with(".bcf") {
+            Self::from_vcf(path, transitions_only)?
+        } else {
+            return Err(PanelError::UnknownFormat(path.to_path_buf()))
+        };
+
+        debug!("Loaded {} SNP panel sites from {}", panel.len(), path.display());
+        Ok(panel)
+    }
+
+    /// Parse a BED file into a [`SitePanel`], where every position of each interval is considered as a site. Intervals
+    /// are kept as such (see [`Site`]).
+    /// 
+    /// # Errors
+    /// Returns a [`PanelError::ReadLine`] or [`PanelError::ParseLine`] if any line could not be read or parsed.
+    pub fn from_bed_reader(reader: impl BufRead) -> Result<Self, PanelError> {
+        let mut panel = Self::default();
+        for_each_record(reader, |fields| {
+            let [chromosome, start, end, ..] = fields else { return None };
+            let (start, end) = (start.parse::<u64>().ok()?, end.parse::<u64>().ok()?);
+            if end < start {
+                return None
+            }
+            panel.inner.entry(ChrName::new(chromosome)).or_default().push(Site{start, end, target: None});
+            Some(())
+        })?;
+        panel.sort();
+        Ok(panel)
+    }
+
+    /// Parse an EIGENSTRAT `.snp` file into a [`SitePanel`]. When `transitions_only` is set, only sites whose alleles
+    /// form a `C/T` or `G/A` transition are kept.
+    /// 
+    /// # Errors
+    /// Returns a [`PanelError::ReadLine`] or [`PanelError::ParseLine`] if any line could not be read or parsed.
+    pub fn from_eigenstrat_reader(reader: impl BufRead, transitions_only: bool) -> Result<Self, PanelError> {
+        let mut panel = Self::default();
+        for_each_record(reader, |fields| {
+            let [_, chromosome, _, position, reference, alternate, ..] = fields else { return None };
+            let position   = position.parse::<u64>().ok()?.checked_sub(1)?;
+            let chromosome = match *chromosome { "23" => "X", "24" => "Y", "90" => "MT", other => other };
+            panel.push(chromosome, position, &[reference.as_bytes(), alternate.as_bytes()], transitions_only);
+            Some(())
+        })?;
+        panel.sort();
+        Ok(panel)
+    }
+
+    /// Read a VCF/BCF file (optionally bgzipped) into a [`SitePanel`]. When `transitions_only` is set, only sites 
+    /// whose alleles form a `C/T` or `G/A` transition are kept.
+    /// 
+    /// # Errors
+    /// Returns a [`PanelError::ReadVcf`] if the file could not be opened or parsed.
+    pub fn from_vcf(path: impl AsRef<Path>, transitions_only: bool) -> Result<Self, PanelError> {
+        let mut reader = bcf::Reader::from_path(path)?;
+        let header     = reader.header().clone();
+        let mut panel  = Self::default();
+        for record in reader.records() {
+            let record     = record?;
+            let Some(rid)  = record.rid() else { continue };
+            let chromosome = String::from_utf8_lossy(header.rid2name(rid)?).to_string();
+            panel.push(&chromosome, record.pos() as u64, &record.alleles(), transitions_only);
+        }
+        panel.sort();
+        Ok(panel)
+    }
+
+    /// Retrieve the sorted, non-overlapping [`Site`]s of a given chromosome, if any.
+    pub fn get(&self, chromosome: &ChrName) -> Option<&[Site]> {
+        self.inner.get(chromosome).map(Vec::as_slice)
+    }
+
+    /// Total number of positions contained within this panel.
+    pub fn len(&self) -> usize {
+        self.inner.values().flatten().map(|site| (site.end - site.start) as usize).sum()
+    }
+
+    /// Check whether this panel contains any site.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Register a site, along with its alleles. Sites which do not form a transition are skipped if `transitions_only`
+    /// is set.
+    fn push(&mut self, chromosome: &str, position: u64, alleles: &[&[u8]], transitions_only: bool) {
+        let target = match transitions_only {
+            true  => match transition_target(alleles) {
+                Some(target) => Some(target),
+                None         => return,
+            },
+            false => None,
+        };
+        self.inner.entry(ChrName::new(chromosome)).or_default().push(Site{start: position, end: position + 1, target});
+    }
+
+    /// Sort sites according to their start coordinate, and merge overlapping or book-ended sites sharing the same 
+    /// target. Duplicate sites with a different target are removed, keeping the first one.
+    fn sort(&mut self) {
+        for sites in self.inner.values_mut() {
+            sites.sort_by_key(|site| site.start);
+            let mut merged: Vec<Site> = Vec::with_capacity(sites.len());
+            for site in sites.drain(..) {
+                match merged.last_mut() {
+                    Some(last) if site.start <= last.end && site.target == last.target => last.end = last.end.max(site.end),
+                    Some(last) if site.start <  last.end => {},
+                    _ => merged.push(site),
+                }
+            }
+            *sites = merged;
+        }
+    }
+}
+
+/// Return the reference nucleotide which may be masked at a site, if its alleles form a `C/T` (`C`) or `G/A` (`G`) 
+/// transition.
+fn transition_target(alleles: &[&[u8]]) -> Option<u8> {
+    let mut seen = [false; 4]; // [C, T, G, A]
+    for allele in alleles {
+        let [nucleotide] = allele else { return None };
+        match nucleotide.to_ascii_uppercase() {
+            b'C' => seen[0] = true,
+            b'T' => seen[1] = true,
+            b'G' => seen[2] = true,
+            b'A' => seen[3] = true,
+            _    => return None,
+        }
+    }
+    match seen {
+        [true, true, false, false] => Some(b'C'),
+        [false, false, true, true] => Some(b'G'),
+        _ => None,
+    }
+}
+
+/// Iterate over the whitespace-separated fields of each line of a text file, skipping empty lines, comments (`#`), 
+/// and `track`|`browser` lines. `parse` returns [`None`] whenever a line is invalid.
+fn for_each_record<F>(reader: impl BufRead, mut parse: F) -> Result<(), PanelError>
+where   F: FnMut(&[&str]) -> Option<()>
+{
+    for (i, line) in reader.lines().enumerate() {
+        let line = line.map_err(|source| PanelError::ReadLine{line: i + 1, source})?;
+        if line.trim().is_empty() || line.starts_with('#') || line.starts_with("track") || line.starts_with("browser") {
+            continue
+        }
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        parse(&fields).ok_or_else(|| PanelError::ParseLine{line: i + 1, content: line.clone()})?;
+    }
+    Ok(())
+}
+
+/// Restrict a reference sequence to the [`Site`]s of a panel: every position which is not a panel site (or whose 
+/// reference nucleotide is not the site's target) is replaced with `N`, and thus never considered as a masking candidate.
+/// 
+/// # Parameters
+/// - `sites`    : sorted panel sites of the reference's chromosome.
+/// - `start`    : 0-based coordinate of the first nucleotide of `reference`.
+/// - `reference`: raw reference sequence.
+/// 
+/// # Usage
+/// ```
+/// use pmd_mask::panel::{restrict_reference, Site};
+/// let sites = [Site{start: 11, end: 12, target: None}, Site{start: 13, end: 15, target: Some(b'G')}];
+/// assert_eq!(restrict_reference(&sites, 10, b"ACGTG"), b"NCNNG");
+/// ```
+pub fn restrict_reference(sites: &[Site], start: u64, reference: &[u8]) -> Vec<u8> {
+    let mut restricted = vec![b'N'; reference.len()];
+    let end   = start + reference.len() as u64;
+    let first = sites.partition_point(|site| site.end <= start);
+    for site in sites[first..].iter().take_while(|site| site.start < end) {
+        let offsets = (site.start.max(start) - start) as usize..(site.end.min(end) - start) as usize;
+        for (nucleotide, restricted) in reference[offsets.clone()].iter().zip(restricted[offsets].iter_mut()) {
+            if site.target.is_none_or(|target| target == nucleotide.to_ascii_uppercase()) {
+                *restricted = *nucleotide;
+            }
+        }
+    }
+    restricted
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn transitions() {
+        assert_eq!(transition_target(&[b"C", b"T"]), Some(b'C'));
+        assert_eq!(transition_target(&[b"t", b"c"]), Some(b'C'));
+        assert_eq!(transition_target(&[b"A", b"G"]), Some(b'G'));
+        for alleles in [&[&b"C"[..], b"A"][..], &[b"C", b"T", b"G"], &[b"CT", b"C"], &[b"C"], &[b"C", b"C"]] {
+            assert_eq!(transition_target(alleles), None, "{alleles:?}");
+        }
+    }
+
+    #[test]
+    fn bed_panel() {
+        let panel = SitePanel::from_bed_reader("# header\nchr1\t10\t12\nchr1\t11\t13\nchr1\t20\t30\nchr2\t5\t6\n".as_bytes()).expect("Invalid panel");
+        let intervals = |chr| panel.get(&ChrName::new(chr)).expect("Missing chromosome").iter().map(|site| (site.start, site.end)).collect::<Vec<_>>();
+        assert_eq!(intervals("chr1"), vec![(10, 13), (20, 30)]);
+        assert_eq!(intervals("chr2"), vec![(5, 6)]);
+        assert_eq!(panel.len(), 14);
+
+        // ---- Large intervals are never expanded into single sites.
+        let panel = SitePanel::from_bed_reader("chr1\t0\t248956422\n".as_bytes()).expect("Invalid panel");
+        assert_eq!(panel.get(&ChrName::new("chr1")).map(<[Site]>::len), Some(1));
+        assert_eq!(panel.len(), 248956422);
+        assert!(SitePanel::from_bed_reader("chr1\t10\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn eigenstrat_panel() {
+        let snp = "rs1 1 0.0 1001 C T\nrs2 1 0.0 2001 A C\nrs3 23 0.0 11 G A\n";
+        let panel = SitePanel::from_eigenstrat_reader(snp.as_bytes(), false).expect("Invalid panel");
+        assert_eq!(panel.get(&ChrName::new("1")).map(<[Site]>::len), Some(2));
+        assert_eq!(panel.get(&ChrName::new("X")), Some(&[Site{start: 10, end: 11, target: None}][..]));
+
+        // ---- Duplicate sites are only kept once.
+        let panel = SitePanel::from_eigenstrat_reader("rs1 1 0.0 1001 C T\nrs2 1 0.0 1001 G A\nrs3 1 0.0 1002 C T\n".as_bytes(), true).expect("Invalid panel");
+        assert_eq!(panel.get(&ChrName::new("1")), Some(&[Site{start: 1000, end: 1002, target: Some(b'C')}][..]));
+        assert!(SitePanel::from_eigenstrat_reader("rs1 1 0.0 0 C T\n".as_bytes(), false).is_err());
+    }
+
+    #[test]
+    fn unknown_format() {
+        assert!(matches!(SitePanel::from_path("panel.txt", false), Err(PanelError::UnknownFormat(_))));
+        assert!(matches!(SitePanel::from_path("panel.bed", true), Err(PanelError::MissingAlleles)));
+    }
+
+    #[test]
+    fn restrict() {
+        let sites = [Site{start: 2, end: 3, target: Some(b'C')}, Site{start: 4, end: 5, target: None}, Site{start: 20, end: 21, target: None}];
+        assert_eq!(restrict_reference(&sites, 0, b"ACCGT"), b"NNCNT");
+        assert_eq!(restrict_reference(&sites, 3, b"GT"), b"NT");
+        assert_eq!(restrict_reference(&[], 3, b"GT"), b"NN");
+
+        // ---- Intervals partially overlapping the reference are clipped.
+        let sites = [Site{start: 0, end: 12, target: None}, Site{start: 14, end: 100, target: None}];
+        assert_eq!(restrict_reference(&sites, 10, b"ACGTGA"), b"ACNNGA");
+    }
+}
@@ -8,6 +8,18 @@ pub enum CliError {
     InvalidBamOutputFmt,
 
     #[error("The provided value must either be 0, or a non negative integer. Got {0}")]
-    InvalidThreadValue(#[source] std::num::ParseIntError)
+    InvalidThreadValue(#[source] std::num::ParseIntError),
+
+    #[error("The provided masking character must be a single, printable ASCII character. Got '{0}'")]
+    ParseMaskChar(String),
+
+    #[error("Expected a '<RG-ID|LB>=<misincorporation file>' pair. Got '{0}'")]
+    ParseReadGroupFile(String),
+
+    #[error("Expected a '<min>-<max>=<misincorporation file>' pair. Got '{0}'")]
+    ParseLengthBinFile(String),
+
+    #[error("The provided confidence level must be a number strictly between 0 and 1. Got '{0}'")]
+    InvalidConfidenceLevel(String),
 }
 
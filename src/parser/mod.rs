@@ -1,13 +1,17 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 mod error;
 use error::CliError;
 
-use clap::{Parser, ArgAction, ColorChoice};
+use clap::{Parser, Args, Subcommand, ArgAction, ColorChoice};
 use num_cpus::{self};
 use rust_htslib::bam;
 use log::info;
 
+use pmd_mask::genome::LibraryType;
+use pmd_mask::misincorporation::{ThresholdStrategy, ThresholdOptions, BinomialInterval};
+use pmd_mask::mask::{MaskMode, LengthBin, MissingPolicy};
+
 
 /// Convert the user provided output format string to a htslib-friendly enum
 /// 
@@ -33,6 +37,53 @@ fn parse_output_fmt(s: &str) -> Result<bam::Format, CliError> {
 }
 
 
+/// Parses the user-provided string into a single ASCII character, used as a replacement character when hard-masking.
+/// 
+/// # Errors
+/// Returns a [`CliError::ParseMaskChar`] if the provided string is not exactly one, graphic ASCII character.
+fn parse_mask_char(s: &str) -> Result<u8, CliError> {
+    match s.as_bytes() {
+        [c] if c.is_ascii_graphic() => Ok(*c),
+        _ => Err(CliError::ParseMaskChar(s.to_string())),
+    }
+}
+
+/// Parses a user-provided `<KEY>=<PATH>` pair, mapping a read group (`ID` or `LB`) to a misincorporation file.
+/// 
+/// # Errors
+/// Returns a [`CliError::ParseReadGroupFile`] if the string does not contain any '=' separator, or if either side is empty.
+fn parse_read_group_file(s: &str) -> Result<(String, PathBuf), CliError> {
+    match s.split_once('=') {
+        Some((key, path)) if !key.is_empty() && !path.is_empty() => Ok((key.to_string(), PathBuf::from(path))),
+        _ => Err(CliError::ParseReadGroupFile(s.to_string())),
+    }
+}
+
+/// Parses a user-provided `<min>-<max>=<PATH>` pair, mapping a read length bin to a misincorporation file.
+/// 
+/// # Errors
+/// Returns a [`CliError::ParseLengthBinFile`] if the string does not contain any '=' separator, if the path is empty, or
+/// if the left-hand side is not a valid [`LengthBin`].
+fn parse_length_bin_file(s: &str) -> Result<(LengthBin, PathBuf), CliError> {
+    let err = || CliError::ParseLengthBinFile(s.to_string());
+    match s.split_once('=') {
+        Some((bin, path)) if !path.is_empty() => Ok((bin.parse().map_err(|_| err())?, PathBuf::from(path))),
+        _ => Err(err()),
+    }
+}
+
+/// Parses the user-provided string into a confidence level, i.e. a floating point value within the open interval `(0, 1)`.
+/// 
+/// # Errors
+/// Returns a [`CliError::InvalidConfidenceLevel`] if the string cannot be parsed into a float, or if the value is not 
+/// strictly between 0 and 1.
+fn parse_confidence_level(s: &str) -> Result<f64, CliError> {
+    match s.parse::<f64>() {
+        Ok(level) if level > 0.0 && level < 1.0 => Ok(level),
+        _ => Err(CliError::InvalidConfidenceLevel(s.to_string())),
+    }
+}
+
 /// Parses the user-provided string into a u32, specifying the number of allocated threads
 /// 
 /// # Behavior 
@@ -65,8 +116,12 @@ fn parse_threads(s: &str) -> Result<u32, CliError> {
 /// frequency estimates of MapDamage-v2 (see: https://github.com/ginolhac/mapDamage.git).
 #[derive(Parser, Debug)]
 #[command(name="pmd-mask", author, version, about, long_about = None, color=ColorChoice::Always)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 #[clap(propagate_version = true)]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Misincorporation frequency threshold.
     /// 
     /// This is the misincorporation frequency threshold at which masking is applied on reads.
@@ -77,6 +132,74 @@ pub struct Cli {
     #[arg(short, long, default_value("0.01"))]
     pub threshold: f32,
 
+    /// Strategy used to select the threshold position of each profile (first-below|last-above|moving-average[:<window>]).
+    /// 
+    /// - first-below: use the first position whose misincorporation frequency is lower or equal to --threshold.
+    /// 
+    /// - last-above: use the position following the last position whose misincorporation frequency is greater than 
+    ///   --threshold. This prevents a single, noisy dip of the profile (e.g. at low coverage) from stopping masking early.
+    /// 
+    /// - moving-average: smooth the profile using a centered moving average over <window> positions (default: 5), and 
+    ///   use the first position whose smoothed frequency is lower or equal to --threshold.
+    /// 
+    /// The selected strategy is recorded within the metrics file (see --metrics-file).
+    #[arg(long, default_value("first-below"))]
+    pub threshold_strategy: ThresholdStrategy,
+
+    /// Only consider a position below --threshold once the upper bound of a binomial confidence interval of its 
+    /// misincorporation frequency is (wilson|clopper-pearson).
+    /// 
+    /// Raw misincorporation frequencies are unreliable at low depth: e.g. observing no C>T out of 10 reference 
+    /// Cytosines does not imply the misincorporation frequency is lower than 1%. With this option, the upper bound of 
+    /// the confidence interval is computed from the C>T (or G>A) counts, and the number of reference C (or G) found at
+    /// each position, and compared against --threshold instead of the raw frequency (see --confidence-level).
+    /// 
+    /// - wilson: Wilson score interval.
+    /// 
+    /// - clopper-pearson: Clopper-Pearson ("exact") interval. This interval is more conservative than the Wilson score interval.
+    /// 
+    /// The selected interval is recorded within the metrics file (see --metrics-file).
+    #[arg(long)]
+    pub confidence_interval: Option<BinomialInterval>,
+
+    /// Confidence level of the binomial confidence interval (see --confidence-interval).
+    #[arg(long, default_value("0.95"), requires("confidence_interval"), value_parser(parse_confidence_level))]
+    pub confidence_level: f64,
+
+    /// Minimum number of observed reference C (or G) required at every leading position of a chromosome and strand's profile.
+    /// 
+    /// Leading positions span from the first position of each end, up to and including the position selected as a 
+    /// threshold (see --threshold-strategy), or every position if the threshold is never met. Sparse tail positions,
+    /// which never take part in threshold selection, are thus ignored.
+    /// 
+    /// Small contigs (e.g. chrY, unplaced scaffolds, or MT in low-coverage samples) often hold too few counts to provide
+    /// a stable threshold. Any chromosome and strand falling short of this requirement instead uses a profile pooled 
+    /// across all chromosomes (see --pool-strands). The decision taken for each chromosome and strand is logged.
+    /// 
+    /// A value of 0 disables this requirement.
+    #[arg(long, default_value("0"))]
+    pub min_count: usize,
+
+    /// Additionally pool both strands when computing the genome-wide fallback profile (see --min-count and --missing-contigs).
+    #[arg(long)]
+    pub pool_strands: bool,
+
+    /// How to handle records aligned to contigs which are absent from the misincorporation profile (mask|skip|pooled|fail).
+    /// 
+    /// - mask: mask candidates along the full length of the read.
+    /// 
+    /// - skip: leave the record untouched.
+    /// 
+    /// - pooled: use the thresholds of a genome-wide profile, pooled across all chromosomes (and strands, when using --pool-strands).
+    ///   Requires a misincorporation profile: this policy cannot be used along --thresholds-file or --mask-5p/--mask-3p.
+    /// 
+    /// - fail: abort with an error.
+    /// 
+    /// Note that the single merged entry of a mapDamage run performed with '--merge-reference-sequences' applies to 
+    /// every contig: such contigs are thus never considered missing.
+    #[arg(long, default_value("mask"))]
+    pub missing_contigs: MissingPolicy,
+
     /// Output metrics file.
     /// 
     /// Path to an output tab-separated file, summarizing the 3p and 5p position where the masking threshold was met, 
@@ -89,9 +212,27 @@ pub struct Cli {
     /// Fields columns are '<Chromosome name> <Strand> <5p-end position> <3p-end position>'.
     /// 
     /// "NA" values indicate the threshold was never met for this particular entry thus masking was applied to the whole sequence.
+    /// 
+    /// The table is preceded by '#'-prefixed lines, recording the selected --threshold-strategy and --confidence-interval.
+    /// 
+    /// Once masking is complete, '#'-prefixed summary lines are appended, reporting the number of processed and masked
+    /// reads, along with the number of bases masked at each end when masking every candidate, and when only masking 
+    /// mismatches (see --mismatches-only).
     #[arg(short='M', long, required(false))]
     pub metrics_file: Option<PathBuf>,
 
+    /// Output JSON metrics report.
+    /// 
+    /// Path to an output JSON file, reporting the masking thresholds of each chromosome and strand found within the 
+    /// header of the input alignment file, along with the misincorporation frequency and counts found at the selected 
+    /// position, the reason of any fallback (e.g. --min-count, --missing-contigs), and the number of processed reads,
+    /// masked reads and masked bases of each chromosome and strand. Overall statistics are reported within 'summary'.
+    /// 
+    /// The report is formatted as a MultiQC custom content table: name the file with a '_mqc.json' suffix to let
+    /// MultiQC pick it up.
+    #[arg(long, required(false))]
+    pub json_metrics: Option<PathBuf>,
+
     /// Input alignment file (SAM|BAM|CRAM)
     /// 
     /// Input bam file, on which pmd-masking should be performed. When unspecified, the pmd-mask will look for standard input.
@@ -106,7 +247,8 @@ pub struct Cli {
 
     /// Output format. (SAM|BAM|CRAM). 
     /// 
-    /// If unspecified: Will output to SAM if stdout is targeted. Or use the original input format.
+    /// If unspecified: the format is inferred from the extension of --output ('.sam', '.bam', '.cram'), or from the
+    /// format of the input alignment file (e.g. when targeting stdout). Defaults to SAM if neither is known.
     #[arg(short='O', long, required(false), value_parser(parse_output_fmt))]
     pub output_fmt: Option<bam::Format>,
 
@@ -123,8 +265,39 @@ pub struct Cli {
     /// Path to a reference genome, in fasta file format. The provided file must be indexed, and the corresponding .fai file should be located at the same directory, and carry the same name.
     /// 
     /// The provided reference genome must, of course, be the same as the one used to align the input sequences. At this stage, pmd-mask does **NOT** ensure the provided reference is consistent with the input's header information, and thus using a different reference may result in undefined behavior.
-    #[arg(short='f', long)]
-    pub reference: PathBuf,
+    #[arg(short='f', long, required(true))]
+    pub reference: Option<PathBuf>,
+
+    /// Only mask records overlapping this region (samtools-style: 'chr', 'chr:start', 'chr:start-' or 'chr:start-end').
+    /// 
+    /// Coordinates are 1-based and inclusive. This option may be specified multiple times, and combined with 
+    /// --regions-file. Records found outside of the requested regions are not written to the output.
+    /// 
+    /// As with samtools, a region matching the name of a contig of the input's header is considered as the whole contig,
+    /// even if this name contains ':' (e.g. 'HLA-A*01:01:01:01').
+    /// 
+    /// Region-restricted processing requires a coordinate-sorted and indexed input alignment file (see --bam).
+    #[arg(long = "region", value_name = "REGION", requires("bam"))]
+    pub regions: Vec<String>,
+
+    /// Only mask records overlapping the intervals of this BED file.
+    /// 
+    /// Only the first three fields (chrom, chromStart, chromEnd) are used. Coordinates are 0-based and half-open.
+    /// Overlapping intervals are merged, and records overlapping several intervals are only written once.
+    /// 
+    /// Region-restricted processing requires a coordinate-sorted and indexed input alignment file (see --bam).
+    #[arg(long, requires("bam"))]
+    pub regions_file: Option<PathBuf>,
+
+    /// Maximum number of reference contigs kept in memory, when the input is not coordinate-sorted.
+    /// 
+    /// pmd-mask loads each reference contig once, in its entirety, and keeps it in memory while masking. For 
+    /// coordinate-sorted input (i.e. '@HD SO:coordinate'), a single contig is kept at a time. Otherwise, up to this 
+    /// number of contigs are cached, and the least recently used contig is evicted whenever a new one must be loaded. 
+    /// 
+    /// Increasing this value reduces the number of contig reloads on unsorted input, at the cost of a higher memory footprint.
+    #[arg(long, default_value("4"), value_parser(clap::value_parser!(u32).range(1..)))]
+    pub max_cached_contigs: u32,
 
     /// Input misincorporation file
     /// 
@@ -132,8 +305,168 @@ pub struct Cli {
     /// This file provides with strand-specific PMD frequency estimates, which are then used by pmd-mask to compute the pb thresholds at which masking should be performed.
     /// 
     /// Note that this file MUST have been obtained using the same input bam file as the one used with this program. Applying pmd-mask using a misincorporation file from a different sample may result with imprecise thresholds estimates, and thus either create (over|under)correction. Note that pmd-mask does not, and most probably cannot check that the two files are consistent.
-    #[arg(short, long)]
-    pub misincorporation: PathBuf,
+    /// 
+    /// Not required when using --estimate-damage, --thresholds-file, or fixed-length masking (see --mask-5p and --mask-3p).
+    #[arg(short, long, required_unless_present_any(["estimate_damage", "mask_5p", "thresholds_file"]), conflicts_with_all(["estimate_damage", "mask_5p", "thresholds_file"]))]
+    pub misincorporation: Option<PathBuf>,
+
+    /// Load masking thresholds from a previous metrics file, instead of computing them from a misincorporation file.
+    /// 
+    /// Path leading to a metrics file, previously written by pmd-mask using --metrics-file. This allows thresholds to be
+    /// computed once per sample, reviewed and edited, and then reused across reruns and shards. Lines starting with '#'
+    /// are ignored, and "NA" positions apply masking along the full length of reads. 
+    /// 
+    /// Since no misincorporation profile is available, --threshold and related options have no effect, and 
+    /// '--mask-mode rescale' is rejected.
+    #[arg(long, conflicts_with_all(["estimate_damage", "mask_5p"]))]
+    pub thresholds_file: Option<PathBuf>,
+
+    /// Estimate the misincorporation profile from the input alignment file, instead of using a misincorporation file.
+    /// 
+    /// pmd-mask then performs a first pass over the input alignment file, and counts the number of reference C/G, along 
+    /// with C>T and G>A substitutions, for each chromosome, strand, read end and position, in the spirit of mapDamage
+    /// (see 'pmd-mask profile'). Masking is applied during a second pass. This requires a seekable input file 
+    /// (see --bam): the standard input cannot be used. 
+    #[arg(long, requires("bam"))]
+    pub estimate_damage: bool,
+
+    /// Number of positions profiled from each end of the reads, when using --estimate-damage.
+    /// 
+    /// Read ends whose misincorporation frequency never falls below --threshold within this number of positions are
+    /// masked along their full length.
+    #[arg(long, default_value("70"), requires("estimate_damage"), value_parser(clap::value_parser!(u32).range(1..)))]
+    pub profile_length: u32,
+
+    /// Mask candidate nucleotides (e.g. reference C's, for double-stranded libraries) found within the first N positions
+    /// of every read, without using any misincorporation profile.
+    /// 
+    /// Fixed-length masking is useful whenever no misincorporation profile can be obtained, e.g. for libraries which 
+    /// are too small to be profiled. Every chromosome and strand of the input's header is then assigned the same 
+    /// thresholds. Requires --mask-3p. Cannot be used along '--mask-mode rescale'.
+    #[arg(long, requires("mask_3p"), conflicts_with("estimate_damage"))]
+    pub mask_5p: Option<usize>,
+
+    /// Mask candidate nucleotides (e.g. reference G's, for double-stranded libraries) found within the last M positions
+    /// of every read, without using any misincorporation profile (see --mask-5p).
+    #[arg(long, requires("mask_5p"))]
+    pub mask_3p: Option<usize>,
+
+    /// Use a separate misincorporation file for the records of a read group ('<RG-ID|LB>=<misincorporation file>').
+    /// 
+    /// The key is matched against the ID, then the library (LB) of every '@RG' record of the input's header. Records 
+    /// are then assigned to their read group using their 'RG' aux tag, and masked using the thresholds computed from the
+    /// corresponding misincorporation file. Records without an 'RG' tag, or belonging to any other read group are masked
+    /// using the thresholds of --misincorporation. This option may be specified multiple times, e.g. to mask libraries 
+    /// with and without UDG-treatment within the same file.
+    #[arg(long = "rg-misincorporation", value_name = "KEY=FILE", value_parser(parse_read_group_file))]
+    pub read_group_misincorporations: Vec<(String, PathBuf)>,
+
+    /// Use a separate misincorporation file for the records of a read length bin ('<min>-<max>=<misincorporation file>').
+    /// 
+    /// Bins are inclusive, and may be left open (e.g. '70-=long.txt'). The length of a record is defined as its absolute
+    /// template length (TLEN) when it is part of a proper pair, and as the length of its sequence otherwise. Records 
+    /// whose length does not lie within any bin are masked using the thresholds of --misincorporation. This option may 
+    /// be specified multiple times, but bins may not overlap. Note that --rg-misincorporation takes precedence.
+    /// 
+    /// Each file should be obtained by running mapDamage on the records of the corresponding length bin.
+    #[arg(long = "length-misincorporation", value_name = "BIN=FILE", value_parser(parse_length_bin_file))]
+    pub length_misincorporations: Vec<(LengthBin, PathBuf)>,
+
+    /// Library preparation protocol (double|single).
+    /// 
+    /// - double: Double-stranded libraries exhibit C>T transitions at the 5p end, and G>A transitions at the 3p end of reads.
+    ///   pmd-mask will thus mask reference Cytosines at the 5p end, and reference Guanines at the 3p end.
+    /// 
+    /// - single: Single-stranded libraries exhibit C>T transitions at both the 5p and 3p ends of reads. pmd-mask will thus 
+    ///   use C>T misincorporation frequencies to compute thresholds at both ends, and only mask reference Cytosines 
+    ///   (i.e. Guanines for reads aligned on the reverse strand).
+    #[arg(short='l', long, default_value("double"))]
+    pub library: LibraryType,
+
+    /// Masking mode (hard|soft|rescale|softclip|annotate).
+    /// 
+    /// - hard: Replace masked nucleotides with the character specified with --mask-char, and set their base quality to the 
+    ///   value of --mask-quality.
+    /// 
+    /// - soft: Keep the original nucleotides, and only set their base quality to the value of --mask-quality. This is useful
+    ///   when working with genotypers which are able to weight low-quality bases.
+    /// 
+    /// - rescale: Keep the original nucleotides, and rescale the base quality of every putative deamination product 
+    ///   (i.e. T over a reference C, or A over a reference G) according to its position-specific misincorporation frequency,
+    ///   found within the misincorporation file. Base qualities are computed by combining the sequencing error with the 
+    ///   probability of damage, in the spirit of mapDamage's rescaling. --threshold, --mask-char and --mask-quality are 
+    ///   ignored in this mode.
+    /// 
+    /// - softclip: Soft-clip reads from either end, up to the last masking candidate, and rewrite their CIGAR accordingly.
+    ///   The alignment start of reads is shifted when clipping their 5p end. Records which would end up entirely 
+    ///   soft-clipped are hard-masked instead. Note that the mate information (RNEXT, PNEXT, TLEN) of paired reads is not
+    ///   updated: consider running 'samtools fixmate' afterwards if required.
+    /// 
+    /// - annotate: Keep the original nucleotides and base qualities, and list the read offsets which would have been 
+    ///   masked within a 'ZO:B:I' aux tag (0-based, in the orientation of the stored sequence), along with their number
+    ///   within a 'ZC:i' aux tag. This lets downstream tools decide whether to use putatively damaged positions.
+    #[arg(long, default_value("hard"))]
+    pub mask_mode: MaskMode,
+
+    /// Replacement character of masked nucleotides.
+    /// 
+    /// Only applies when using '--mask-mode hard'.
+    #[arg(long, default_value("N"), value_parser(parse_mask_char))]
+    pub mask_char: u8,
+
+    /// Phred base quality of masked nucleotides.
+    #[arg(long, default_value("0"), value_parser(clap::value_parser!(u8).range(0..=93)))]
+    pub mask_quality: u8,
+
+    /// Only mask actual fragment ends of unmerged paired-end reads.
+    /// 
+    /// By default, pmd-mask considers both ends of a read as the ends of the sequenced DNA fragment. This is usually not
+    /// the case for unmerged paired-end reads, whose 3' end often lies within the fragment. With this option, pmd-mask 
+    /// uses the FLAG and mate information of each read to only mask its 5' end, along with its 3' end if and only if the
+    /// alignment reaches the end of the template (as defined by TLEN).
+    /// 
+    /// Unpaired reads, improper pairs and pairs without a template length are masked on both ends.
+    #[arg(long)]
+    pub fragment_aware: bool,
+
+    /// Only mask candidates carrying a deamination product.
+    /// 
+    /// By default, pmd-mask masks every read position whose reference nucleotide is a candidate (i.e. a reference C at
+    /// the 5p end, or a reference G at the 3p end), even when the read carries the reference nucleotide. With this 
+    /// option, pmd-mask only masks candidates where the read carries the corresponding deamination product: a T over a
+    /// reference C, or an A over a reference G. This keeps informative bases, at the cost of a less conservative masking.
+    #[arg(long)]
+    pub mismatches_only: bool,
+
+    /// Keep track of the original nucleotides and base qualities of masked reads, so that they may later be restored.
+    /// 
+    /// The read offsets of every altered nucleotide are listed within a 'ZR:B:I' aux tag, while their original 
+    /// nucleotides and base qualities are respectively kept within 'ZB:Z' and 'ZQ:B:C' aux tags. When using
+    /// '--mask-mode softclip', the original CIGAR and 1-based alignment start of clipped reads are also kept within 
+    /// 'ZX:Z' and 'ZP:i' aux tags. Use 'pmd-mask unmask' to restore the original records.
+    #[arg(long)]
+    pub reversible: bool,
+
+    /// Only mask candidates located at the sites of a SNP panel (e.g. 1240K).
+    /// 
+    /// Accepted formats are inferred from the file extension:
+    /// 
+    ///   - BED ('.bed'): 0-based, half-open intervals. Every position of each interval is considered as a site.
+    /// 
+    ///   - VCF ('.vcf', '.vcf.gz', '.bcf'): each record is considered as a site.
+    /// 
+    ///   - EIGENSTRAT ('.snp'): 'ID CHROM GENETIC_POS PHYSICAL_POS REF ALT'. Chromosomes 23, 24 and 90 are respectively renamed X, Y and MT.
+    /// 
+    /// Chromosome names must match those of the input alignment file.
+    #[arg(long)]
+    pub panel: Option<PathBuf>,
+
+    /// Only consider SNP panel sites whose alleles form a C/T or G/A transition.
+    /// 
+    /// Reference Cytosines are then only masked at C/T sites, and reference Guanines at G/A sites. Requires a VCF or
+    /// EIGENSTRAT panel (see --panel), since BED files do not provide alleles.
+    #[arg(long, requires("panel"))]
+    pub panel_transitions_only: bool,
 
     /// Set the verbosity level (-v|-vv|-vvv)
     /// 
@@ -142,21 +475,23 @@ pub struct Cli {
     /// Levels: -v (Info) | -vv (Debug) | -vvv (Trace)  
     /// 
     /// Warn level is active no matter what, but can be disabled by using the --quiet argument
-    #[arg(short='v', long, action = ArgAction::Count)]
+    #[arg(short='v', long, action = ArgAction::Count, global = true)]
     pub verbose: u8,
 
     /// Disable warnings.
     /// 
     /// By defaults, warnings are emmited and redirected to stderr no matter the verbosity level.
     /// Use this argument to disable Warnings. Only errors will be displayed. Note that using this argument will also have the effect of removing verbosity.
-    #[clap(short='q', long)]
+    #[clap(short='q', long, global = true)]
     pub quiet: bool,
 
     /// Set additional worker threads
     ///
-    /// Set the number of additional (de)compression threads. Setting this value to zero will preempt all the available cores.
+    /// Set the number of additional (de)compression and masking threads. Setting this value to zero will preempt all the available cores.
+    /// 
+    /// When this value is greater than one, records are read in batches and masked on a separate pool of worker threads of the same size, before being written back in their original order.
     /// 
-    /// Note that this requires instantiating a Threadpool, which also lives in its own thread. Thus setting this to 8 will actually instantiate 10 threads: (1 runtime thread, 8 worker threads, 1 threadpool manager)
+    /// Note that this requires instantiating a htslib Threadpool, which also lives in its own thread. Thus setting this to 8 will actually instantiate 18 threads: (1 runtime thread, 8 (de)compression threads, 1 threadpool manager, 8 masking threads)
     /// 
     /// # General guidelines:  
     /// 
@@ -165,10 +500,108 @@ pub struct Cli {
     ///     - If your output is in BAM/CRAM format, set this value to your liking. Note that anything greater than 8 threads is rarely beneficial for compressing bam files., especially for low coverage samples.
     /// 
     /// 
-    #[clap(short='@', long, default_value("1"), value_parser(parse_threads))]
+    #[clap(short='@', long, default_value("1"), value_parser(parse_threads), global = true)]
     pub threads: u32
 }
 
+impl Cli {
+    /// Gather the user-defined parameters dictating how masking thresholds are selected (see [`ThresholdOptions`]).
+    pub fn threshold_options(&self) -> ThresholdOptions {
+        ThresholdOptions {
+            strategy  : self.threshold_strategy,
+            interval  : self.confidence_interval,
+            confidence: self.confidence_level,
+            min_count : self.min_count,
+            pool_strands: self.pool_strands,
+        }
+    }
+
+    /// Path to the reference genome. Always set when masking, since clap enforces its presence whenever no subcommand
+    /// is requested.
+    pub fn reference(&self) -> &Path {
+        self.reference.as_deref().expect("Missing reference genome")
+    }
+
+}
+
+/// Additional pmd-mask commands. Masking is performed when no command is specified.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Restore the original records of an alignment file, masked using --reversible.
+    Unmask(UnmaskArgs),
+
+    /// Estimate the misincorporation profile of an alignment file, and write it as a mapDamage-v2 'misincorporation.txt' file.
+    Profile(ProfileArgs),
+}
+
+/// Arguments of the 'unmask' command.
+#[derive(Args, Debug)]
+pub struct UnmaskArgs {
+    /// Input alignment file (SAM|BAM|CRAM), masked using --reversible.
+    /// 
+    /// When unspecified, pmd-mask will look for standard input.
+    #[arg(short, long, required(false))]
+    pub bam: Option<PathBuf>,
+
+    /// Output file (SAM|BAM|CRAM. See --output-fmt).
+    /// 
+    /// Output bam file If not specified, print to stdout.
+    #[arg(short, long, required(false))]
+    pub output: Option<PathBuf>,
+
+    /// Output format. (SAM|BAM|CRAM). 
+    /// 
+    /// If unspecified: the format is inferred from the extension of --output, or from the format of the input 
+    /// alignment file (e.g. when targeting stdout). Defaults to SAM if neither is known.
+    #[arg(short='O', long, required(false), value_parser(parse_output_fmt))]
+    pub output_fmt: Option<bam::Format>,
+
+    /// Output compression level. 
+    /// 
+    /// Set the compression for BAM|CRAM output files. 9 if unspecified
+    #[arg(long, default_value("9"))]
+    pub compress_level: u32,
+
+    /// Reference genome. (fasta|fa)[.gz]
+    /// 
+    /// Only required when reading or writing CRAM files.
+    #[arg(short='f', long)]
+    pub reference: Option<PathBuf>,
+}
+
+/// Arguments of the 'profile' command.
+#[derive(Args, Debug)]
+pub struct ProfileArgs {
+    /// Input alignment file (SAM|BAM|CRAM)
+    /// 
+    /// When unspecified, pmd-mask will look for standard input.
+    #[arg(short, long, required(false))]
+    pub bam: Option<PathBuf>,
+
+    /// Output misincorporation file.
+    /// 
+    /// Counts are written using the format of mapDamage-v2's 'misincorporation.txt' file, and may thus directly be used
+    /// with --misincorporation. Only aligned positions are profiled: the indel and soft-clip columns are always set to
+    /// zero. If not specified, print to stdout.
+    #[arg(short, long, required(false))]
+    pub output: Option<PathBuf>,
+
+    /// Reference genome. (fasta|fa)[.gz]
+    /// 
+    /// Path to an indexed reference genome, in fasta file format. This must be the same as the one used to align the 
+    /// input sequences.
+    #[arg(short='f', long)]
+    pub reference: PathBuf,
+
+    /// Number of positions profiled from each end of the reads.
+    #[arg(long, default_value("70"), value_parser(clap::value_parser!(u32).range(1..)))]
+    pub length: u32,
+
+    /// Maximum number of reference contigs kept in memory, when the input is not coordinate-sorted.
+    #[arg(long, default_value("4"), value_parser(clap::value_parser!(u32).range(1..)))]
+    pub max_cached_contigs: u32,
+}
+
 
 
 
@@ -198,6 +631,49 @@ mod test {
         }
     }
 
+    #[test]
+    fn mask_char_parser() {
+        for (input, want) in [("N", b'N'), ("n", b'n'), ("X", b'X'), ("-", b'-')] {
+            assert!(matches!(parse_mask_char(input), Ok(c) if c == want));
+        }
+
+        for invalid in ["", "NN", " ", "é", "\t"] {
+            assert!(parse_mask_char(invalid).is_err())
+        }
+    }
+
+    #[test]
+    fn read_group_file_parser() {
+        for (input, key, path) in [("lib1=misincorporation.txt", "lib1", "misincorporation.txt"), ("1=a=b.txt", "1", "a=b.txt")] {
+            assert!(matches!(parse_read_group_file(input), Ok((k, p)) if k == key && p == Path::new(path)));
+        }
+
+        for invalid in ["", "lib1", "=misincorporation.txt", "lib1="] {
+            assert!(parse_read_group_file(invalid).is_err())
+        }
+    }
+
+    #[test]
+    fn length_bin_file_parser() {
+        assert!(matches!(parse_length_bin_file("0-49=short.txt"), Ok((LengthBin{min: 0, max: Some(49)}, p)) if p == Path::new("short.txt")));
+        assert!(matches!(parse_length_bin_file("50-=long.txt"), Ok((LengthBin{min: 50, max: None}, p)) if p == Path::new("long.txt")));
+
+        for invalid in ["", "0-49", "0-49=", "49-0=short.txt", "=short.txt", "short=short.txt"] {
+            assert!(parse_length_bin_file(invalid).is_err())
+        }
+    }
+
+    #[test]
+    fn confidence_level_parser() {
+        for (input, want) in [("0.95", 0.95), ("0.5", 0.5), ("0.999", 0.999)] {
+            assert!(matches!(parse_confidence_level(input), Ok(level) if level == want));
+        }
+
+        for invalid in ["", "0", "1", "1.5", "-0.95", "95%", "NaN"] {
+            assert!(parse_confidence_level(invalid).is_err())
+        }
+    }
+
     #[test]
     fn output_format_parser() {
 
@@ -0,0 +1,318 @@
+use std::{collections::BTreeMap, io::{self, Write}};
+
+use rust_htslib::bam::{self, ext::BamRecordExtensions};
+
+use crate::genome::{ChrName, Orientation, Strand, Position, LibraryType};
+use crate::misincorporation::{Misincorporations, MisincorporationRecord};
+
+/// Default number of positions profiled from each end of the reads. Matches the default `--length` of mapDamage-v2.
+pub const DEFAULT_PROFILE_LENGTH: usize = 70;
+
+/// Column names of a [mapDamage-v2](https://github.com/ginolhac/mapDamage)'s [`misincorporation.txt`](https://ginolhac.github.io/mapDamage/#a4) file.
+const HEADER: [&str; 30] = [
+    "Chr", "End", "Std", "Pos", "A", "C", "G", "T", "Total",
+    "G>A", "C>T", "A>G", "T>C", "A>C", "A>T", "C>G", "C>A", "T>G", "T>A", "G>C", "G>T",
+    "A>-", "T>-", "C>-", "G>-", "->A", "->T", "->C", "->G", "S"
+];
+
+/// Substitutions reported by mapDamage-v2, in column order, as `(reference, read)` nucleotide pairs.
+const SUBSTITUTIONS: [(u8, u8); 12] = [
+    (b'G', b'A'), (b'C', b'T'), (b'A', b'G'), (b'T', b'C'), (b'A', b'C'), (b'A', b'T'),
+    (b'C', b'G'), (b'C', b'A'), (b'T', b'G'), (b'T', b'A'), (b'G', b'C'), (b'G', b'T'),
+];
+
+const NUCLEOTIDES: [u8; 4] = [b'A', b'C', b'G', b'T'];
+
+/// Number of reference and read nucleotide pairs observed at a given position, indexed as `[reference][read]`
+/// (see [`nucleotide_index()`]).
+type PairCounts = [[u64; 4]; 4];
+
+/// Return the index of a nucleotide within [`NUCLEOTIDES`], or [`None`] for ambiguous nucleotides.
+#[inline]
+fn nucleotide_index(nucleotide: u8) -> Option<usize> {
+    match nucleotide.to_ascii_uppercase() {
+        b'A' => Some(0),
+        b'C' => Some(1),
+        b'G' => Some(2),
+        b'T' => Some(3),
+        _    => None,
+    }
+}
+
+/// Return the complement of a nucleotide index (see [`nucleotide_index()`]).
+#[inline]
+fn complement(index: usize) -> usize {
+    3 - index
+}
+
+/// Streaming estimator of the misincorporation profile of an alignment file, in the spirit of
+/// [mapDamage-v2](https://github.com/ginolhac/mapDamage).
+///
+/// For every chromosome, strand and read end, and for each of the first `length` positions of that end, a
+/// [`DamageProfiler`] counts the reference nucleotides, along with every observed substitution. Reads are considered
+/// in their sequencing orientation: the sequence and reference of reads aligned on the reverse strand are
+/// reverse-complemented, so that `C>T` transitions are always expected at the 5p end.
+///
+/// Positions are counted from the first and last aligned nucleotide of each read, i.e. soft-clipped nucleotides are
+/// ignored. Only aligned positions (`M`, `=`, `X` CIGAR operations) are counted: insertions, deletions and soft-clips
+/// are not profiled, and their columns are always set to zero.
+///
+/// # Usage
+/// ```
+/// use std::error::Error;
+/// use rust_htslib::{faidx, bam::{self, Read}};
+/// use pmd_mask::profiler::DamageProfiler;
+/// use pmd_mask::reference::ReferenceCache;
+/// use pmd_mask::genome::{ChrName, LibraryType};
+/// fn main() -> Result<(), Box<dyn Error>> {
+///     let mut reader    = bam::Reader::from_path("tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam")?;
+///     let mut reference = ReferenceCache::new(faidx::Reader::from_path("tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz")?, 1)?;
+///     let mut profiler  = DamageProfiler::new(25);
+///
+///     let chromosome = ChrName::new("MT");
+///     for record in reader.records() {
+///         let record = record?;
+///         profiler.add_record(&record, &chromosome, &reference.fetch_contig(0, &chromosome)?);
+///     }
+///
+///     assert_eq!(profiler.reads(), 1000);
+///     let profile = profiler.misincorporations(LibraryType::DoubleStranded);
+///     assert_eq!(profile.len(), 2 * 2 * 25);
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct DamageProfiler {
+    length: usize,
+    reads : u64,
+    counts: BTreeMap<usize, (ChrName, Vec<PairCounts>)>,
+}
+
+impl DamageProfiler {
+    /// Instantiate an empty [`DamageProfiler`], profiling the first `length` positions of each read end.
+    pub fn new(length: usize) -> Self {
+        Self { length, reads: 0, counts: BTreeMap::new() }
+    }
+
+    /// Return the number of records which were profiled.
+    pub fn reads(&self) -> u64 {
+        self.reads
+    }
+
+    /// Return the index of a position within the counts of a chromosome.
+    #[inline]
+    fn index(&self, end: Orientation, strand: Strand, position: usize) -> usize {
+        let end    = match end    { Orientation::ThreePrime => 0, Orientation::FivePrime => 1 };
+        let strand = match strand { Strand::Forward         => 0, Strand::Reverse       => 1 };
+        (end * 2 + strand) * self.length + position
+    }
+
+    /// Count the nucleotides and substitutions of a single record, given the name and the full sequence of its
+    /// reference `contig`.
+    ///
+    /// Unmapped, secondary, supplementary, QC-failed and duplicate records are ignored. Ambiguous nucleotides
+    /// (within either the read, or the reference) are not counted.
+    pub fn add_record(&mut self, record: &bam::Record, chromosome: &ChrName, contig: &[u8]) {
+        if record.is_unmapped() || record.is_secondary() || record.is_supplementary() || record.is_quality_check_failed() || record.is_duplicate() {
+            return
+        }
+        let Ok(tid) = usize::try_from(record.tid()) else { return };
+
+        // ---- Records without a stored sequence (SEQ '*') carry no information.
+        if record.seq_len() == 0 {
+            return
+        }
+
+        let seq       = record.seq().as_bytes();
+        let cigar     = record.cigar();
+        let (first, last) = (cigar.leading_softclips() as usize, seq.len().saturating_sub(cigar.trailing_softclips() as usize + 1));
+        let strand    = if record.is_reverse() { Strand::Reverse } else { Strand::Forward };
+
+        let length    = self.length;
+        let positions = record.aligned_pairs().map(|[readpos, refpos]| [readpos as usize, refpos as usize]).collect::<Vec<_>>();
+        let mut pairs = Vec::with_capacity(positions.len());
+        for [readpos, refpos] in positions {
+            let (Some(reference), Some(base)) = (contig.get(refpos).and_then(|n| nucleotide_index(*n)), nucleotide_index(seq[readpos])) else { continue };
+
+            // ---- Orient the read and the reference according to the sequencing direction.
+            let (from_left, from_right) = (readpos - first, last - readpos);
+            let (reference, base, five_prime, three_prime) = match strand {
+                Strand::Forward => (reference, base, from_left, from_right),
+                Strand::Reverse => (complement(reference), complement(base), from_right, from_left),
+            };
+            for (end, position) in [(Orientation::FivePrime, five_prime), (Orientation::ThreePrime, three_prime)] {
+                if position < length {
+                    pairs.push((self.index(end, strand, position), reference, base));
+                }
+            }
+        }
+
+        let (_, counts) = self.counts.entry(tid).or_insert_with(|| (chromosome.clone(), vec![[[0; 4]; 4]; 4 * length]));
+        for (index, reference, base) in pairs {
+            counts[index][reference][base] += 1;
+        }
+        self.reads += 1;
+    }
+
+    /// Iterate over every profiled position, in the order of a mapDamage-v2 `misincorporation.txt` file: i.e. by
+    /// chromosome (in the order of the alignment file's header), then by end (3p, 5p), strand (+, -) and position.
+    ///
+    /// Chromosomes without any profiled record are skipped.
+    fn positions(&self) -> impl Iterator<Item = (&ChrName, Orientation, Strand, usize, &PairCounts)> {
+        self.counts.values().flat_map(move |(chromosome, counts)| {
+            [Orientation::ThreePrime, Orientation::FivePrime].into_iter().flat_map(move |end| {
+                [Strand::Forward, Strand::Reverse].into_iter().flat_map(move |strand| {
+                    (0..self.length).map(move |position| (chromosome, end, strand, position, &counts[self.index(end, strand, position)]))
+                })
+            })
+        })
+    }
+
+    /// Obtain the full misincorporation profile as a [`Misincorporations`] struct, i.e. what would be obtained by
+    /// reading the output of [`DamageProfiler::write()`] with [`Misincorporations::profile_from_path()`]
+    pub fn misincorporations(&self, library: LibraryType) -> Misincorporations {
+        let (c, g, t, a) = (1, 2, 3, 0);
+        let records = self.positions().map(|(chromosome, end, strand, position, counts)| MisincorporationRecord {
+            chromosome: chromosome.clone(),
+            end,
+            strand,
+            position: Position::new(position + 1),
+            c_counts: counts[c].iter().sum::<u64>() as usize,
+            g_counts: counts[g].iter().sum::<u64>() as usize,
+            c_to_t  : counts[c][t] as usize,
+            g_to_a  : counts[g][a] as usize,
+        }).collect();
+        Misincorporations::new(records, library)
+    }
+
+    /// Write the full misincorporation profile in the format of a [mapDamage-v2](https://github.com/ginolhac/mapDamage)'s
+    /// [`misincorporation.txt`](https://ginolhac.github.io/mapDamage/#a4) file. Every line of `comments` is written
+    /// as a '#'-prefixed line, before the column names.
+    ///
+    /// # Errors
+    /// Bubbles out any [`io::Error`] arising from `writer`.
+    pub fn write<W: Write>(&self, writer: &mut W, comments: &[String]) -> io::Result<()> {
+        for comment in comments {
+            writeln!(writer, "# {comment}")?;
+        }
+        writeln!(writer, "{}", HEADER.join("\t"))?;
+        for (chromosome, end, strand, position, counts) in self.positions() {
+            let nucleotides = NUCLEOTIDES.iter().map(|n| counts[nucleotide_index(*n).unwrap()].iter().sum::<u64>()).collect::<Vec<_>>();
+            write!(writer, "{chromosome}\t{end}\t{strand}\t{}", position + 1)?;
+            for count in nucleotides.iter() {
+                write!(writer, "\t{count}")?;
+            }
+            write!(writer, "\t{}", nucleotides.iter().sum::<u64>())?;
+            for (reference, base) in SUBSTITUTIONS {
+                write!(writer, "\t{}", counts[nucleotide_index(reference).unwrap()][nucleotide_index(base).unwrap()])?;
+            }
+            // ---- Indels and soft-clips are not profiled.
+            writeln!(writer, "{}", "\t0".repeat(HEADER.len() - 9 - SUBSTITUTIONS.len()))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_htslib::bam::record::{Cigar, CigarString};
+
+    fn dummy_record(seq: &[u8], cigar: &[Cigar], reverse: bool) -> bam::Record {
+        let mut record = bam::Record::new();
+        record.set(b"read", Some(&CigarString(cigar.to_vec())), seq, &vec![30; seq.len()]);
+        record.set_tid(0);
+        record.set_pos(0);
+        if reverse { record.set_reverse() }
+        record
+    }
+
+    fn counts_at(profile: &Misincorporations, end: Orientation, strand: Strand, position: usize) -> &MisincorporationRecord {
+        profile.iter()
+            .find(|record| record.end == end && record.strand == strand && record.position == Position::new(position))
+            .expect("Missing position")
+    }
+
+    #[test]
+    fn forward_read() {
+        let chromosome = ChrName::new("chr1");
+        let mut profiler = DamageProfiler::new(3);
+        // 5p: C>T at the first position | 3p: G>A at the last position.
+        profiler.add_record(&dummy_record(b"TCAGA", &[Cigar::Match(5)], false), &chromosome, b"CCAGG");
+
+        let profile = profiler.misincorporations(LibraryType::DoubleStranded);
+        assert_eq!(profile.len(), 2 * 2 * 3);
+
+        let first = counts_at(&profile, Orientation::FivePrime, Strand::Forward, 1);
+        assert_eq!((first.c_counts, first.c_to_t), (1, 1));
+        let second = counts_at(&profile, Orientation::FivePrime, Strand::Forward, 2);
+        assert_eq!((second.c_counts, second.c_to_t), (1, 0));
+        let last = counts_at(&profile, Orientation::ThreePrime, Strand::Forward, 1);
+        assert_eq!((last.g_counts, last.g_to_a), (1, 1));
+        let reverse = counts_at(&profile, Orientation::FivePrime, Strand::Reverse, 1);
+        assert_eq!((reverse.c_counts, reverse.g_counts), (0, 0));
+    }
+
+    #[test]
+    fn reverse_read() {
+        let chromosome = ChrName::new("chr1");
+        let mut profiler = DamageProfiler::new(2);
+        // Reverse-complemented, the read and reference are respectively 'TCTGT' and 'CCTGC':
+        // i.e. C>T at both the 5p and 3p end.
+        profiler.add_record(&dummy_record(b"ACAGA", &[Cigar::Match(5)], true), &chromosome, b"GCAGG");
+
+        let profile = profiler.misincorporations(LibraryType::DoubleStranded);
+        let first = counts_at(&profile, Orientation::FivePrime, Strand::Reverse, 1);
+        assert_eq!((first.c_counts, first.c_to_t), (1, 1));
+        let last = counts_at(&profile, Orientation::ThreePrime, Strand::Reverse, 1);
+        assert_eq!((last.c_counts, last.c_to_t, last.g_counts), (1, 1, 0));
+    }
+
+    #[test]
+    fn ignore_missing_sequence() {
+        let chromosome = ChrName::new("chr1");
+        let mut profiler = DamageProfiler::new(2);
+        profiler.add_record(&dummy_record(b"", &[Cigar::Match(5)], false), &chromosome, b"CCAGG");
+        assert_eq!(profiler.reads, 0);
+        assert!(profiler.misincorporations(LibraryType::DoubleStranded).is_empty());
+    }
+
+    #[test]
+    fn ignore_softclips() {
+        let chromosome = ChrName::new("chr1");
+        let mut profiler = DamageProfiler::new(1);
+        profiler.add_record(&dummy_record(b"AATCA", &[Cigar::SoftClip(2), Cigar::Match(3)], false), &chromosome, b"CCA");
+
+        let profile = profiler.misincorporations(LibraryType::DoubleStranded);
+        let first = counts_at(&profile, Orientation::FivePrime, Strand::Forward, 1);
+        assert_eq!((first.c_counts, first.c_to_t), (1, 1));
+    }
+
+    #[test]
+    fn ignore_filtered_records() {
+        let chromosome = ChrName::new("chr1");
+        let mut profiler = DamageProfiler::new(1);
+        let mut record = dummy_record(b"TCA", &[Cigar::Match(3)], false);
+        record.set_duplicate();
+        profiler.add_record(&record, &chromosome, b"CCA");
+        assert_eq!(profiler.reads(), 0);
+        assert!(profiler.misincorporations(LibraryType::DoubleStranded).is_empty());
+    }
+
+    #[test]
+    fn write_roundtrip() {
+        let chromosome = ChrName::new("chr1");
+        let mut profiler = DamageProfiler::new(3);
+        profiler.add_record(&dummy_record(b"TCAGA", &[Cigar::Match(5)], false), &chromosome, b"CCAGG");
+        profiler.add_record(&dummy_record(b"ACAGA", &[Cigar::Match(5)], true), &chromosome, b"GCAGG");
+
+        let mut buffer = Vec::new();
+        profiler.write(&mut buffer, &["table produced by pmd-mask".to_string()]).unwrap();
+        let text = String::from_utf8(buffer.clone()).unwrap();
+        assert!(text.starts_with("# table produced by pmd-mask\nChr\tEnd\tStd\tPos\tA\tC\tG\tT\tTotal\tG>A\tC>T"));
+        assert!(text.lines().skip(2).all(|line| line.split('\t').count() == HEADER.len()));
+
+        let parsed = Misincorporations::profile_from_reader(buffer.as_slice(), LibraryType::DoubleStranded).unwrap();
+        assert_eq!(*parsed, *profiler.misincorporations(LibraryType::DoubleStranded));
+    }
+}
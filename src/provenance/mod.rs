@@ -0,0 +1,145 @@
+use std::collections::HashSet;
+
+use rust_htslib::bam::{self, header::HeaderRecord};
+
+use crate::mask::Masks;
+
+/// Program name (`PN`) and base identifier (`ID`) of the `@PG` header records appended by `pmd-mask`.
+pub const PROGRAM_NAME: &str = env!("CARGO_PKG_NAME");
+
+/// Program version (`VN`) of the `@PG` header records appended by `pmd-mask`.
+pub const PROGRAM_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Append a `@PG` header record to the provided `header`, documenting the `command_line` used to run `pmd-mask`.
+/// 
+/// - `ID` is set to [`PROGRAM_NAME`], and suffixed with `.1`, `.2`, etc. if this identifier is already found within the
+///   header (following samtools' conventions).
+/// - `PP` chains the record to the last program of the header, i.e. the last `@PG` record which is not referenced
+///   by the `PP` field of any other record. `PP` is omitted if the header does not contain any `@PG` record.
+/// 
+/// Returns the `ID` of the appended record.
+/// 
+/// # Usage
+/// ```
+/// use rust_htslib::bam::{self, header::HeaderRecord};
+/// use pmd_mask::provenance::push_program;
+/// 
+/// let mut header = bam::Header::new();
+/// header.push_record(HeaderRecord::new(b"PG").push_tag(b"ID", "bwa").push_tag(b"PN", "bwa"));
+/// 
+/// assert_eq!(push_program(&mut header, "pmd-mask -f ref.fa"), "pmd-mask");
+/// assert_eq!(push_program(&mut header, "pmd-mask -f ref.fa"), "pmd-mask.1");
+/// 
+/// let programs = header.to_hashmap().remove("PG").expect("Missing @PG records");
+/// assert_eq!(programs[1]["PP"], "bwa");
+/// assert_eq!(programs[2]["PP"], "pmd-mask");
+/// ```
+pub fn push_program(header: &mut bam::Header, command_line: &str) -> String {
+    let programs = header.to_hashmap().remove("PG").unwrap_or_default();
+
+    // ---- Find a unique identifier.
+    let ids = programs.iter().filter_map(|record| record.get("ID")).collect::<HashSet<_>>();
+    let mut id = PROGRAM_NAME.to_string();
+    let mut suffix = 0;
+    while ids.contains(&id) {
+        suffix += 1;
+        id = format!("{PROGRAM_NAME}.{suffix}");
+    }
+
+    // ---- Chain to the last program which was not followed by another one.
+    let parents = programs.iter().filter_map(|record| record.get("PP")).collect::<HashSet<_>>();
+    let previous = programs.iter().rev()
+        .filter_map(|record| record.get("ID"))
+        .find(|id| !parents.contains(id))
+        .or_else(|| programs.last().and_then(|record| record.get("ID")))
+        .cloned();
+
+    let mut record = HeaderRecord::new(b"PG");
+    record.push_tag(b"ID", &id).push_tag(b"PN", PROGRAM_NAME);
+    if let Some(ref previous) = previous {
+        record.push_tag(b"PP", previous);
+    }
+    record.push_tag(b"VN", PROGRAM_VERSION).push_tag(b"CL", command_line);
+    header.push_record(&record);
+    id
+}
+
+/// Append the masking thresholds of `masks` to the provided `header`, as `@CO` lines. Each line carries the same 
+/// content as [`Masks::write()`], prefixed with [`PROGRAM_NAME`] (i.e. `@CO\tpmd-mask: <Chr>\t<Std>\t<5p>\t<3p>`)
+/// 
+/// # Usage
+/// ```
+/// use rust_htslib::bam;
+/// use pmd_mask::{Masks, genome::LibraryType};
+/// use pmd_mask::provenance::push_thresholds;
+/// 
+/// let masks = Masks::from_path("tests/test-data/bam/dummy-MTonly/misincorporation.txt", 0.01, LibraryType::DoubleStranded, Default::default()).unwrap();
+/// let mut header = bam::Header::new();
+/// push_thresholds(&mut header, &masks);
+/// 
+/// let comments = header.comments().collect::<Vec<_>>();
+/// assert_eq!(comments[0], "pmd-mask: Chr\tStd\t5p\t3p");
+/// assert!(comments.iter().any(|comment| comment.starts_with("pmd-mask: MT\t+\t")));
+/// ```
+pub fn push_thresholds(header: &mut bam::Header, masks: &Masks) {
+    let mut thresholds = Vec::new();
+    masks.write(&mut thresholds).expect("Writing within a Vec<u8> is infallible");
+    for line in String::from_utf8_lossy(&thresholds).lines() {
+        header.push_comment(format!("{PROGRAM_NAME}: {line}").as_bytes());
+    }
+}
+
+/// Format a command line as a single string, suitable for the `CL` field of a `@PG` header record. Arguments 
+/// containing whitespace are quoted.
+/// 
+/// # Usage
+/// ```
+/// use pmd_mask::provenance::command_line;
+/// assert_eq!(command_line(["pmd-mask", "-b", "my file.bam"]), "pmd-mask -b 'my file.bam'");
+/// ```
+pub fn command_line<I, S>(args: I) -> String
+where   I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+{
+    args.into_iter()
+        .map(|arg| match arg.as_ref() {
+            arg if arg.contains(char::is_whitespace) => format!("'{arg}'"),
+            arg                                      => arg.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn programs(header: &bam::Header) -> Vec<(String, Option<String>)> {
+        header.to_hashmap().remove("PG").unwrap_or_default().into_iter()
+            .map(|record| (record["ID"].clone(), record.get("PP").cloned()))
+            .collect()
+    }
+
+    #[test]
+    fn push_program_empty_header() {
+        let mut header = bam::Header::new();
+        assert_eq!(push_program(&mut header, "pmd-mask"), "pmd-mask");
+        assert_eq!(programs(&header), vec![("pmd-mask".to_string(), None)]);
+
+        let record = &header.to_hashmap()["PG"][0];
+        assert_eq!(record["VN"], PROGRAM_VERSION);
+        assert_eq!(record["CL"], "pmd-mask");
+    }
+
+    #[test]
+    fn push_program_chains_to_leaf() {
+        // ---- samtools may append its @PG records out of order: bwa -> samtools, followed by an orphan 'picard' record.
+        let mut header = bam::Header::new();
+        header.push_record(HeaderRecord::new(b"PG").push_tag(b"ID", "bwa"));
+        header.push_record(HeaderRecord::new(b"PG").push_tag(b"ID", "picard"));
+        header.push_record(HeaderRecord::new(b"PG").push_tag(b"ID", "samtools").push_tag(b"PP", "bwa"));
+
+        push_program(&mut header, "pmd-mask");
+        assert_eq!(programs(&header).last(), Some(&("pmd-mask".to_string(), Some("samtools".to_string()))));
+    }
+}
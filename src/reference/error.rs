@@ -0,0 +1,11 @@
+use thiserror::Error;
+
+/// Error type associated with [`crate::reference::ReferenceCache`]
+#[derive(Debug, Error)]
+pub enum ReferenceError {
+    #[error("Failed to fetch contig '{contig}' from the reference genome [{source}]")]
+    FetchContig{contig: String, #[source] source: rust_htslib::errors::Error},
+
+    #[error("The reference cache must be able to hold at least one contig")]
+    NullCapacity,
+}
@@ -0,0 +1,222 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use rust_htslib::{bam, faidx};
+use log::debug;
+
+use crate::genome::ChrName;
+
+mod error;
+pub use error::ReferenceError;
+
+/// A bounded, Least-Recently-Used cache of reference contigs, built upon a [`faidx::Reader`].
+/// 
+/// Each contig is fetched once, in its entirety, and kept within an owned, shared buffer. Reads may then simply slice 
+/// their reference sequence from this buffer (see [`ReferenceCache::fetch()`]), instead of querying the underlying faidx
+/// index for every record. Cached contigs may also be shared across threads (see [`ReferenceCache::fetch_contig()`]).
+/// 
+/// - For coordinate-sorted input, a capacity of a single contig is sufficient, since records are grouped by contig.
+/// - For unsorted input, a larger capacity may spare us from repeatedly reloading the same contigs, at the cost of 
+///   a higher memory footprint. See [`ReferenceCache::for_header()`].
+/// 
+/// # Usage
+/// ```
+/// use std::error::Error;
+/// use rust_htslib::faidx;
+/// use pmd_mask::reference::ReferenceCache;
+/// use pmd_mask::genome::ChrName;
+/// fn main() -> Result<(), Box<dyn Error>> {
+///     let reader    = faidx::Reader::from_path("tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz")?;
+///     let mut cache = ReferenceCache::new(reader, 1)?;
+/// 
+///     let refseq = cache.fetch(0, &ChrName::new("MT"), 0, 5)?;
+///     assert_eq!(refseq, b"GATCA");
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ReferenceCache {
+    reader  : faidx::Reader,
+    capacity: usize,
+    contigs : VecDeque<(usize, Arc<[u8]>)>,
+}
+
+impl ReferenceCache {
+    /// Instantiate a new [`ReferenceCache`], holding up to `capacity` contigs in memory.
+    /// 
+    /// # Errors
+    /// Returns a [`ReferenceError::NullCapacity`] if `capacity` is zero.
+    pub fn new(reader: faidx::Reader, capacity: usize) -> Result<Self, ReferenceError> {
+        if capacity == 0 {
+            return Err(ReferenceError::NullCapacity)
+        }
+        Ok(Self{reader, capacity, contigs: VecDeque::with_capacity(capacity)})
+    }
+
+    /// Instantiate a new [`ReferenceCache`], whose capacity is chosen according to the sort order of the alignment file:
+    /// a single contig is kept in memory if the header's `@HD` line specifies `SO:coordinate`. Otherwise, up to 
+    /// `max_contigs` are kept.
+    /// 
+    /// # Errors
+    /// Returns a [`ReferenceError::NullCapacity`] if `max_contigs` is zero.
+    pub fn for_header(reader: faidx::Reader, header: &bam::Header, max_contigs: usize) -> Result<Self, ReferenceError> {
+        let capacity = match is_coordinate_sorted(header) {
+            true  => { debug!("Input is coordinate-sorted. Caching a single reference contig at a time."); 1 },
+            false => { debug!("Input is not coordinate-sorted. Caching up to {max_contigs} reference contigs."); max_contigs },
+        };
+        Self::new(reader, capacity)
+    }
+
+    /// Retrieve the reference sequence of contig `chromosome` (with target id `tid`), between the 0-based `start` 
+    /// (inclusive) and `end` (exclusive) coordinates. The contig is loaded into memory if it is not already cached,
+    /// possibly evicting the least recently used contig.
+    /// 
+    /// Coordinates are clamped to the length of the contig, and thus may return a truncated sequence.
+    /// 
+    /// # Errors
+    /// Returns a [`ReferenceError::FetchContig`] if the contig could not be retrieved from the reference genome.
+    pub fn fetch(&mut self, tid: usize, chromosome: &ChrName, start: usize, end: usize) -> Result<&[u8], ReferenceError> {
+        self.load_contig(tid, chromosome)?;
+        Ok(slice_contig(&self.contigs[0].1, start, end))
+    }
+
+    /// Retrieve a shared handle to the full sequence of contig `chromosome` (with target id `tid`). The contig is loaded
+    /// into memory if it is not already cached, possibly evicting the least recently used contig. 
+    /// 
+    /// Evicted contigs remain valid for as long as a handle to them exists.
+    /// 
+    /// # Errors
+    /// Returns a [`ReferenceError::FetchContig`] if the contig could not be retrieved from the reference genome.
+    pub fn fetch_contig(&mut self, tid: usize, chromosome: &ChrName) -> Result<Arc<[u8]>, ReferenceError> {
+        self.load_contig(tid, chromosome)?;
+        Ok(Arc::clone(&self.contigs[0].1))
+    }
+
+    /// Ensure contig `tid` is cached, and move it at the front of the cache.
+    fn load_contig(&mut self, tid: usize, chromosome: &ChrName) -> Result<(), ReferenceError> {
+        match self.contigs.iter().position(|(cached, _)| *cached == tid) {
+            Some(0) => {},
+            Some(i) => {
+                let contig = self.contigs.remove(i).expect("Missing cached contig");
+                self.contigs.push_front(contig);
+            },
+            None => {
+                if self.contigs.len() == self.capacity {
+                    self.contigs.pop_back();
+                }
+                let sequence = self.load(chromosome)?;
+                self.contigs.push_front((tid, sequence.into()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Load the full sequence of a contig into an owned buffer.
+    fn load(&self, chromosome: &ChrName) -> Result<Vec<u8>, ReferenceError> {
+        debug!("Loading reference contig {chromosome} into memory");
+        // htslib clamps the end coordinate to the length of the contig.
+        let raw = self.reader.fetch_seq(chromosome.inner(), 0, i64::MAX as usize)
+            .map_err(|source| ReferenceError::FetchContig{contig: chromosome.to_string(), source})?;
+        let sequence = raw.to_vec();
+
+        // SAFETY: rust-htslib never frees the buffer returned by htslib's faidx_fetch_seq64(). It was allocated by htslib
+        //         using malloc, and is no longer referenced once copied.
+        unsafe { libc::free(raw.as_ptr() as *mut std::ffi::c_void) }
+        Ok(sequence)
+    }
+}
+
+/// Slice a contig between the 0-based `start` (inclusive) and `end` (exclusive) coordinates. Coordinates are clamped to
+/// the length of the contig, and thus may return a truncated sequence.
+/// 
+/// # Usage
+/// ```
+/// use pmd_mask::reference::slice_contig;
+/// assert_eq!(slice_contig(b"GATCACAGG", 2, 5), b"TCA");
+/// assert_eq!(slice_contig(b"GATCACAGG", 7, 12), b"GG");
+/// ```
+pub fn slice_contig(contig: &[u8], start: usize, end: usize) -> &[u8] {
+    let end = end.min(contig.len());
+    &contig[start.min(end)..end]
+}
+
+/// Check whether the `@HD` line of a [`bam::Header`] specifies a coordinate sort order (`SO:coordinate`)
+fn is_coordinate_sorted(header: &bam::Header) -> bool {
+    header.to_hashmap()
+        .get("HD")
+        .and_then(|records| records.first())
+        .and_then(|record| record.get("SO"))
+        .is_some_and(|order| order == "coordinate")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_htslib::bam::header::HeaderRecord;
+
+    const REFERENCE: &str = "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz";
+
+    fn header(sort_order: &str) -> bam::Header {
+        let mut header = bam::Header::new();
+        let mut record = HeaderRecord::new(b"HD");
+        record.push_tag(b"VN", "1.6");
+        record.push_tag(b"SO", sort_order);
+        header.push_record(&record);
+        header
+    }
+
+    #[test]
+    fn coordinate_sorted() {
+        assert!(is_coordinate_sorted(&header("coordinate")));
+        assert!(!is_coordinate_sorted(&header("queryname")));
+        assert!(!is_coordinate_sorted(&bam::Header::new()));
+    }
+
+    #[test]
+    fn capacity_from_header() {
+        let reader = || faidx::Reader::from_path(REFERENCE).expect("Failed to open reference");
+        assert_eq!(ReferenceCache::for_header(reader(), &header("coordinate"), 4).expect("Invalid cache").capacity, 1);
+        assert_eq!(ReferenceCache::for_header(reader(), &header("unsorted"), 4).expect("Invalid cache").capacity, 4);
+        assert!(ReferenceCache::new(reader(), 0).is_err());
+    }
+
+    #[test]
+    fn fetch_matches_faidx() {
+        let reader    = faidx::Reader::from_path(REFERENCE).expect("Failed to open reference");
+        let want      = reader.fetch_seq_string("MT", 100, 149).expect("Failed to fetch sequence");
+        let mut cache = ReferenceCache::new(reader, 1).expect("Invalid cache");
+
+        assert_eq!(cache.fetch(0, &ChrName::new("MT"), 100, 150).expect("Failed to fetch sequence"), want.as_bytes());
+        assert_eq!(cache.contigs.len(), 1);
+
+        // ---- Out of bounds coordinates are clamped.
+        let contig_len = cache.contigs[0].1.len();
+        assert_eq!(cache.fetch(0, &ChrName::new("MT"), contig_len - 2, contig_len + 10).expect("Failed to fetch").len(), 2);
+        assert!(cache.fetch(0, &ChrName::new("MT"), contig_len + 5, contig_len + 10).expect("Failed to fetch").is_empty());
+    }
+
+    #[test]
+    fn least_recently_used_eviction() {
+        let reader    = faidx::Reader::from_path(REFERENCE).expect("Failed to open reference");
+        let mut cache = ReferenceCache::new(reader, 2).expect("Invalid cache");
+        let mt        = ChrName::new("MT");
+
+        // ---- Register the same contig under different target ids.
+        for tid in [0, 1, 0, 2] {
+            cache.fetch(tid, &mt, 0, 10).expect("Failed to fetch sequence");
+        }
+        assert_eq!(cache.contigs.iter().map(|(tid, _)| *tid).collect::<Vec<_>>(), vec![2, 0]);
+    }
+
+    #[test]
+    fn evicted_contigs_remain_valid() {
+        let reader    = faidx::Reader::from_path(REFERENCE).expect("Failed to open reference");
+        let mut cache = ReferenceCache::new(reader, 1).expect("Invalid cache");
+        let mt        = ChrName::new("MT");
+
+        let contig = cache.fetch_contig(0, &mt).expect("Failed to fetch contig");
+        cache.fetch_contig(1, &mt).expect("Failed to fetch contig");
+        assert_eq!(cache.contigs.len(), 1);
+        assert_eq!(slice_contig(&contig, 0, 5), b"GATCA");
+    }
+}
@@ -0,0 +1,20 @@
+use thiserror::Error;
+
+/// Error type associated with [`crate::region::Region`] and [`crate::region::Regions`]
+#[derive(Debug, Error)]
+pub enum RegionError {
+    #[error("Failed to parse '{0}' into a valid region. Expected format: 'chr', 'chr:start', 'chr:start-' or 'chr:start-end' (1-based, inclusive)")]
+    ParseRegion(String),
+
+    #[error("Failed to open regions file [{0}]")]
+    OpenBed(#[source] std::io::Error),
+
+    #[error("Failed to read line {line} of regions file [{source}]")]
+    ReadBed{line: usize, #[source] source: std::io::Error},
+
+    #[error("Failed to parse line {line} of regions file. Expected at least three fields: 'chrom start end' (0-based, half-open). Got '{content}'")]
+    ParseBed{line: usize, content: String},
+
+    #[error("Contig '{0}' was not found within the header of the input alignment file")]
+    UnknownContig(String),
+}
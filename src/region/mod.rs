@@ -0,0 +1,309 @@
+use std::{fmt::{self, Display, Formatter}, fs::File, io::{BufRead, BufReader}, path::Path, str::FromStr};
+
+use rust_htslib::bam::HeaderView;
+
+use crate::genome::ChrName;
+
+mod error;
+pub use error::RegionError;
+
+/// A genomic interval, restricting masking to the records overlapping it. 
+/// 
+/// Coordinates are 0-based and half-open. An unspecified `end` spans the interval up to the end of the contig.
+/// 
+/// [`Region`]s may be parsed from samtools-style strings (`chr`, `chr:start`, `chr:start-` or `chr:start-end`), whose
+/// coordinates are 1-based and inclusive. Thousands separators (`,`) are ignored. Since contig names may themselves 
+/// contain `:` (e.g. `HLA-A*01:01:01:01`), prefer [`Region::from_header()`] whenever a header is available.
+/// 
+/// # Usage
+/// ```
+/// use pmd_mask::region::Region;
+/// use pmd_mask::genome::ChrName;
+/// 
+/// let region: Region = "MT:1,001-2000".parse().expect("Invalid region");
+/// assert_eq!(region, Region{chromosome: ChrName::new("MT"), start: 1000, end: Some(2000)});
+/// 
+/// let region: Region = "MT".parse().expect("Invalid region");
+/// assert_eq!(region, Region{chromosome: ChrName::new("MT"), start: 0, end: None});
+/// 
+/// let region: Region = "MT:101-".parse().expect("Invalid region");
+/// assert_eq!(region, Region{chromosome: ChrName::new("MT"), start: 100, end: None});
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub chromosome: ChrName,
+    pub start     : u64,
+    pub end       : Option<u64>,
+}
+
+impl FromStr for Region {
+    type Err = RegionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || RegionError::ParseRegion(s.to_string());
+        let parse_coordinate = |c: &str| c.replace(',', "").parse::<u64>().map_err(|_| err());
+
+        let region = match s.rsplit_once(':') {
+            None => Self{chromosome: ChrName::new(s), start: 0, end: None},
+            Some((chromosome, coordinates)) => {
+                let (start, end) = match coordinates.split_once('-') {
+                    Some((start, ""))  => (parse_coordinate(start)?, None),
+                    Some((start, end)) => (parse_coordinate(start)?, Some(parse_coordinate(end)?)),
+                    None               => (parse_coordinate(coordinates)?, None),
+                };
+                Self{chromosome: ChrName::new(chromosome), start: start.checked_sub(1).ok_or_else(err)?, end}
+            }
+        };
+
+        match (region.chromosome.inner().is_empty(), region.end) {
+            (true, _)                           => Err(err()),
+            (_, Some(end)) if end < region.start => Err(err()),
+            _                                    => Ok(region),
+        }
+    }
+}
+
+impl Region {
+    /// Parse a samtools-style region string, using the target names of a [`HeaderView`] to disambiguate contig names
+    /// containing `:`. As with htslib, a string matching the name of a contig is considered as the whole contig. 
+    /// Otherwise, the string is parsed as `chr:start-end` (see [`Region::from_str()`]).
+    /// 
+    /// # Usage
+    /// ```
+    /// use rust_htslib::bam::{HeaderView, header::{Header, HeaderRecord}};
+    /// use pmd_mask::region::Region;
+    /// use pmd_mask::genome::ChrName;
+    /// 
+    /// let mut header = Header::new();
+    /// header.push_record(HeaderRecord::new(b"SQ").push_tag(b"SN", "HLA-A*01:01:01:01").push_tag(b"LN", 3503));
+    /// let header_view = HeaderView::from_header(&header);
+    /// 
+    /// let region = Region::from_header("HLA-A*01:01:01:01", &header_view).expect("Invalid region");
+    /// assert_eq!(region, Region{chromosome: ChrName::new("HLA-A*01:01:01:01"), start: 0, end: None});
+    /// 
+    /// let region = Region::from_header("HLA-A*01:01:01:01:101-200", &header_view).expect("Invalid region");
+    /// assert_eq!(region, Region{chromosome: ChrName::new("HLA-A*01:01:01:01"), start: 100, end: Some(200)});
+    /// ```
+    /// 
+    /// # Errors
+    /// Returns a [`RegionError::ParseRegion`] if `s` is neither a contig name, nor a valid region string.
+    pub fn from_header(s: &str, header_view: &HeaderView) -> Result<Self, RegionError> {
+        match header_view.tid(s.as_bytes()) {
+            Some(_) => Ok(Self{chromosome: ChrName::new(s), start: 0, end: None}),
+            None    => s.parse(),
+        }
+    }
+}
+
+impl Display for Region {
+    /// Return a samtools-style, 1-based representation of a [`Region`]
+    /// ```
+    /// use pmd_mask::region::Region;
+    /// use pmd_mask::genome::ChrName;
+    /// let region = Region{chromosome: ChrName::new("MT"), start: 99, end: Some(200)};
+    /// assert_eq!(format!("{region}"), "MT:100-200");
+    /// ```
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let out = match self.end {
+            Some(end) => format!("{}:{}-{}", self.chromosome, self.start + 1, end),
+            None      => format!("{}:{}", self.chromosome, self.start + 1),
+        };
+        out.fmt(f)
+    }
+}
+
+/// A [`Region`], resolved against the target ids of an alignment file's [`HeaderView`]. Coordinates are 0-based and
+/// half-open, and directly usable with [`rust_htslib::bam::IndexedReader::fetch()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ResolvedRegion {
+    pub tid  : u32,
+    pub start: i64,
+    pub end  : i64,
+}
+
+/// A collection of [`Region`]s, gathered from the command line and/or a BED file.
+/// 
+/// Region strings of the command line are only parsed once resolved against a header (see [`Regions::push_str()`]).
+/// 
+/// # Usage
+/// ```
+/// use rust_htslib::bam::{self, Read};
+/// use pmd_mask::region::{Regions, ResolvedRegion};
+/// 
+/// let mut regions = Regions::default();
+/// regions.push("MT:101-200".parse().expect("Invalid region"));
+/// regions.push("MT:151-300".parse().expect("Invalid region"));
+/// 
+/// let bam      = bam::Reader::from_path("tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam").expect("Invalid bam");
+/// let resolved = regions.resolve(bam.header()).expect("Unknown contig");
+/// let tid      = bam.header().tid(b"MT").expect("Missing contig");
+/// assert_eq!(resolved, vec![ResolvedRegion{tid, start: 100, end: 300}]);
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct Regions { inner: Vec<Region>, specs: Vec<String> }
+
+impl Regions {
+    /// Parse a BED file into a collection of [`Region`]s. See [`Regions::from_bed_reader()`]
+    /// 
+    /// # Errors
+    /// - Returns a [`RegionError::OpenBed`] if the file could not be opened.
+    /// - Bubbles out any error arising from [`Regions::from_bed_reader()`].
+    pub fn from_bed(path: impl AsRef<Path>) -> Result<Self, RegionError> {
+        Self::from_bed_reader(BufReader::new(File::open(path).map_err(RegionError::OpenBed)?))
+    }
+
+    /// Parse BED records from a reader into a collection of [`Region`]s. Only the first three fields 
+    /// (`chrom`, `chromStart`, `chromEnd`) are used. Empty lines, comments (`#`) and `track`|`browser` lines are skipped.
+    /// 
+    /// # Errors
+    /// - Returns a [`RegionError::ReadBed`] if any line could not be read.
+    /// - Returns a [`RegionError::ParseBed`] if any line contains less than three fields, or invalid coordinates.
+    pub fn from_bed_reader(reader: impl BufRead) -> Result<Self, RegionError> {
+        let mut regions = Self::default();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line.map_err(|source| RegionError::ReadBed{line: i + 1, source})?;
+            if line.trim().is_empty() || line.starts_with('#') || line.starts_with("track") || line.starts_with("browser") {
+                continue
+            }
+
+            let err = || RegionError::ParseBed{line: i + 1, content: line.clone()};
+            let mut fields = line.split_whitespace();
+            let (Some(chromosome), Some(start), Some(end)) = (fields.next(), fields.next(), fields.next()) else {
+                return Err(err())
+            };
+            let start = start.parse::<u64>().map_err(|_| err())?;
+            let end   = end.parse::<u64>().map_err(|_| err())?;
+            if end < start {
+                return Err(err())
+            }
+            regions.push(Region{chromosome: ChrName::new(chromosome), start, end: Some(end)});
+        }
+        Ok(regions)
+    }
+
+    /// Append a [`Region`] to this collection.
+    pub fn push(&mut self, region: Region) {
+        self.inner.push(region)
+    }
+
+    /// Append a samtools-style region string to this collection. The string is only parsed when resolving this 
+    /// collection against a header, so that contig names containing `:` are recognized (see [`Region::from_header()`]).
+    pub fn push_str(&mut self, spec: &str) {
+        self.specs.push(spec.to_string())
+    }
+
+    /// Extend this collection with the [`Region`]s of another.
+    pub fn extend(&mut self, other: Regions) {
+        self.inner.extend(other.inner);
+        self.specs.extend(other.specs);
+    }
+
+    /// Check whether this collection contains any [`Region`].
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty() && self.specs.is_empty()
+    }
+
+    /// Resolve every [`Region`] against the target ids of a [`HeaderView`]. Resolved regions are sorted according to 
+    /// their target id and start coordinate. Overlapping and book-ended regions are merged.
+    /// 
+    /// # Errors
+    /// - Returns a [`RegionError::ParseRegion`] if any region string is invalid (see [`Region::from_header()`]).
+    /// - Returns a [`RegionError::UnknownContig`] if any region targets a contig which is absent from the header.
+    pub fn resolve(&self, header_view: &HeaderView) -> Result<Vec<ResolvedRegion>, RegionError> {
+        let parsed = self.specs.iter().map(|spec| Region::from_header(spec, header_view)).collect::<Result<Vec<_>, _>>()?;
+        let mut resolved = Vec::with_capacity(self.inner.len() + parsed.len());
+        for region in self.inner.iter().chain(parsed.iter()) {
+            let tid = header_view.tid(region.chromosome.inner().as_bytes())
+                .ok_or_else(|| RegionError::UnknownContig(region.chromosome.to_string()))?;
+            let contig_len = header_view.target_len(tid).unwrap_or(i64::MAX as u64);
+            let end        = region.end.unwrap_or(contig_len).min(contig_len);
+            resolved.push(ResolvedRegion{tid, start: region.start as i64, end: end as i64});
+        }
+        resolved.sort();
+
+        let mut merged: Vec<ResolvedRegion> = Vec::with_capacity(resolved.len());
+        for region in resolved {
+            match merged.last_mut() {
+                Some(last) if last.tid == region.tid && region.start <= last.end => last.end = last.end.max(region.end),
+                _ => merged.push(region),
+            }
+        }
+        Ok(merged)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_htslib::bam;
+    use crate::genome::Strand;
+    use crate::mask::dummy::dummy_bam;
+
+    #[test]
+    fn parse_region() {
+        let region = |s: &str| s.parse::<Region>();
+        assert_eq!(region("chr1:100").expect("Invalid region"), Region{chromosome: ChrName::new("chr1"), start: 99, end: None});
+        assert_eq!(region("chr1:100-100").expect("Invalid region"), Region{chromosome: ChrName::new("chr1"), start: 99, end: Some(100)});
+        assert_eq!(region("chr1:100-").expect("Invalid region"), Region{chromosome: ChrName::new("chr1"), start: 99, end: None});
+        for invalid in ["", ":100-200", "chr1:0-10", "chr1:200-100", "chr1:a-b", "chr1:-100"] {
+            assert!(region(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn parse_bed() {
+        let bed = "track name=test\n# comment\nchr1\t0\t100\tname\n\nchr2 50 60\n";
+        let regions = Regions::from_bed_reader(bed.as_bytes()).expect("Invalid bed");
+        assert_eq!(regions.inner, vec![
+            Region{chromosome: ChrName::new("chr1"), start: 0, end: Some(100)},
+            Region{chromosome: ChrName::new("chr2"), start: 50, end: Some(60)},
+        ]);
+
+        for (invalid, line) in [("chr1\t0\n", 1), ("chr1\t0\t100\nchr1\t100\t0\n", 2), ("chr1\tfoo\t100\n", 1)] {
+            match Regions::from_bed_reader(invalid.as_bytes()) {
+                Err(RegionError::ParseBed{line: got, ..}) => assert_eq!(got, line),
+                other => panic!("Expected a ParseBed error. Got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn resolve_and_merge() {
+        let (header, _) = dummy_bam(Strand::Forward, 0);
+        let header_view = HeaderView::from_header(&header);
+
+        let mut regions = Regions::default();
+        for region in ["chr1:501-600", "chr1:1-100", "chr1:51-200", "chr1:201-300", "chr1:249250000"] {
+            regions.push(region.parse().expect("Invalid region"));
+        }
+        assert_eq!(regions.resolve(&header_view).expect("Failed to resolve regions"), vec![
+            ResolvedRegion{tid: 0, start: 0,         end: 300},
+            ResolvedRegion{tid: 0, start: 500,       end: 600},
+            ResolvedRegion{tid: 0, start: 249249999, end: 249250621},
+        ]);
+
+        regions.push("chr2".parse().expect("Invalid region"));
+        assert!(matches!(regions.resolve(&header_view), Err(RegionError::UnknownContig(contig)) if contig == "chr2"));
+    }
+
+    #[test]
+    fn resolve_contig_names_with_colons() {
+        let mut header = bam::header::Header::new();
+        for name in ["HLA-A*01:01:01:01", "HLA-A*01:01:01"] {
+            header.push_record(bam::header::HeaderRecord::new(b"SQ").push_tag(b"SN", name).push_tag(b"LN", 3503));
+        }
+        let header_view = HeaderView::from_header(&header);
+
+        // ---- Whole contig names take precedence over 'chr:start' parsing.
+        let mut regions = Regions::default();
+        regions.push_str("HLA-A*01:01:01:01");
+        regions.push_str("HLA-A*01:01:01:101-200");
+        assert_eq!(regions.resolve(&header_view).expect("Failed to resolve regions"), vec![
+            ResolvedRegion{tid: 0, start: 0,   end: 3503},
+            ResolvedRegion{tid: 1, start: 100, end: 200},
+        ]);
+
+        regions.push_str("HLA-A*01:01:01:01:a-b");
+        assert!(matches!(regions.resolve(&header_view), Err(RegionError::ParseRegion(_))));
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use rust_htslib::bam::HeaderView;
+use serde::Serialize;
+
+use crate::genome::{LibraryType, Orientation};
+use crate::mask::{Masks, MaskThreshold, MasksError, Fallback, ThresholdDetails};
+use crate::metrics::{MaskStats, TargetStats};
+
+/// Number of masked reads and bases of a set of records.
+/// 
+/// - `candidates_*` is the number of masking candidates found at either end (see [`EndCounts`](crate::metrics::EndCounts)).
+/// - `mismatches_*` is the number of these candidates where the read carries the deamination product.
+/// - `masked_bases_*` is the number of bases actually hard- or soft-masked, or soft-clipped at either end. Candidates
+///   which are left untouched (e.g. with `--mismatches-only`, `--mask-mode rescale` or `--mask-mode annotate`) are
+///   not counted.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct MaskedCounts {
+    pub reads          : u64,
+    pub masked_reads   : u64,
+    pub candidates_5p  : u64,
+    pub candidates_3p  : u64,
+    pub mismatches_5p  : u64,
+    pub mismatches_3p  : u64,
+    pub masked_bases_5p: u64,
+    pub masked_bases_3p: u64,
+}
+
+impl From<&TargetStats> for MaskedCounts {
+    fn from(stats: &TargetStats) -> Self {
+        Self {
+            reads          : stats.reads,
+            masked_reads   : stats.masked_reads,
+            candidates_5p  : stats.five_prime.candidates,
+            candidates_3p  : stats.three_prime.candidates,
+            mismatches_5p  : stats.five_prime.mismatches,
+            mismatches_3p  : stats.three_prime.mismatches,
+            masked_bases_5p: stats.five_prime.masked,
+            masked_bases_3p: stats.three_prime.masked,
+        }
+    }
+}
+
+impl MaskedCounts {
+    /// Add the counts of `other` to these.
+    fn add(&mut self, other: &MaskedCounts) {
+        self.reads           += other.reads;
+        self.masked_reads    += other.masked_reads;
+        self.candidates_5p   += other.candidates_5p;
+        self.candidates_3p   += other.candidates_3p;
+        self.mismatches_5p   += other.mismatches_5p;
+        self.mismatches_3p   += other.mismatches_3p;
+        self.masked_bases_5p += other.masked_bases_5p;
+        self.masked_bases_3p += other.masked_bases_3p;
+    }
+}
+
+impl From<&MaskStats> for MaskedCounts {
+    fn from(stats: &MaskStats) -> Self {
+        Self::from(&TargetStats{reads: stats.reads, masked_reads: stats.masked_reads, five_prime: stats.five_prime, three_prime: stats.three_prime})
+    }
+}
+
+/// Report of a single chromosome and strand.
+/// 
+/// - `threshold_*`: first position where masking does *not* apply. [`None`] when masking applies along the full read.
+/// - `frequency_*`, `misincorporations_*` and `observations_*`: misincorporation frequency, number of `C>T` (or 
+///   `G>A`) substitutions, and number of reference `C` (or `G`) found at the selected threshold position.
+/// - `fallback`: reason why this entry does not use thresholds computed from its own profile (see [`Fallback`]).
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct EntryReport {
+    pub chromosome          : String,
+    pub strand              : char,
+    pub threshold_5p        : Option<usize>,
+    pub threshold_3p        : Option<usize>,
+    pub frequency_5p        : Option<f32>,
+    pub frequency_3p        : Option<f32>,
+    pub misincorporations_5p: Option<usize>,
+    pub misincorporations_3p: Option<usize>,
+    pub observations_5p     : Option<usize>,
+    pub observations_3p     : Option<usize>,
+    pub fallback            : Option<Fallback>,
+    #[serde(flatten)]
+    pub counts              : MaskedCounts,
+}
+
+impl EntryReport {
+    /// Fill the threshold position, frequency and counts of a given end.
+    fn set_end(&mut self, end: Orientation, threshold: Option<&MaskThreshold>, details: Option<&ThresholdDetails>, library: &LibraryType) {
+        let position = threshold.and_then(|threshold| threshold.get_threshold(&end))
+            .map(|position| position.inner())
+            .filter(|position| *position != usize::MAX);
+        let record   = details.and_then(|details| details.get(&end)).filter(|_| position.is_some());
+        let (frequency, counts) = (record.map(|record| record.target_freq(library)), record.map(|record| record.target_counts(library)));
+        let (threshold, frequency_field, misincorporations, observations) = match end {
+            Orientation::FivePrime  => (&mut self.threshold_5p, &mut self.frequency_5p, &mut self.misincorporations_5p, &mut self.observations_5p),
+            Orientation::ThreePrime => (&mut self.threshold_3p, &mut self.frequency_3p, &mut self.misincorporations_3p, &mut self.observations_3p),
+        };
+        *threshold         = position;
+        *frequency_field   = frequency;
+        *misincorporations = counts.map(|(misincorporations, _)| misincorporations);
+        *observations      = counts.map(|(_, observations)| observations);
+    }
+}
+
+/// Overall statistics of a masking run, along with the number of masked reads and bases of every chromosome.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct RunSummary {
+    #[serde(flatten)]
+    pub counts     : MaskedCounts,
+    pub chromosomes: BTreeMap<String, MaskedCounts>,
+}
+
+/// Plot configuration of a MultiQC custom content table.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct TableConfig {
+    id   : &'static str,
+    title: &'static str,
+}
+
+/// A structured report of a masking run, serialized as JSON, and formatted as a 
+/// [MultiQC custom content](https://multiqc.info/docs/custom_content/) table: `data` contains one row for each 
+/// chromosome and strand of the alignment file's header, keyed by their [`MaskEntry`](crate::mask::MaskEntry) 
+/// representation (e.g. `'MT +'`). Overall statistics are found within `summary`. 
+/// 
+/// Note that MultiQC only picks up files whose name ends with `_mqc.json`. Only the default thresholds are reported,
+/// i.e. excluding those of read groups and length bins.
+/// 
+/// # Usage
+/// ```
+/// use std::error::Error;
+/// use rust_htslib::bam::{self, Read};
+/// use pmd_mask::mask::Masks;
+/// use pmd_mask::metrics::MaskStats;
+/// use pmd_mask::genome::LibraryType;
+/// use pmd_mask::report::RunReport;
+/// fn main() -> Result<(), Box<dyn Error>> {
+///     let masks  = Masks::from_path("tests/test-data/bam/dummy-MTonly/misincorporation.txt", 0.01, LibraryType::DoubleStranded, Default::default())?;
+///     let bam    = bam::Reader::from_path("tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam")?;
+///     let report = RunReport::new(&masks, &MaskStats::default(), bam.header(), LibraryType::DoubleStranded)?;
+/// 
+///     let mt = &report.data["MT +"];
+///     assert_eq!((mt.chromosome.as_str(), mt.strand), ("MT", '+'));
+///     assert!(mt.threshold_5p.is_some() && mt.frequency_5p.is_some_and(|freq| freq <= 0.01));
+/// 
+///     let mut json = Vec::new();
+///     report.write(&mut json)?;
+///     assert!(String::from_utf8(json)?.contains("\"plot_type\": \"table\""));
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RunReport {
+    id          : &'static str,
+    section_name: &'static str,
+    description : &'static str,
+    plot_type   : &'static str,
+    pconfig     : TableConfig,
+    pub data    : BTreeMap<String, EntryReport>,
+    pub summary : RunSummary,
+}
+
+impl RunReport {
+    /// Build a [`RunReport`] from the [`Masks`] and [`MaskStats`] of a masking run, by resolving every target of the 
+    /// alignment file's [`HeaderView`] (see [`Masks::resolve()`]). The [`LibraryType`] dictates which frequencies and
+    /// counts are reported (see [`MisincorporationRecord::target_counts()`](crate::misincorporation::MisincorporationRecord::target_counts)).
+    /// 
+    /// # Errors
+    /// Bubbles out any error arising from [`Masks::resolve()`].
+    pub fn new(masks: &Masks, stats: &MaskStats, header_view: &HeaderView, library: LibraryType) -> Result<Self, MasksError> {
+        let table = masks.resolve(header_view)?;
+        let mut data    = BTreeMap::new();
+        let mut summary = RunSummary{counts: MaskedCounts::from(stats), chromosomes: BTreeMap::new()};
+        for (index, resolved) in table.iter().enumerate() {
+            let fallback = match resolved.fallback {
+                Some(policy)                                   => Some(Fallback::Missing(policy)),
+                None if !masks.contains(&resolved.entry)       => Some(Fallback::MergedReference),
+                None                                           => resolved.details.and_then(|details| details.fallback),
+            };
+            let counts = stats.get_target(index / 2, resolved.entry.strand).map(MaskedCounts::from).unwrap_or_default();
+            let mut report = EntryReport{chromosome: resolved.entry.chromosome.inner().to_string(), strand: resolved.entry.strand.into(), fallback, counts, ..Default::default()};
+            for end in [Orientation::FivePrime, Orientation::ThreePrime] {
+                report.set_end(end, resolved.threshold, resolved.details, &library);
+            }
+
+            summary.chromosomes.entry(report.chromosome.clone()).or_default().add(&counts);
+            data.insert(resolved.entry.to_string(), report);
+        }
+
+        Ok(Self {
+            id          : "pmd_mask",
+            section_name: "pmd-mask",
+            description : "Masking thresholds and statistics of each chromosome and strand.",
+            plot_type   : "table",
+            pconfig     : TableConfig{id: "pmd_mask_table", title: "pmd-mask: masking thresholds"},
+            data,
+            summary,
+        })
+    }
+
+    /// Serialize this report within a writer, as pretty-printed JSON.
+    pub fn write(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        serde_json::to_writer_pretty(&mut *writer, self)?;
+        writeln!(writer)?;
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_htslib::bam::header::{Header, HeaderRecord};
+    use crate::genome::Strand;
+    use crate::mask::MissingPolicy;
+    use crate::metrics::EndCounts;
+
+    #[test]
+    fn fallbacks_and_counts() {
+        let mut header = Header::new();
+        for (name, len) in [("MT", "16569"), ("chrUn", "1000")] {
+            header.push_record(HeaderRecord::new(b"SQ").push_tag(b"SN", name).push_tag(b"LN", len));
+        }
+        let header_view = HeaderView::from_header(&header);
+        let library     = LibraryType::DoubleStranded;
+        let mut masks   = Masks::from_path("tests/test-data/bam/dummy-MTonly/misincorporation.txt", 0.01, library, Default::default()).expect("Failed to compute masks");
+        masks.set_missing_policy(MissingPolicy::Skip);
+
+        let mut stats = MaskStats::default();
+        stats.add_record(0, Strand::Reverse, true, &EndCounts{candidates: 3, mismatches: 1, masked: 1}, &EndCounts::default());
+        stats.add_record(0, Strand::Forward, false, &EndCounts::default(), &EndCounts::default());
+
+        let report = RunReport::new(&masks, &stats, &header_view, library).expect("Failed to build report");
+        assert_eq!(report.data.len(), 4);
+
+        // ---- MT is found within the profile, and carries its own statistics.
+        let mt = &report.data["MT -"];
+        assert_eq!((mt.fallback, mt.counts.reads, mt.counts.masked_reads), (None, 1, 1));
+        assert_eq!((mt.counts.candidates_5p, mt.counts.mismatches_5p, mt.counts.masked_bases_5p), (3, 1, 1));
+        assert!(mt.threshold_3p.is_some() && mt.misincorporations_3p.is_some());
+        assert_eq!(report.summary.chromosomes["MT"].reads, 2);
+
+        // ---- chrUn is missing: neither thresholds, nor frequencies are reported.
+        let missing = &report.data["chrUn +"];
+        assert_eq!(missing.fallback, Some(Fallback::Missing(MissingPolicy::Skip)));
+        assert_eq!((missing.threshold_5p, missing.frequency_5p, missing.counts), (None, None, MaskedCounts::default()));
+    }
+}
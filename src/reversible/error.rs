@@ -0,0 +1,17 @@
+use thiserror::Error;
+
+/// Error type enum for [`crate::reversible`]
+#[derive(Debug, Error)]
+pub enum ReversibleError {
+    #[error("Record already carries reversible masking tags. Please restore it with 'pmd-mask unmask' before masking it again")]
+    AlreadyMasked,
+
+    #[error("Invalid or missing '{tag}' aux tag. Cannot restore the original record")]
+    InvalidTag{tag: String},
+
+    #[error("Reversible masking tags do not match the length of the record's sequence. Cannot restore the original record")]
+    LengthMismatch,
+
+    #[error(transparent)]
+    HtsLibError(#[from] rust_htslib::errors::Error),
+}
@@ -0,0 +1,222 @@
+use rust_htslib::bam::{self, ext::BamRecordExtensions, record::{Aux, AuxArray, CigarString}};
+
+mod error;
+pub use error::ReversibleError;
+
+use crate::clip;
+
+/// Aux tag listing the read offsets whose nucleotide or base quality was replaced (`B:I` array, 0-based).
+pub const OFFSETS_TAG: &[u8; 2] = b"ZR";
+
+/// Aux tag carrying the original nucleotides found at each offset of [`OFFSETS_TAG`] (`Z`).
+pub const BASES_TAG: &[u8; 2] = b"ZB";
+
+/// Aux tag carrying the original Phred base qualities found at each offset of [`OFFSETS_TAG`] (`B:C` array, without any
+/// offset).
+pub const QUALS_TAG: &[u8; 2] = b"ZQ";
+
+/// Aux tag carrying the original CIGAR of a record, when it was rewritten (`Z`).
+pub const CIGAR_TAG: &[u8; 2] = b"ZX";
+
+/// Aux tag carrying the original 1-based alignment start of a record, when it was shifted (`i`).
+pub const POS_TAG: &[u8; 2] = b"ZP";
+
+const TAGS: [&[u8; 2]; 5] = [OFFSETS_TAG, BASES_TAG, QUALS_TAG, CIGAR_TAG, POS_TAG];
+
+/// Record every alteration of a `masked` record within aux tags, so that its `original` counterpart may later be 
+/// restored (see [`restore()`]). This is similar in spirit to the `OQ` tag of the SAM specification, except that only
+/// the replaced nucleotides and base qualities are kept:
+/// - offsets where either the nucleotide or base quality differs are listed within [`OFFSETS_TAG`], along with their 
+///   original values within [`BASES_TAG`] and [`QUALS_TAG`].
+/// - the original CIGAR and alignment start are respectively kept within [`CIGAR_TAG`] and [`POS_TAG`], if they differ.
+/// 
+/// No tag is appended if the `masked` record does not differ from its `original` counterpart.
+/// 
+/// # Errors
+/// - Returns a [`ReversibleError::AlreadyMasked`] if the `original` record already carries any of these tags, since 
+///   masking it again would irremediably lose its previous state.
+/// - Returns a [`ReversibleError::HtsLibError`] if htslib fails to append any aux field.
+/// 
+/// # Usage
+/// ```
+/// use rust_htslib::bam;
+/// use pmd_mask::reversible::{store, restore};
+/// 
+/// let mut original = bam::Record::new();
+/// original.set(b"read", None, b"TCGATA", &[37; 6]);
+/// 
+/// let mut masked = original.clone();
+/// masked.set(b"read", None, b"NCGATN", &[0, 37, 37, 37, 37, 0]);
+/// store(&original, &mut masked).expect("Failed to store original record");
+/// 
+/// restore(&mut masked).expect("Failed to restore record");
+/// assert_eq!(masked.seq().as_bytes(), b"TCGATA");
+/// assert_eq!(masked.qual(), &[37; 6]);
+/// ```
+pub fn store(original: &bam::Record, masked: &mut bam::Record) -> Result<(), ReversibleError> {
+    if TAGS.iter().any(|tag| original.aux(*tag).is_ok()) {
+        return Err(ReversibleError::AlreadyMasked)
+    }
+
+    let (original_seq, masked_seq) = (original.seq().as_bytes(), masked.seq().as_bytes());
+    let (original_qual, masked_qual) = (original.qual(), masked.qual());
+
+    let mut offsets = Vec::new();
+    let mut bases   = Vec::new();
+    let mut quals   = Vec::new();
+    for (i, (base, qual)) in original_seq.iter().zip(original_qual.iter()).enumerate() {
+        if masked_seq.get(i) != Some(base) || masked_qual.get(i) != Some(qual) {
+            offsets.push(i as u32);
+            bases.push(*base);
+            quals.push(*qual);
+        }
+    }
+
+    if !offsets.is_empty() {
+        // NOTE: bases are decoded from the sequence, and are thus always valid ASCII.
+        let bases = String::from_utf8_lossy(&bases);
+        masked.push_aux(OFFSETS_TAG, Aux::ArrayU32(AuxArray::from(&offsets)))?;
+        masked.push_aux(BASES_TAG, Aux::String(&bases))?;
+        masked.push_aux(QUALS_TAG, Aux::ArrayU8(AuxArray::from(&quals)))?;
+    }
+
+    if original.cigar().take() != masked.cigar().take() {
+        masked.push_aux(CIGAR_TAG, Aux::String(&original.cigar().to_string()))?;
+    }
+
+    if original.pos() != masked.pos() {
+        masked.push_aux(POS_TAG, Aux::I32(original.pos() as i32 + 1))?;
+    }
+    Ok(())
+}
+
+/// Restore a record which was reversibly masked (see [`store()`]), and remove the corresponding aux tags.
+/// 
+/// Returns `true` if the record was restored, and `false` if it did not carry any reversible masking tag (i.e. was
+/// left untouched during masking).
+/// 
+/// # Errors
+/// - Returns a [`ReversibleError::InvalidTag`] if any tag is missing, or holds an unexpected type or value.
+/// - Returns a [`ReversibleError::LengthMismatch`] if the stored offsets do not match the record's sequence.
+/// - Returns a [`ReversibleError::HtsLibError`] if htslib fails to remove any aux field.
+pub fn restore(record: &mut bam::Record) -> Result<bool, ReversibleError> {
+    if !TAGS.iter().any(|tag| record.aux(*tag).is_ok()) {
+        return Ok(false)
+    }
+    let invalid = |tag: &[u8; 2]| ReversibleError::InvalidTag{tag: String::from_utf8_lossy(tag).to_string()};
+
+    let mut seq  = record.seq().as_bytes();
+    let mut qual = record.qual().to_vec();
+    if record.aux(OFFSETS_TAG).is_ok() {
+        let offsets = match record.aux(OFFSETS_TAG) {
+            Ok(Aux::ArrayU32(offsets)) => offsets.iter().collect::<Vec<u32>>(),
+            _                          => return Err(invalid(OFFSETS_TAG)),
+        };
+        let bases = match record.aux(BASES_TAG) {
+            Ok(Aux::String(bases)) => bases.as_bytes().to_vec(),
+            _                      => return Err(invalid(BASES_TAG)),
+        };
+        let quals = match record.aux(QUALS_TAG) {
+            Ok(Aux::ArrayU8(quals)) => quals.iter().collect::<Vec<u8>>(),
+            _                       => return Err(invalid(QUALS_TAG)),
+        };
+
+        if offsets.len() != bases.len() || offsets.len() != quals.len() {
+            return Err(ReversibleError::LengthMismatch)
+        }
+        for ((offset, base), q) in offsets.into_iter().zip(bases).zip(quals) {
+            let offset = offset as usize;
+            if offset >= seq.len() {
+                return Err(ReversibleError::LengthMismatch)
+            }
+            seq[offset]  = base;
+            qual[offset] = q;
+        }
+    }
+
+    let cigar = match record.aux(CIGAR_TAG) {
+        Ok(Aux::String(cigar)) => CigarString::try_from(cigar).map_err(|_| invalid(CIGAR_TAG))?,
+        Ok(_)                  => return Err(invalid(CIGAR_TAG)),
+        Err(_)                 => record.cigar().take(),
+    };
+
+    let pos = match record.aux(POS_TAG) {
+        Ok(Aux::I32(pos)) if pos > 0 => Some(pos as i64 - 1),
+        Ok(_)                        => return Err(invalid(POS_TAG)),
+        Err(_)                       => None,
+    };
+
+    let qname = record.qname().to_vec();
+    record.set(&qname, Some(&cigar), &seq, &qual);
+    if let Some(pos) = pos {
+        record.set_pos(pos);
+        record.set_bin(clip::reg2bin(pos, record.reference_end()));
+    }
+
+    for tag in TAGS {
+        if record.aux(tag).is_ok() {
+            record.remove_aux(tag)?;
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_htslib::bam::record::Cigar;
+
+    fn dummy_record() -> bam::Record {
+        let mut record = bam::Record::new();
+        record.set(b"read", Some(&CigarString(vec![Cigar::Match(8)])), b"TTCGATAA", &[30, 31, 32, 33, 34, 35, 36, 37]);
+        record.set_pos(100);
+        record.push_aux(b"NM", Aux::I32(1)).expect("Failed to push aux");
+        record
+    }
+
+    #[test]
+    fn store_and_restore_softclip() {
+        let original   = dummy_record();
+        let mut masked = original.clone();
+        masked.set(b"read", Some(&CigarString(vec![Cigar::SoftClip(2), Cigar::Match(6)])), b"NNCGATAA", &[0, 0, 32, 33, 34, 35, 36, 37]);
+        masked.set_pos(102);
+
+        store(&original, &mut masked).expect("Failed to store original record");
+        assert!(restore(&mut masked).expect("Failed to restore record"));
+
+        assert_eq!(masked.pos(), original.pos());
+        assert_eq!(masked.cigar().take(), original.cigar().take());
+        assert_eq!(masked.seq().as_bytes(), original.seq().as_bytes());
+        assert_eq!(masked.qual(), original.qual());
+        assert_eq!(masked.bin(), clip::reg2bin(original.pos(), original.reference_end()));
+        assert_eq!(masked.aux_iter().count(), 1);
+    }
+
+    #[test]
+    fn store_untouched_record() {
+        let original   = dummy_record();
+        let mut masked = original.clone();
+        store(&original, &mut masked).expect("Failed to store original record");
+        assert_eq!(masked.aux_iter().count(), 1);
+        assert!(!restore(&mut masked).expect("Failed to restore record"));
+    }
+
+    #[test]
+    fn store_already_masked() {
+        let mut original = dummy_record();
+        original.push_aux(OFFSETS_TAG, Aux::ArrayU32(AuxArray::from(&vec![0u32]))).expect("Failed to push aux");
+        let mut masked = original.clone();
+        assert!(matches!(store(&original, &mut masked), Err(ReversibleError::AlreadyMasked)));
+    }
+
+    #[test]
+    fn restore_invalid_tags() {
+        let mut record = dummy_record();
+        record.push_aux(OFFSETS_TAG, Aux::ArrayU32(AuxArray::from(&vec![0u32, 1]))).expect("Failed to push aux");
+        assert!(matches!(restore(&mut record), Err(ReversibleError::InvalidTag{..})));
+
+        record.push_aux(BASES_TAG, Aux::String("T")).expect("Failed to push aux");
+        record.push_aux(QUALS_TAG, Aux::ArrayU8(AuxArray::from(&vec![30u8]))).expect("Failed to push aux");
+        assert!(matches!(restore(&mut record), Err(ReversibleError::LengthMismatch)));
+    }
+}
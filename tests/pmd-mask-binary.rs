@@ -23,6 +23,7 @@ fn basic_command_stdout() {
     .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
     .args(canon_arg!("--bam", "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam"))
     .args(canon_arg!("--misincorporation", "tests/test-data/bam/dummy-MTonly/misincorporation.txt"))
+    .args(["--output-fmt", "SAM"])
     .assert();
 
     println!("{cmd}");
@@ -91,4 +92,1076 @@ fn complex_command() {
     assert!(!output_is_masked(&fixture_bam));
 
     fixture_bam.close().expect("Failed to delete fixture");
-}
\ No newline at end of file
+}
+
+#[test]
+fn single_stranded_library() {
+    let fixture_bam = NamedTempFile::new("output.bam").expect("Failed to create fixture for output bam");
+
+    let cmd = Command::cargo_bin("pmd-mask").expect("Invalid")
+    .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+    .args(canon_arg!("--bam", "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam"))
+    .args(canon_arg!("--misincorporation", "tests/test-data/bam/dummy-MTonly/misincorporation.txt"))
+    .args(["--output", fixture_bam.to_str().expect("Non UTF8 character in fixture")])
+    .args(["--library", "single"])
+    .assert();
+
+    println!("{cmd}");
+
+    cmd.success()
+        .code(0)
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::is_empty());
+
+    assert!(output_is_masked(&fixture_bam));
+
+    fixture_bam.close().expect("Failed to delete fixture");
+}
+
+#[test]
+fn soft_masking() {
+    let fixture_bam = NamedTempFile::new("output.bam").expect("Failed to create fixture for output bam");
+    let input_bam   = "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam";
+
+    let cmd = Command::cargo_bin("pmd-mask").expect("Invalid")
+    .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+    .args(canon_arg!("--bam", input_bam))
+    .args(canon_arg!("--misincorporation", "tests/test-data/bam/dummy-MTonly/misincorporation.txt"))
+    .args(["--output", fixture_bam.to_str().expect("Non UTF8 character in fixture")])
+    .args(["--mask-mode", "soft", "--mask-quality", "2"])
+    .assert();
+
+    println!("{cmd}");
+
+    cmd.success()
+        .code(0)
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::is_empty());
+
+    // ---- Sequences should remain untouched, while some base qualities should be downscaled.
+    let mut input        = rust_htslib_read_back(Path::new(input_bam));
+    let mut output       = rust_htslib_read_back(&fixture_bam);
+    let mut downscaled   = false;
+    for (want, got) in input.records().zip(output.records()) {
+        let (want, got) = (want.expect("Invalid Record"), got.expect("Invalid Record"));
+        assert_eq!(want.seq().as_bytes(), got.seq().as_bytes());
+        downscaled |= got.qual().iter().zip(want.qual()).any(|(got, want)| *got == 2 && *want != 2);
+    }
+    assert!(downscaled);
+
+    fixture_bam.close().expect("Failed to delete fixture");
+}
+
+#[test]
+fn rescale_base_qualities() {
+    let fixture_bam = NamedTempFile::new("output.bam").expect("Failed to create fixture for output bam");
+    let input_bam   = "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam";
+
+    let cmd = Command::cargo_bin("pmd-mask").expect("Invalid")
+    .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+    .args(canon_arg!("--bam", input_bam))
+    .args(canon_arg!("--misincorporation", "tests/test-data/bam/dummy-MTonly/misincorporation.txt"))
+    .args(["--output", fixture_bam.to_str().expect("Non UTF8 character in fixture")])
+    .args(["--mask-mode", "rescale"])
+    .assert();
+
+    println!("{cmd}");
+
+    cmd.success()
+        .code(0)
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::is_empty());
+
+    // ---- Sequences should remain untouched, while base qualities may only be downscaled.
+    let mut input      = rust_htslib_read_back(Path::new(input_bam));
+    let mut output     = rust_htslib_read_back(&fixture_bam);
+    let mut downscaled = false;
+    for (want, got) in input.records().zip(output.records()) {
+        let (want, got) = (want.expect("Invalid Record"), got.expect("Invalid Record"));
+        assert_eq!(want.seq().as_bytes(), got.seq().as_bytes());
+        assert!(got.qual().iter().zip(want.qual()).all(|(got, want)| got <= want));
+        downscaled |= got.qual() != want.qual();
+    }
+    assert!(downscaled);
+
+    fixture_bam.close().expect("Failed to delete fixture");
+}
+
+#[test]
+fn soft_clipping() {
+    use rust_htslib::bam::record::Cigar;
+
+    let fixture_bam = NamedTempFile::new("output.bam").expect("Failed to create fixture for output bam");
+    let input_bam   = "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam";
+
+    let cmd = Command::cargo_bin("pmd-mask").expect("Invalid")
+    .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+    .args(canon_arg!("--bam", input_bam))
+    .args(canon_arg!("--misincorporation", "tests/test-data/bam/dummy-MTonly/misincorporation.txt"))
+    .args(["--output", fixture_bam.to_str().expect("Non UTF8 character in fixture")])
+    .args(["--mask-mode", "softclip", "--output-fmt", "BAM"])
+    .assert();
+
+    println!("{cmd}");
+
+    cmd.success()
+        .code(0)
+        .stderr(predicate::str::is_empty())
+        .stdout(predicate::str::is_empty());
+
+    // ---- Soft-clipped records should keep their sequence, and never start further upstream than the original.
+    let mut input   = rust_htslib_read_back(Path::new(input_bam));
+    let mut output  = rust_htslib_read_back(&fixture_bam);
+    let mut clipped = false;
+    for (want, got) in input.records().zip(output.records()) {
+        let (want, got) = (want.expect("Invalid Record"), got.expect("Invalid Record"));
+        assert_eq!(want.qname(), got.qname());
+        assert!(got.pos() >= want.pos());
+        if got.cigar() != want.cigar() {
+            assert_eq!(want.seq().as_bytes(), got.seq().as_bytes());
+            assert!(got.cigar().iter().any(|op| matches!(op, Cigar::SoftClip(_))));
+            assert!(got.cigar().end_pos() <= want.cigar().end_pos());
+            clipped = true;
+        }
+    }
+    assert!(clipped);
+
+    fixture_bam.close().expect("Failed to delete fixture");
+}
+
+#[test]
+fn fragment_aware_unpaired() {
+    let input_bam = "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam";
+    let fixtures  = ["default.bam", "fragment-aware.bam"].map(|name| NamedTempFile::new(name).expect("Failed to create fixture for output bam"));
+
+    for (fixture_bam, extra_args) in fixtures.iter().zip([vec![], vec!["--fragment-aware"]]) {
+        Command::cargo_bin("pmd-mask").expect("Invalid")
+            .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+            .args(canon_arg!("--bam", input_bam))
+            .args(canon_arg!("--misincorporation", "tests/test-data/bam/dummy-MTonly/misincorporation.txt"))
+            .args(["--output", fixture_bam.to_str().expect("Non UTF8 character in fixture"), "--output-fmt", "BAM"])
+            .args(extra_args)
+            .assert()
+            .success()
+            .code(0);
+    }
+
+    // ---- Unpaired reads span their whole fragment: fragment-aware masking should not change anything.
+    let mut default        = rust_htslib_read_back(&fixtures[0]);
+    let mut fragment_aware = rust_htslib_read_back(&fixtures[1]);
+    for (want, got) in default.records().zip(fragment_aware.records()) {
+        let (want, got) = (want.expect("Invalid Record"), got.expect("Invalid Record"));
+        if want.is_paired() { continue }
+        assert_eq!(want.qname(), got.qname());
+        assert_eq!(want.seq().as_bytes(), got.seq().as_bytes());
+        assert_eq!(want.qual(), got.qual());
+    }
+
+    for fixture_bam in fixtures {
+        fixture_bam.close().expect("Failed to delete fixture");
+    }
+}
+
+#[test]
+fn multithreaded_masking_preserves_order() {
+    let input_bam = "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam";
+    let fixtures  = ["single.bam", "multi.bam"].map(|name| NamedTempFile::new(name).expect("Failed to create fixture for output bam"));
+
+    for (fixture_bam, threads) in fixtures.iter().zip(["1", "4"]) {
+        Command::cargo_bin("pmd-mask").expect("Invalid")
+            .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+            .args(canon_arg!("--bam", input_bam))
+            .args(canon_arg!("--misincorporation", "tests/test-data/bam/dummy-MTonly/misincorporation.txt"))
+            .args(["--output", fixture_bam.to_str().expect("Non UTF8 character in fixture"), "--output-fmt", "BAM"])
+            .args(["--threads", threads])
+            .assert()
+            .success()
+            .code(0);
+    }
+
+    let mut single = rust_htslib_read_back(&fixtures[0]);
+    let mut multi  = rust_htslib_read_back(&fixtures[1]);
+    let (single, multi) = (single.records(), multi.records());
+    let mut count = 0;
+    for (want, got) in single.zip(multi) {
+        let (want, got) = (want.expect("Invalid Record"), got.expect("Invalid Record"));
+        assert_eq!(want.qname(), got.qname());
+        assert_eq!(want.seq().as_bytes(), got.seq().as_bytes());
+        assert_eq!(want.qual(), got.qual());
+        count += 1;
+    }
+    assert_eq!(count, rust_htslib_read_back(Path::new(input_bam)).records().count());
+
+    for fixture_bam in fixtures {
+        fixture_bam.close().expect("Failed to delete fixture");
+    }
+}
+
+#[test]
+fn region_restricted_masking() {
+    use assert_fs::prelude::*;
+    use std::collections::HashSet;
+
+    let input_bam   = "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam";
+    let fixture_bam = NamedTempFile::new("output.bam").expect("Failed to create fixture for output bam");
+    let regions_bed = NamedTempFile::new("regions.bed").expect("Failed to create fixture for regions file");
+    regions_bed.write_str("MT\t300\t400\nMT\t350\t500\n").expect("Failed to write regions file");
+
+    Command::cargo_bin("pmd-mask").expect("Invalid")
+        .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+        .args(canon_arg!("--bam", input_bam))
+        .args(canon_arg!("--misincorporation", "tests/test-data/bam/dummy-MTonly/misincorporation.txt"))
+        .args(["--output", fixture_bam.to_str().expect("Non UTF8 character in fixture"), "--output-fmt", "BAM"])
+        .args(["--region", "MT:1-100", "--regions-file", regions_bed.to_str().expect("Non UTF8 character in fixture")])
+        .assert()
+        .success()
+        .code(0);
+
+    // ---- Only records overlapping [0, 100) or [300, 500) should be written, exactly once.
+    use rust_htslib::bam::ext::BamRecordExtensions;
+    let overlaps = |record: &bam::Record| [(0, 100), (300, 500)].iter().any(|(start, end)| record.pos() < *end && record.reference_end() > *start);
+    let want = rust_htslib_read_back(Path::new(input_bam)).records()
+        .map(|record| record.expect("Invalid Record"))
+        .filter(overlaps)
+        .map(|record| record.qname().to_vec())
+        .collect::<Vec<_>>();
+    let got = rust_htslib_read_back(&fixture_bam).records()
+        .map(|record| record.expect("Invalid Record").qname().to_vec())
+        .collect::<Vec<_>>();
+
+    assert!(!want.is_empty());
+    assert_eq!(got.iter().collect::<HashSet<_>>().len(), got.len());
+    assert_eq!(got.iter().collect::<HashSet<_>>(), want.iter().collect::<HashSet<_>>());
+    assert!(output_is_masked(&fixture_bam));
+
+    fixture_bam.close().expect("Failed to delete fixture");
+    regions_bed.close().expect("Failed to delete fixture");
+}
+
+#[test]
+fn region_requires_index() {
+    use assert_fs::prelude::*;
+
+    // ---- Copy the input bam without its index.
+    let unindexed_bam = NamedTempFile::new("unindexed.bam").expect("Failed to create fixture for input bam");
+    unindexed_bam.write_binary(&std::fs::read("tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam").expect("Failed to read input bam"))
+        .expect("Failed to copy input bam");
+
+    Command::cargo_bin("pmd-mask").expect("Invalid")
+        .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+        .args(["--bam", unindexed_bam.to_str().expect("Non UTF8 character in fixture")])
+        .args(canon_arg!("--misincorporation", "tests/test-data/bam/dummy-MTonly/misincorporation.txt"))
+        .args(["--region", "MT:1-100"])
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("Failed to load the index"));
+
+    unindexed_bam.close().expect("Failed to delete fixture");
+}
+
+#[test]
+fn snp_panel_masking() {
+    use assert_fs::prelude::*;
+    use rust_htslib::bam::ext::BamRecordExtensions;
+
+    let input_bam   = "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam";
+    let fixtures    = ["default.bam", "panel.bam"].map(|name| NamedTempFile::new(name).expect("Failed to create fixture for output bam"));
+    let panel_bed   = NamedTempFile::new("panel.bed").expect("Failed to create fixture for panel file");
+    panel_bed.write_str("MT\t0\t200\n").expect("Failed to write panel file");
+
+    for (fixture_bam, extra_args) in fixtures.iter().zip([vec![], vec!["--panel", panel_bed.to_str().expect("Non UTF8 character in fixture")]]) {
+        Command::cargo_bin("pmd-mask").expect("Invalid")
+            .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+            .args(canon_arg!("--bam", input_bam))
+            .args(canon_arg!("--misincorporation", "tests/test-data/bam/dummy-MTonly/misincorporation.txt"))
+            .args(["--output", fixture_bam.to_str().expect("Non UTF8 character in fixture"), "--output-fmt", "BAM"])
+            .args(extra_args)
+            .assert()
+            .success()
+            .code(0);
+    }
+
+    // ---- Masked positions should be a subset of the default run, restricted to panel sites.
+    let mut default = rust_htslib_read_back(&fixtures[0]);
+    let mut panel   = rust_htslib_read_back(&fixtures[1]);
+    let (mut masked_default, mut masked_panel) = (0, 0);
+    for (want, got) in default.records().zip(panel.records()) {
+        let (want, got) = (want.expect("Invalid Record"), got.expect("Invalid Record"));
+        let (want_seq, got_seq) = (want.seq().as_bytes(), got.seq().as_bytes());
+        for [readpos, refpos] in got.aligned_pairs() {
+            let readpos = readpos as usize;
+            if got_seq[readpos] == b'N' {
+                assert_eq!(want_seq[readpos], b'N');
+                assert!(refpos < 200);
+                masked_panel += 1;
+            }
+            if want_seq[readpos] == b'N' { masked_default += 1 }
+        }
+    }
+    assert!(masked_panel > 0);
+    assert!(masked_panel < masked_default);
+
+    for fixture_bam in fixtures {
+        fixture_bam.close().expect("Failed to delete fixture");
+    }
+    panel_bed.close().expect("Failed to delete fixture");
+}
+
+#[test]
+fn snp_panel_transitions_require_alleles() {
+    use assert_fs::prelude::*;
+    let panel_bed = NamedTempFile::new("panel.bed").expect("Failed to create fixture for panel file");
+    panel_bed.write_str("MT\t0\t200\n").expect("Failed to write panel file");
+
+    Command::cargo_bin("pmd-mask").expect("Invalid")
+        .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+        .args(canon_arg!("--bam", "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam"))
+        .args(canon_arg!("--misincorporation", "tests/test-data/bam/dummy-MTonly/misincorporation.txt"))
+        .args(["--panel", panel_bed.to_str().expect("Non UTF8 character in fixture"), "--panel-transitions-only"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("BED files do not provide alleles"));
+
+    panel_bed.close().expect("Failed to delete fixture");
+}
+
+#[test]
+fn mask_mismatches_only() {
+    let input_bam   = "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam";
+    let fixture_bam = NamedTempFile::new("output.bam").expect("Failed to create fixture for output bam");
+    let metrics     = NamedTempFile::new("metrics.tsv").expect("Failed to create fixture for metrics file");
+
+    Command::cargo_bin("pmd-mask").expect("Invalid")
+        .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+        .args(canon_arg!("--bam", input_bam))
+        .args(canon_arg!("--misincorporation", "tests/test-data/bam/dummy-MTonly/misincorporation.txt"))
+        .args(["--output", fixture_bam.to_str().expect("Non UTF8 character in fixture"), "--output-fmt", "BAM"])
+        .args(["--metrics-file", metrics.to_str().expect("Non UTF8 character in fixture")])
+        .arg("--mismatches-only")
+        .assert()
+        .success()
+        .code(0);
+
+    // ---- Masked nucleotides should always be deamination products (T over a reference C, A over a reference G).
+    let mut input  = rust_htslib_read_back(Path::new(input_bam));
+    let mut output = rust_htslib_read_back(&fixture_bam);
+    let mut masked = 0;
+    for (want, got) in input.records().zip(output.records()) {
+        let (want, got) = (want.expect("Invalid Record").seq().as_bytes(), got.expect("Invalid Record").seq().as_bytes());
+        for (original, masked_base) in want.iter().zip(got.iter()) {
+            if original != masked_base {
+                assert_eq!(*masked_base, b'N');
+                assert!(matches!(original, b'T' | b'A'));
+                masked += 1;
+            }
+        }
+    }
+    assert!(masked > 0);
+
+    // ---- Metrics should report the number of masked bases of each mode.
+    let metrics_content = std::fs::read_to_string(&metrics).expect("Failed to read metrics file");
+    assert!(metrics_content.contains("# Reads processed: 1000"));
+    let mismatches_line = metrics_content.lines().find(|line| line.starts_with("# Masked bases (mismatches only)")).expect("Missing mismatches line");
+    let reported = mismatches_line.split_whitespace().filter_map(|n| n.parse::<u64>().ok()).collect::<Vec<_>>();
+    assert_eq!(reported[reported.len()-2..].iter().sum::<u64>(), masked);
+
+    fixture_bam.close().expect("Failed to delete fixture");
+    metrics.close().expect("Failed to delete fixture");
+}
+
+#[test]
+fn annotate_only_mode() {
+    let input_bam = "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam";
+    let mut fixtures = Vec::new();
+    for mode in ["hard", "annotate"] {
+        let fixture_bam = NamedTempFile::new(format!("output-{mode}.bam")).expect("Failed to create fixture for output bam");
+        Command::cargo_bin("pmd-mask").expect("Invalid")
+            .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+            .args(canon_arg!("--bam", input_bam))
+            .args(canon_arg!("--misincorporation", "tests/test-data/bam/dummy-MTonly/misincorporation.txt"))
+            .args(["--output", fixture_bam.to_str().expect("Non UTF8 character in fixture"), "--output-fmt", "BAM"])
+            .args(["--mask-mode", mode])
+            .assert()
+            .success()
+            .code(0);
+        fixtures.push(fixture_bam);
+    }
+
+    // ---- Annotated records should keep their sequence and qualities, and list the offsets masked in hard mode.
+    let mut input     = rust_htslib_read_back(Path::new(input_bam));
+    let mut hard      = rust_htslib_read_back(&fixtures[0]);
+    let mut annotated = rust_htslib_read_back(&fixtures[1]);
+    for ((original, hard), annotated) in input.records().zip(hard.records()).zip(annotated.records()) {
+        let (original, hard, annotated) = (original.expect("Invalid Record"), hard.expect("Invalid Record"), annotated.expect("Invalid Record"));
+        assert_eq!(original.seq().as_bytes(), annotated.seq().as_bytes());
+        assert_eq!(original.qual(), annotated.qual());
+
+        let want = original.seq().as_bytes().iter().zip(hard.seq().as_bytes().iter())
+            .enumerate()
+            .filter_map(|(i, (a, b))| (a != b).then_some(i as u32))
+            .collect::<Vec<_>>();
+        let got = match annotated.aux(b"ZO") {
+            Ok(bam::record::Aux::ArrayU32(offsets)) => offsets.iter().collect::<Vec<_>>(),
+            Err(_)                                  => Vec::new(),
+            Ok(other)                               => panic!("Unexpected aux type: {other:?}"),
+        };
+        assert_eq!(want, got);
+        assert_eq!(annotated.aux(b"ZC").ok(), Some(bam::record::Aux::I32(want.len() as i32)));
+    }
+
+    for fixture in fixtures {
+        fixture.close().expect("Failed to delete fixture");
+    }
+}
+
+#[test]
+fn reversible_masking_roundtrip() {
+    let input_bam = "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam";
+    for mode in ["hard", "softclip", "rescale"] {
+        let masked_bam   = NamedTempFile::new(format!("masked-{mode}.bam")).expect("Failed to create fixture for masked bam");
+        let restored_bam = NamedTempFile::new(format!("restored-{mode}.bam")).expect("Failed to create fixture for restored bam");
+
+        Command::cargo_bin("pmd-mask").expect("Invalid")
+            .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+            .args(canon_arg!("--bam", input_bam))
+            .args(canon_arg!("--misincorporation", "tests/test-data/bam/dummy-MTonly/misincorporation.txt"))
+            .args(["--output", masked_bam.to_str().expect("Non UTF8 character in fixture"), "--output-fmt", "BAM"])
+            .args(["--mask-mode", mode, "--reversible"])
+            .assert()
+            .success()
+            .code(0);
+
+        Command::cargo_bin("pmd-mask").expect("Invalid")
+            .arg("unmask")
+            .args(["--bam", masked_bam.to_str().expect("Non UTF8 character in fixture")])
+            .args(["--output", restored_bam.to_str().expect("Non UTF8 character in fixture"), "--output-fmt", "BAM"])
+            .assert()
+            .success()
+            .code(0);
+
+        // ---- Restored records should be strictly identical to the original ones.
+        let mut input    = rust_htslib_read_back(Path::new(input_bam));
+        let mut masked   = rust_htslib_read_back(&masked_bam);
+        let mut restored = rust_htslib_read_back(&restored_bam);
+        let mut altered  = 0;
+        for ((want, masked), got) in input.records().zip(masked.records()).zip(restored.records()) {
+            let (want, masked, got) = (want.expect("Invalid Record"), masked.expect("Invalid Record"), got.expect("Invalid Record"));
+            altered += (want.seq().as_bytes() != masked.seq().as_bytes() || want.qual() != masked.qual() || want.cigar() != masked.cigar()) as usize;
+            assert_eq!(want.qname(), got.qname());
+            assert_eq!((want.tid(), want.pos(), want.bin()), (got.tid(), got.pos(), got.bin()));
+            assert_eq!(want.cigar(), got.cigar());
+            assert_eq!(want.seq().as_bytes(), got.seq().as_bytes());
+            assert_eq!(want.qual(), got.qual());
+            assert_eq!(want.aux_iter().count(), got.aux_iter().count());
+        }
+        assert!(altered > 0, "{mode}: no record was altered");
+
+        masked_bam.close().expect("Failed to delete fixture");
+        restored_bam.close().expect("Failed to delete fixture");
+    }
+}
+
+#[test]
+fn output_header_provenance() {
+    let fixture_bam = NamedTempFile::new("output.bam").expect("Failed to create fixture for output bam");
+    Command::cargo_bin("pmd-mask").expect("Invalid")
+        .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+        .args(canon_arg!("--bam", "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam"))
+        .args(canon_arg!("--misincorporation", "tests/test-data/bam/dummy-MTonly/misincorporation.txt"))
+        .args(["--output", fixture_bam.to_str().expect("Non UTF8 character in fixture"), "--output-fmt", "BAM"])
+        .args(["--threshold", "0.05"])
+        .assert()
+        .success()
+        .code(0);
+
+    let output = rust_htslib_read_back(&fixture_bam);
+    let header = bam::Header::from_template(output.header());
+
+    // ---- @PG record should be chained to the last program of the input.
+    let programs = header.to_hashmap().remove("PG").expect("Missing @PG records");
+    let program  = programs.last().expect("Missing @PG records");
+    assert_eq!(program["ID"], "pmd-mask");
+    assert_eq!(program["PN"], "pmd-mask");
+    assert_eq!(program["PP"], "samtools");
+    assert_eq!(program["VN"], env!("CARGO_PKG_VERSION"));
+    assert!(program["CL"].contains("--threshold 0.05"));
+
+    // ---- @CO records should carry the same content as the metrics file.
+    let comments = header.comments().collect::<Vec<_>>();
+    assert_eq!(comments.first().map(|c| c.as_ref()), Some("pmd-mask: Chr\tStd\t5p\t3p"));
+    assert!(comments.iter().any(|comment| comment.starts_with("pmd-mask: MT\t+\t")));
+
+    fixture_bam.close().expect("Failed to delete fixture");
+}
+
+#[test]
+fn output_format_defaults() {
+    let input_bam = "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam";
+    let run = |output: &NamedTempFile| {
+        Command::cargo_bin("pmd-mask").expect("Invalid")
+            .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+            .args(canon_arg!("--bam", input_bam))
+            .args(canon_arg!("--misincorporation", "tests/test-data/bam/dummy-MTonly/misincorporation.txt"))
+            .args(["--output", output.to_str().expect("Non UTF8 character in fixture")])
+            .assert()
+            .success()
+            .code(0);
+        std::fs::read(output).expect("Failed to read output back")
+    };
+
+    // ---- No extension: use the input's format (BGZF-compressed BAM)
+    let no_extension = NamedTempFile::new("output").expect("Failed to create fixture");
+    assert_eq!(&run(&no_extension)[..2], &[0x1f, 0x8b]);
+
+    // ---- Output extension takes precedence over the input's format.
+    let sam = NamedTempFile::new("output.sam").expect("Failed to create fixture");
+    assert!(run(&sam).starts_with(b"@HD"));
+
+    // ---- Standard output: use the input's format as well.
+    let stdout = Command::cargo_bin("pmd-mask").expect("Invalid")
+        .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+        .args(canon_arg!("--bam", input_bam))
+        .args(canon_arg!("--misincorporation", "tests/test-data/bam/dummy-MTonly/misincorporation.txt"))
+        .assert()
+        .success()
+        .code(0)
+        .get_output()
+        .stdout
+        .clone();
+    assert_eq!(&stdout[..2], &[0x1f, 0x8b]);
+
+    for fixture in [no_extension, sam] {
+        fixture.close().expect("Failed to delete fixture");
+    }
+}
+
+#[test]
+fn read_group_thresholds() {
+    // ---- Create a misincorporation file devoid of any C>T and G>A misincorporation (e.g. a UDG-treated library).
+    let misincorporation = std::fs::read_to_string("tests/test-data/bam/dummy-MTonly/misincorporation.txt").expect("Failed to read misincorporation file");
+    let udg_treated = misincorporation.lines().map(|line| match line.starts_with('#') || line.starts_with("Chr") {
+        true  => line.to_string(),
+        false => line.split('\t').enumerate().map(|(i, field)| if i == 9 || i == 10 { "0" } else { field }).collect::<Vec<_>>().join("\t"),
+    }).collect::<Vec<_>>().join("\n");
+    let udg_file = NamedTempFile::new("udg-misincorporation.txt").expect("Failed to create fixture for misincorporation file");
+    std::fs::write(&udg_file, udg_treated).expect("Failed to write misincorporation file");
+
+    // ---- Records of the dummy bam all belong to read group '1' (LB: Illumina)
+    for (key, want_masking) in [("Illumina", false), ("1", false), ("unknown", true)] {
+        let fixture_bam = NamedTempFile::new("output.bam").expect("Failed to create fixture for output bam");
+        Command::cargo_bin("pmd-mask").expect("Invalid")
+            .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+            .args(canon_arg!("--bam", "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam"))
+            .args(canon_arg!("--misincorporation", "tests/test-data/bam/dummy-MTonly/misincorporation.txt"))
+            .args(["--output", fixture_bam.to_str().expect("Non UTF8 character in fixture"), "--output-fmt", "BAM"])
+            .args(["--rg-misincorporation", &format!("{key}={}", udg_file.to_str().expect("Non UTF8 character in fixture"))])
+            .assert()
+            .success()
+            .code(0);
+
+        let mut output = rust_htslib_read_back(&fixture_bam);
+        let masked = output.records().map(|record| record.expect("Invalid Record").seq().as_bytes().iter().filter(|base| **base == b'N').count()).sum::<usize>();
+        assert_eq!(masked > 0, want_masking, "{key}");
+        fixture_bam.close().expect("Failed to delete fixture");
+    }
+    udg_file.close().expect("Failed to delete fixture");
+}
+
+#[test]
+fn length_stratified_thresholds() {
+    // ---- Create a misincorporation file devoid of any C>T and G>A misincorporation.
+    let misincorporation = std::fs::read_to_string("tests/test-data/bam/dummy-MTonly/misincorporation.txt").expect("Failed to read misincorporation file");
+    let undamaged = misincorporation.lines().map(|line| match line.starts_with('#') || line.starts_with("Chr") {
+        true  => line.to_string(),
+        false => line.split('\t').enumerate().map(|(i, field)| if i == 9 || i == 10 { "0" } else { field }).collect::<Vec<_>>().join("\t"),
+    }).collect::<Vec<_>>().join("\n");
+    let undamaged_file = NamedTempFile::new("undamaged-misincorporation.txt").expect("Failed to create fixture for misincorporation file");
+    std::fs::write(&undamaged_file, undamaged).expect("Failed to write misincorporation file");
+
+    // ---- Long reads use the undamaged thresholds, and should thus never be masked.
+    const MIN_LENGTH: usize = 50;
+    let fixture_bam = NamedTempFile::new("output.bam").expect("Failed to create fixture for output bam");
+    Command::cargo_bin("pmd-mask").expect("Invalid")
+        .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+        .args(canon_arg!("--bam", "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam"))
+        .args(canon_arg!("--misincorporation", "tests/test-data/bam/dummy-MTonly/misincorporation.txt"))
+        .args(["--output", fixture_bam.to_str().expect("Non UTF8 character in fixture"), "--output-fmt", "BAM"])
+        .args(["--length-misincorporation", &format!("{MIN_LENGTH}-={}", undamaged_file.to_str().expect("Non UTF8 character in fixture"))])
+        .assert()
+        .success()
+        .code(0);
+
+    let mut output = rust_htslib_read_back(&fixture_bam);
+    let (mut short_masked, mut long_masked) = (0, 0);
+    for record in output.records() {
+        let record = record.expect("Invalid Record");
+        let masked = record.seq().as_bytes().iter().filter(|base| **base == b'N').count();
+        match record.seq_len() >= MIN_LENGTH {
+            true  => long_masked  += masked,
+            false => short_masked += masked,
+        }
+    }
+    assert!(short_masked > 0);
+    assert_eq!(long_masked, 0);
+
+    fixture_bam.close().expect("Failed to delete fixture");
+    undamaged_file.close().expect("Failed to delete fixture");
+}
+
+#[test]
+fn profile_and_estimate_damage() {
+    // ---- Profile the dummy bam into a mapDamage-compatible misincorporation file.
+    let profile = NamedTempFile::new("misincorporation.txt").expect("Failed to create fixture for misincorporation file");
+    Command::cargo_bin("pmd-mask").expect("Invalid")
+        .args(["profile"])
+        .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+        .args(canon_arg!("--bam", "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam"))
+        .args(["--output", profile.to_str().expect("Non UTF8 character in fixture")])
+        .assert()
+        .success()
+        .code(0)
+        .stdout(predicate::str::is_empty());
+
+    let contents = std::fs::read_to_string(&profile).expect("Failed to read misincorporation file");
+    assert!(contents.starts_with("# table produced by pmd-mask"));
+    let records = contents.lines().filter(|line| line.starts_with("MT\t")).collect::<Vec<_>>();
+    assert_eq!(records.len(), 2 * 2 * 70);
+    assert!(records.iter().all(|line| line.split('\t').count() == 30));
+
+    // ---- Masking with the profile, or with an in-memory estimate should yield the exact same output.
+    let mut outputs = Vec::new();
+    for source in [vec!["--misincorporation", profile.to_str().expect("Non UTF8 character in fixture")], vec!["--estimate-damage"]] {
+        let fixture_bam = NamedTempFile::new("output.bam").expect("Failed to create fixture for output bam");
+        Command::cargo_bin("pmd-mask").expect("Invalid")
+            .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+            .args(canon_arg!("--bam", "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam"))
+            .args(["--output", fixture_bam.to_str().expect("Non UTF8 character in fixture"), "--output-fmt", "BAM"])
+            .args(source)
+            .assert()
+            .success()
+            .code(0);
+        assert!(output_is_masked(&fixture_bam));
+        outputs.push(rust_htslib_read_back(&fixture_bam).records().map(|record| record.expect("Invalid Record").seq().as_bytes()).collect::<Vec<_>>());
+        fixture_bam.close().expect("Failed to delete fixture");
+    }
+    assert_eq!(outputs[0], outputs[1]);
+
+    // ---- Estimating damage requires a seekable input file.
+    Command::cargo_bin("pmd-mask").expect("Invalid")
+        .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+        .args(["--estimate-damage"])
+        .assert()
+        .failure();
+
+    profile.close().expect("Failed to delete fixture");
+}
+
+#[test]
+fn threshold_strategies() {
+    // ---- Create a misincorporation file with a single, noisy dip at the second position of every 5p profile.
+    let misincorporation = std::fs::read_to_string("tests/test-data/bam/dummy-MTonly/misincorporation.txt").expect("Failed to read misincorporation file");
+    let noisy = misincorporation.lines().map(|line| match line.split('\t').collect::<Vec<_>>() {
+        fields if fields.len() > 10 && fields[1] == "5p" && fields[3] == "2" => fields.iter().enumerate().map(|(i, field)| if i == 10 { "0" } else { field }).collect::<Vec<_>>().join("\t"),
+        _ => line.to_string(),
+    }).collect::<Vec<_>>().join("\n");
+    let noisy_file = NamedTempFile::new("noisy-misincorporation.txt").expect("Failed to create fixture for misincorporation file");
+    std::fs::write(&noisy_file, noisy).expect("Failed to write misincorporation file");
+
+    let mut five_prime_thresholds = Vec::new();
+    for strategy in ["first-below", "last-above", "moving-average:3"] {
+        let metrics     = NamedTempFile::new("metrics.tsv").expect("Failed to create fixture for metrics file");
+        let fixture_bam = NamedTempFile::new("output.bam").expect("Failed to create fixture for output bam");
+        Command::cargo_bin("pmd-mask").expect("Invalid")
+            .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+            .args(canon_arg!("--bam", "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam"))
+            .args(["--misincorporation", noisy_file.to_str().expect("Non UTF8 character in fixture")])
+            .args(["--output", fixture_bam.to_str().expect("Non UTF8 character in fixture"), "--output-fmt", "BAM"])
+            .args(["--metrics-file", metrics.to_str().expect("Non UTF8 character in fixture")])
+            .args(["--threshold-strategy", strategy])
+            .assert()
+            .success()
+            .code(0);
+
+        // ---- The strategy should be recorded within the metrics file.
+        let metrics_content = std::fs::read_to_string(&metrics).expect("Failed to read metrics file");
+        assert!(metrics_content.contains(&format!("# Threshold strategy: {strategy}")));
+        let mt_forward = metrics_content.lines().find(|line| line.starts_with("MT\t+\t")).expect("Missing MT entry");
+        five_prime_thresholds.push(mt_forward.split('\t').nth(2).expect("Missing 5p threshold").to_string());
+
+        metrics.close().expect("Failed to delete fixture");
+        fixture_bam.close().expect("Failed to delete fixture");
+    }
+
+    // ---- Only first-below should stop at the noisy dip.
+    assert_eq!(five_prime_thresholds[0], "2");
+    assert!(five_prime_thresholds[1..].iter().all(|position| position != "2"), "{five_prime_thresholds:?}");
+    noisy_file.close().expect("Failed to delete fixture");
+}
+
+#[test]
+fn binomial_confidence_thresholds() {
+    // ---- Simulate a low-depth misincorporation file, by downscaling every count.
+    let misincorporation = std::fs::read_to_string("tests/test-data/bam/dummy-MTonly/misincorporation.txt").expect("Failed to read misincorporation file");
+    let low_depth = misincorporation.lines().map(|line| match line.starts_with('#') || line.starts_with("Chr") {
+        true  => line.to_string(),
+        false => line.split('\t').enumerate().map(|(i, field)| match i {
+            0..=3 => field.to_string(),
+            _     => (field.parse::<usize>().expect("Invalid count") / 20).to_string(),
+        }).collect::<Vec<_>>().join("\t"),
+    }).collect::<Vec<_>>().join("\n");
+    let low_depth_file = NamedTempFile::new("low-depth-misincorporation.txt").expect("Failed to create fixture for misincorporation file");
+    std::fs::write(&low_depth_file, low_depth).expect("Failed to write misincorporation file");
+
+    // ---- Retrieve the thresholds of every entry ("NA" meaning masking applies along the full read).
+    let thresholds = |interval: Option<&str>| {
+        let metrics = NamedTempFile::new("metrics.tsv").expect("Failed to create fixture for metrics file");
+        let fixture_bam = NamedTempFile::new("output.bam").expect("Failed to create fixture for output bam");
+        let mut cmd = Command::cargo_bin("pmd-mask").expect("Invalid");
+        cmd.args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+            .args(canon_arg!("--bam", "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam"))
+            .args(["--misincorporation", low_depth_file.to_str().expect("Non UTF8 character in fixture")])
+            .args(["--output", fixture_bam.to_str().expect("Non UTF8 character in fixture"), "--output-fmt", "BAM"])
+            .args(["--metrics-file", metrics.to_str().expect("Non UTF8 character in fixture")]);
+        if let Some(interval) = interval {
+            cmd.args(["--confidence-interval", interval, "--confidence-level", "0.99"]);
+        }
+        cmd.assert().success().code(0);
+
+        let metrics_content = std::fs::read_to_string(&metrics).expect("Failed to read metrics file");
+        if let Some(interval) = interval {
+            assert!(metrics_content.contains(&format!("# Confidence interval: {interval} (0.99)")));
+        }
+        let thresholds = metrics_content.lines()
+            .filter(|line| !line.starts_with('#') && !line.starts_with("Chr"))
+            .map(|line| line.split('\t').collect::<Vec<_>>())
+            .map(|fields| ((fields[0].to_string(), fields[1].to_string()), fields[2..].iter().map(|field| field.parse::<usize>().unwrap_or(usize::MAX)).collect::<Vec<_>>()))
+            .collect::<std::collections::HashMap<_, _>>();
+        metrics.close().expect("Failed to delete fixture");
+        fixture_bam.close().expect("Failed to delete fixture");
+        thresholds
+    };
+
+    // ---- Upper bounds are always greater than raw frequencies: thresholds may only be pushed further within reads.
+    //      Entries which never meet the threshold are missing from the metrics file.
+    let raw = thresholds(None);
+    for interval in ["wilson", "clopper-pearson"] {
+        let bounded = thresholds(Some(interval));
+        assert!(bounded.len() < raw.len());
+        assert!(bounded.iter().all(|(entry, bounded)| raw.get(entry).is_some_and(|raw| raw.iter().zip(bounded.iter()).all(|(raw, bounded)| bounded >= raw))));
+    }
+    low_depth_file.close().expect("Failed to delete fixture");
+}
+
+#[test]
+fn min_count_pooled_fallback() {
+    // ---- Retrieve the thresholds of every entry ("NA" meaning masking applies along the full read).
+    let thresholds = |pooling: &[&str]| {
+        let metrics = NamedTempFile::new("metrics.tsv").expect("Failed to create fixture for metrics file");
+        let fixture_bam = NamedTempFile::new("output.bam").expect("Failed to create fixture for output bam");
+        let output = Command::cargo_bin("pmd-mask").expect("Invalid")
+            .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+            .args(canon_arg!("--bam", "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam"))
+            .args(canon_arg!("--misincorporation", "tests/test-data/bam/dummy-MTonly/misincorporation.txt"))
+            .args(["--output", fixture_bam.to_str().expect("Non UTF8 character in fixture"), "--output-fmt", "BAM"])
+            .args(["--metrics-file", metrics.to_str().expect("Non UTF8 character in fixture")])
+            .args(["-v"])
+            .args(pooling)
+            .assert().success().code(0);
+        let stderr = String::from_utf8_lossy(&output.get_output().stderr).to_string();
+
+        let thresholds = std::fs::read_to_string(&metrics).expect("Failed to read metrics file").lines()
+            .filter(|line| !line.starts_with('#') && !line.starts_with("Chr"))
+            .map(|line| line.split('\t').map(str::to_string).collect::<Vec<_>>())
+            .map(|fields| ((fields[0].clone(), fields[1].clone()), fields[2..].to_vec()))
+            .collect::<std::collections::HashMap<_, _>>();
+        metrics.close().expect("Failed to delete fixture");
+        fixture_bam.close().expect("Failed to delete fixture");
+        (thresholds, stderr)
+    };
+
+    // ---- Without any requirement, every chromosome keeps its own profile.
+    let (raw, stderr) = thresholds(&[]);
+    assert!(!stderr.contains("Falling back"));
+    assert!(raw.values().collect::<std::collections::HashSet<_>>().len() > 2);
+
+    // ---- An unreachable requirement forces every entry onto the genome-wide profile of its strand...
+    let (pooled, stderr) = thresholds(&["--min-count", "1000000000"]);
+    assert!(stderr.contains("MT +: found only") && stderr.contains("Falling back to a profile pooled across all chromosomes."));
+    for strand in ["+", "-"] {
+        let strand_thresholds = pooled.iter().filter(|((_, std), _)| std == strand).map(|(_, thresholds)| thresholds).collect::<std::collections::HashSet<_>>();
+        assert_eq!(strand_thresholds.len(), 1);
+    }
+
+    // ---- ...or onto a single genome-wide profile, when pooling strands.
+    let (pooled, stderr) = thresholds(&["--min-count", "1000000000", "--pool-strands"]);
+    assert!(stderr.contains("Falling back to a profile pooled across all chromosomes and strands."));
+    assert_eq!(pooled.values().collect::<std::collections::HashSet<_>>().len(), 1);
+}
+
+#[test]
+fn missing_contig_policies() {
+    // ---- Simulate a misincorporation file lacking the MT contig, and a merged one (mapDamage's --merge-reference-sequences).
+    let misincorporation = std::fs::read_to_string("tests/test-data/bam/dummy-MTonly/misincorporation.txt").expect("Failed to read misincorporation file");
+    let without_mt = misincorporation.lines().filter(|line| !line.starts_with("MT\t")).collect::<Vec<_>>().join("\n");
+    let merged = misincorporation.lines()
+        .filter(|line| line.starts_with('#') || line.starts_with("Chr") || line.starts_with("MT\t"))
+        .map(|line| line.replacen("MT\t", "*\t", 1))
+        .collect::<Vec<_>>().join("\n");
+
+    let misincorporation_file = |name: &str, content: &str| {
+        let file = NamedTempFile::new(name).expect("Failed to create fixture for misincorporation file");
+        std::fs::write(&file, content).expect("Failed to write misincorporation file");
+        file
+    };
+    let (without_mt, merged) = (misincorporation_file("without-MT.txt", &without_mt), misincorporation_file("merged.txt", &merged));
+
+    // ---- Run pmd-mask and retrieve the output sequences.
+    let run = |misincorporation: &Path, policy: &str| {
+        let fixture_bam = NamedTempFile::new("output.bam").expect("Failed to create fixture for output bam");
+        let assert = Command::cargo_bin("pmd-mask").expect("Invalid")
+            .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+            .args(canon_arg!("--bam", "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam"))
+            .args(["--misincorporation", misincorporation.to_str().expect("Non UTF8 character in fixture")])
+            .args(["--output", fixture_bam.to_str().expect("Non UTF8 character in fixture"), "--output-fmt", "BAM"])
+            .args(["--missing-contigs", policy])
+            .assert();
+        let sequences = match assert.get_output().status.success() {
+            true  => Some(rust_htslib_read_back(&fixture_bam).records().map(|record| record.expect("Invalid record").seq().as_bytes()).collect::<Vec<_>>()),
+            false => None,
+        };
+        fixture_bam.close().expect("Failed to delete fixture");
+        (assert, sequences)
+    };
+
+    let input = rust_htslib_read_back(Path::new("tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam")).records()
+        .map(|record| record.expect("Invalid record").seq().as_bytes())
+        .collect::<Vec<_>>();
+    let (_, expected) = run(Path::new("tests/test-data/bam/dummy-MTonly/misincorporation.txt"), "fail");
+    let expected = expected.expect("pmd-mask should not fail when MT is present");
+
+    // ---- Missing contigs are either fully masked, left untouched, masked using genome-wide thresholds, or rejected.
+    let (_, masked) = run(&without_mt, "mask");
+    let masked = masked.expect("Missing output");
+    assert!(masked.iter().zip(expected.iter()).all(|(masked, expected)| masked.iter().filter(|base| **base == b'N').count() >= expected.iter().filter(|base| **base == b'N').count()));
+    assert_ne!(masked, expected);
+
+    let (_, skipped) = run(&without_mt, "skip");
+    assert_eq!(skipped.expect("Missing output"), input);
+
+    let (_, pooled) = run(&without_mt, "pooled");
+    let pooled = pooled.expect("Missing output");
+    assert_ne!(pooled, input);
+    assert_ne!(pooled, masked);
+
+    let (assert, failed) = run(&without_mt, "fail");
+    assert!(failed.is_none());
+    assert.failure().stderr(predicate::str::contains("MT").and(predicate::str::contains("is absent from the misincorporation profile")));
+
+    // ---- The entry of a merged run applies to every contig, regardless of the policy.
+    let (_, from_merged) = run(&merged, "fail");
+    assert_eq!(from_merged.expect("Merged entries should apply to MT"), expected);
+
+    without_mt.close().expect("Failed to delete fixture");
+    merged.close().expect("Failed to delete fixture");
+}
+
+#[test]
+fn fixed_length_masking() {
+    let fixture_bam = NamedTempFile::new("output.bam").expect("Failed to create fixture for output bam");
+    let metrics = NamedTempFile::new("metrics.tsv").expect("Failed to create fixture for metrics file");
+    Command::cargo_bin("pmd-mask").expect("Invalid")
+        .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+        .args(canon_arg!("--bam", "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam"))
+        .args(["--output", fixture_bam.to_str().expect("Non UTF8 character in fixture"), "--output-fmt", "BAM"])
+        .args(["--metrics-file", metrics.to_str().expect("Non UTF8 character in fixture")])
+        .args(["--mask-5p", "5", "--mask-3p", "3"])
+        .assert()
+        .success()
+        .code(0)
+        .stderr(predicate::str::is_empty());
+
+    // ---- Masking only applies within the first 5 and last 3 positions of every read.
+    assert!(output_is_masked(&fixture_bam));
+    let (mut input, mut output) = (rust_htslib_read_back(Path::new("tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam")), rust_htslib_read_back(&fixture_bam));
+    for (input, output) in input.records().zip(output.records()) {
+        let (input, output) = (input.expect("Invalid record").seq().as_bytes(), output.expect("Invalid record").seq().as_bytes());
+        let masked = input.iter().zip(output.iter()).enumerate().filter(|(_, (before, after))| before != after).map(|(i, _)| i);
+        assert!(masked.into_iter().all(|i| i < 5 || i >= input.len() - 3));
+    }
+
+    // ---- Every contig is assigned the same thresholds.
+    let metrics_content = std::fs::read_to_string(&metrics).expect("Failed to read metrics file");
+    assert!(metrics_content.starts_with("# Fixed-length masking: 5 (5p) | 3 (3p)"));
+    assert!(metrics_content.lines().filter(|line| !line.starts_with('#') && !line.starts_with("Chr")).all(|line| line.ends_with("\t6\t4")));
+
+    // ---- Fixed-length masking requires both ends, and cannot be combined with a misincorporation file.
+    Command::cargo_bin("pmd-mask").expect("Invalid")
+        .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+        .args(canon_arg!("--bam", "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam"))
+        .args(["--mask-5p", "5"])
+        .assert()
+        .failure();
+    Command::cargo_bin("pmd-mask").expect("Invalid")
+        .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+        .args(canon_arg!("--bam", "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam"))
+        .args(canon_arg!("--misincorporation", "tests/test-data/bam/dummy-MTonly/misincorporation.txt"))
+        .args(["--mask-5p", "5", "--mask-3p", "3"])
+        .assert()
+        .failure();
+
+    // ---- Base qualities cannot be rescaled without a misincorporation profile.
+    Command::cargo_bin("pmd-mask").expect("Invalid")
+        .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+        .args(canon_arg!("--bam", "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam"))
+        .args(["--output", fixture_bam.to_str().expect("Non UTF8 character in fixture"), "--output-fmt", "BAM"])
+        .args(["--mask-5p", "5", "--mask-3p", "3", "--mask-mode", "rescale"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("'--mask-mode rescale' requires a misincorporation profile"));
+
+    fixture_bam.close().expect("Failed to delete fixture");
+    metrics.close().expect("Failed to delete fixture");
+}
+
+#[test]
+fn json_metrics_report() {
+    let fixture_bam = NamedTempFile::new("output.bam").expect("Failed to create fixture for output bam");
+    let report = NamedTempFile::new("pmd-mask_mqc.json").expect("Failed to create fixture for json report");
+    Command::cargo_bin("pmd-mask").expect("Invalid")
+        .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+        .args(canon_arg!("--bam", "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam"))
+        .args(canon_arg!("--misincorporation", "tests/test-data/bam/dummy-MTonly/misincorporation.txt"))
+        .args(["--output", fixture_bam.to_str().expect("Non UTF8 character in fixture"), "--output-fmt", "BAM"])
+        .args(["--json-metrics", report.to_str().expect("Non UTF8 character in fixture")])
+        .assert()
+        .success()
+        .code(0)
+        .stderr(predicate::str::is_empty());
+
+    // ---- The report is formatted as a MultiQC custom content table.
+    let content = std::fs::read_to_string(&report).expect("Failed to read json report");
+    let json: serde_json::Value = serde_json::from_str(&content).expect("Invalid JSON report");
+    assert_eq!(json["id"], "pmd_mask");
+    assert_eq!(json["plot_type"], "table");
+
+    // ---- Every read is located on MT, and both strands carry a threshold, frequency and counts.
+    assert_eq!(json["summary"]["reads"], 1000);
+    assert_eq!(json["summary"]["chromosomes"]["MT"]["reads"], 1000);
+    assert!(json["summary"]["masked_reads"].as_u64().expect("Missing masked reads") > 0);
+    for strand in ["+", "-"] {
+        let entry = &json["data"][format!("MT {strand}")];
+        assert_eq!(entry["strand"], strand);
+        assert!(entry["fallback"].is_null());
+        for end in ["5p", "3p"] {
+            assert!(entry[format!("threshold_{end}")].is_u64());
+            assert!(entry[format!("frequency_{end}")].as_f64().expect("Missing frequency") <= 0.01);
+            assert!(entry[format!("observations_{end}")].as_u64().expect("Missing observations") > 0);
+        }
+    }
+    let masked_reads = ["MT +", "MT -"].iter().map(|key| json["data"][key]["masked_reads"].as_u64().expect("Missing masked reads")).sum::<u64>();
+    assert_eq!(json["summary"]["masked_reads"].as_u64(), Some(masked_reads));
+
+    // ---- Hard-masking alters every candidate.
+    for end in ["5p", "3p"] {
+        assert!(json["summary"][format!("candidates_{end}")].as_u64().expect("Missing candidates") > 0);
+        assert_eq!(json["summary"][format!("masked_bases_{end}")], json["summary"][format!("candidates_{end}")]);
+    }
+
+    // ---- Annotated records are left untouched: no base is reported as masked.
+    Command::cargo_bin("pmd-mask").expect("Invalid")
+        .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+        .args(canon_arg!("--bam", "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam"))
+        .args(canon_arg!("--misincorporation", "tests/test-data/bam/dummy-MTonly/misincorporation.txt"))
+        .args(["--output", fixture_bam.to_str().expect("Non UTF8 character in fixture"), "--output-fmt", "BAM"])
+        .args(["--json-metrics", report.to_str().expect("Non UTF8 character in fixture")])
+        .args(["--mask-mode", "annotate"])
+        .assert()
+        .success()
+        .code(0);
+
+    let content = std::fs::read_to_string(&report).expect("Failed to read json report");
+    let json: serde_json::Value = serde_json::from_str(&content).expect("Invalid JSON report");
+    for end in ["5p", "3p"] {
+        assert!(json["summary"][format!("candidates_{end}")].as_u64().expect("Missing candidates") > 0);
+        assert_eq!(json["summary"][format!("masked_bases_{end}")], 0);
+    }
+
+    fixture_bam.close().expect("Failed to delete fixture");
+    report.close().expect("Failed to delete fixture");
+}
+
+#[test]
+fn thresholds_from_metrics_file() {
+    let (first_bam, second_bam) = (NamedTempFile::new("first.bam").expect("Failed to create fixture"), NamedTempFile::new("second.bam").expect("Failed to create fixture"));
+    let (first_metrics, second_metrics) = (NamedTempFile::new("first.tsv").expect("Failed to create fixture"), NamedTempFile::new("second.tsv").expect("Failed to create fixture"));
+    Command::cargo_bin("pmd-mask").expect("Invalid")
+        .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+        .args(canon_arg!("--bam", "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam"))
+        .args(canon_arg!("--misincorporation", "tests/test-data/bam/dummy-MTonly/misincorporation.txt"))
+        .args(["--output", first_bam.to_str().expect("Non UTF8 character in fixture"), "--output-fmt", "BAM"])
+        .args(["--metrics-file", first_metrics.to_str().expect("Non UTF8 character in fixture")])
+        .args(["--confidence-interval", "wilson"])
+        .assert()
+        .success()
+        .code(0)
+        .stderr(predicate::str::is_empty());
+
+    // ---- Reusing the metrics file (comments and masking statistics included) yields the same output.
+    Command::cargo_bin("pmd-mask").expect("Invalid")
+        .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+        .args(canon_arg!("--bam", "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam"))
+        .args(["--thresholds-file", first_metrics.to_str().expect("Non UTF8 character in fixture")])
+        .args(["--output", second_bam.to_str().expect("Non UTF8 character in fixture"), "--output-fmt", "BAM"])
+        .args(["--metrics-file", second_metrics.to_str().expect("Non UTF8 character in fixture")])
+        .assert()
+        .success()
+        .code(0)
+        .stderr(predicate::str::is_empty());
+
+    assert!(output_is_masked(&second_bam));
+    let (mut first, mut second) = (rust_htslib_read_back(&first_bam), rust_htslib_read_back(&second_bam));
+    for (first, second) in first.records().zip(second.records()) {
+        let (first, second) = (first.expect("Invalid record"), second.expect("Invalid record"));
+        assert_eq!((first.seq().as_bytes(), first.qual()), (second.seq().as_bytes(), second.qual()));
+    }
+
+    let table = |path: &NamedTempFile| std::fs::read_to_string(path).expect("Failed to read metrics file").lines().filter(|line| !line.starts_with('#')).map(str::to_string).collect::<Vec<_>>();
+    assert_eq!(table(&first_metrics), table(&second_metrics));
+    assert!(std::fs::read_to_string(&second_metrics).expect("Failed to read metrics file").starts_with("# Thresholds file:"));
+
+    // ---- Thresholds files cannot be combined with a misincorporation file.
+    Command::cargo_bin("pmd-mask").expect("Invalid")
+        .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+        .args(canon_arg!("--bam", "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam"))
+        .args(canon_arg!("--misincorporation", "tests/test-data/bam/dummy-MTonly/misincorporation.txt"))
+        .args(["--thresholds-file", first_metrics.to_str().expect("Non UTF8 character in fixture")])
+        .assert()
+        .failure();
+
+    // ---- Thresholds files hold no genome-wide profile to fall back on, for missing contigs.
+    Command::cargo_bin("pmd-mask").expect("Invalid")
+        .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+        .args(canon_arg!("--bam", "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam"))
+        .args(["--thresholds-file", first_metrics.to_str().expect("Non UTF8 character in fixture")])
+        .args(["--missing-contigs", "pooled"])
+        .args(["--output", second_bam.to_str().expect("Non UTF8 character in fixture"), "--output-fmt", "BAM"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("'pooled' missing contig policy requires a misincorporation profile"));
+
+    // ---- Base qualities cannot be rescaled without a misincorporation profile.
+    Command::cargo_bin("pmd-mask").expect("Invalid")
+        .args(canon_arg!("--reference", "tests/test-data/reference/hs37d5-MTonly/hs37d5-MTonly.fa.gz"))
+        .args(canon_arg!("--bam", "tests/test-data/bam/dummy-MTonly/dummy-MTonly-1000.bam"))
+        .args(["--thresholds-file", first_metrics.to_str().expect("Non UTF8 character in fixture")])
+        .args(["--mask-mode", "rescale"])
+        .args(["--output", second_bam.to_str().expect("Non UTF8 character in fixture"), "--output-fmt", "BAM"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("'--mask-mode rescale' requires a misincorporation profile"));
+
+    for fixture in [first_bam, second_bam, first_metrics, second_metrics] {
+        fixture.close().expect("Failed to delete fixture");
+    }
+}